@@ -1,9 +1,11 @@
 use bevy::core_pipeline::tonemapping::Tonemapping;
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
-use bevy::window::CursorGrabMode;
-use bevy_garage_car::Player;
-use bevy_rapier3d::prelude::PhysicsSet;
+use bevy::render::camera::Viewport;
+use bevy::utils::HashMap;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy_garage_car::{Car, LocalPlayer, Player};
+use bevy_rapier3d::prelude::{ContactForceEvent, PhysicsSet, QueryFilter, RapierContext, Velocity};
 
 pub fn grab_mouse(
     mut windows: Query<&mut Window>,
@@ -29,7 +31,16 @@ impl Plugin for CarCameraPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(CameraConfig::default())
             .add_systems(PostStartup, camera_start_system)
-            .add_systems(Update, (grab_mouse, camera_switch_system))
+            .add_systems(
+                Update,
+                (
+                    grab_mouse,
+                    camera_switch_system,
+                    split_screen_system,
+                    // networked clients run no physics and have no contact events
+                    camera_impact_system.run_if(resource_exists::<Events<ContactForceEvent>>),
+                ),
+            )
             .add_systems(
                 PostUpdate,
                 camera_controller_system.after(PhysicsSet::StepSimulation),
@@ -37,10 +48,20 @@ impl Plugin for CarCameraPlugin {
     }
 }
 
-pub fn camera_start_system(mut cmd: Commands) {
+pub fn camera_start_system(mut cmd: Commands, config: Res<CameraConfig>) {
+    for player in 0..config.players.max(1) {
+        spawn_camera(&mut cmd, player);
+    }
+}
+
+pub fn spawn_camera(cmd: &mut Commands, player: usize) -> Entity {
     let sky_blue: Color = Srgba::hex("87CEEB").unwrap().into();
     cmd.spawn((
         Camera3dBundle {
+            camera: Camera {
+                order: player as isize,
+                ..default()
+            },
             #[cfg(not(any(target_arch = "wasm32", target_os = "ios", target_os = "android")))]
             projection: Projection::from(PerspectiveProjection {
                 far: 5000.,
@@ -68,12 +89,20 @@ pub fn camera_start_system(mut cmd: Commands) {
                 Color::srgb(0.8, 0.844, 1.0),
             ),
         },
-        CameraController::default(),
-    ));
+        CameraController {
+            player,
+            ..default()
+        },
+    ))
+    .id()
 }
 
 #[derive(Component)]
 pub struct CameraController {
+    /// Local player index this camera belongs to.
+    pub player: usize,
+    /// Car followed in `CameraMode::Follow`, assigned by `split_screen_system`.
+    pub target: Option<Entity>,
     pub enabled: bool,
     pub sensitivity: f32,
     pub key_forward: KeyCode,
@@ -89,11 +118,21 @@ pub struct CameraController {
     pub pitch: f32,
     pub yaw: f32,
     pub velocity: Vec3,
+    /// Impact shake left, `0..1`, raised by `camera_impact_system` and decayed by `CameraShake`.
+    pub trauma: f32,
+    /// Smoothed follow pose before shake, `None` outside follow mode.
+    pub smoothed: Option<Transform>,
+    /// Mode the camera was last placed for, a change starts a transition.
+    pub mode: Option<CameraMode>,
+    /// Seconds left of the transition into `mode`.
+    pub transition: f32,
 }
 
 impl Default for CameraController {
     fn default() -> Self {
         Self {
+            player: 0,
+            target: None,
             enabled: true,
             sensitivity: 0.5,
             key_forward: KeyCode::KeyW,
@@ -109,11 +148,15 @@ impl Default for CameraController {
             pitch: 0.0,
             yaw: 0.0,
             velocity: Vec3::ZERO,
+            trauma: 0.0,
+            smoothed: None,
+            mode: None,
+            transition: 0.0,
         }
     }
 }
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub enum CameraFollowView {
     Windshield,
     FrontWheel,
@@ -141,16 +184,130 @@ fn follow_props_by_mode(mode: &CameraFollowView) -> (Vec3, Vec3) {
     };
     (look_from, look_at)
 }
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum CameraMode {
     Follow(CameraFollowView, Vec3, Vec3),
+    /// Circles the followed car.
+    Orbit,
+    /// High above the followed car, north up whatever its heading.
+    TopDown,
     Free,
 }
 
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SplitScreen {
+    /// Views stacked top to bottom.
+    Horizontal,
+    /// Views side by side.
+    Vertical,
+}
+
+/// How loosely a follow view trails the car, the default is rigid.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraFollowConfig {
+    /// Seconds for the camera position to close most of the gap to the car, 0 snaps.
+    pub position_lag: f32,
+    /// Same for the camera rotation.
+    pub rotation_lag: f32,
+    /// Seconds of the car velocity added to the look-at point, faster cars show
+    /// more of the track ahead.
+    pub look_ahead: f32,
+}
+
+impl CameraFollowConfig {
+    /// Share of the remaining gap closed over `dt` seconds with a `lag` time constant.
+    fn blend(lag: f32, dt: f32) -> f32 {
+        if lag <= 0. {
+            return 1.;
+        }
+        1. - (-dt / lag).exp()
+    }
+    pub fn smooth(&self, prev: &Transform, target: &Transform, dt: f32) -> Transform {
+        Transform {
+            translation: prev
+                .translation
+                .lerp(target.translation, Self::blend(self.position_lag, dt)),
+            rotation: prev
+                .rotation
+                .slerp(target.rotation, Self::blend(self.rotation_lag, dt)),
+            scale: target.scale,
+        }
+    }
+}
+
+/// Follow camera shake, a little with speed and a lot on impacts.
+#[derive(Debug, Clone)]
+pub struct CameraShake {
+    pub enabled: bool,
+    /// Meters of shake at `full_speed`.
+    pub speed_magnitude: f32,
+    /// Speed in m/s at which the speed shake peaks.
+    pub full_speed: f32,
+    /// Meters of shake at full trauma.
+    pub impact_magnitude: f32,
+    /// Contact force in newtons that adds full trauma.
+    pub impact_force: f32,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// Radians of roll per meter of shake.
+    pub roll: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            speed_magnitude: 0.02,
+            full_speed: 50.,
+            impact_magnitude: 0.25,
+            impact_force: 2e5,
+            decay: 1.5,
+            roll: 0.2,
+        }
+    }
+}
+
+impl CameraShake {
+    /// Perturbs a follow camera transform at `time` seconds and decays `trauma` by `dt`.
+    pub fn apply(&self, tf: &mut Transform, trauma: &mut f32, speed: f32, time: f32, dt: f32) {
+        *trauma = (*trauma - self.decay * dt).max(0.);
+        let speed = (speed / self.full_speed.max(0.1)).clamp(0., 1.);
+        // squared so low speeds and light touches barely move the view
+        let amount =
+            self.speed_magnitude * speed * speed + self.impact_magnitude * *trauma * *trauma;
+        if amount <= 0. {
+            return;
+        }
+        // sum of unrelated sines, smooth but not visibly periodic
+        let noise = |frequency: f32, phase: f32| {
+            0.6 * (time * frequency + phase).sin() + 0.4 * (time * frequency * 2.3 + phase).sin()
+        };
+        let offset = Vec3::new(noise(23., 0.), noise(19., 1.7), 0.) * amount;
+        tf.translation += tf.rotation.mul_vec3(offset);
+        tf.rotate_local_z(noise(17., 3.1) * amount * self.roll);
+    }
+}
+
 #[derive(Resource)]
 pub struct CameraConfig {
     pub mode: CameraMode,
     pub prev: Transform,
+    /// Number of local players, one camera and viewport each.
+    pub players: usize,
+    pub split: SplitScreen,
+    /// Pull the follow camera in front of walls between it and the car.
+    pub collision: bool,
+    /// Closest the camera gets to the look-at point when pulled in.
+    pub collision_min_distance: f32,
+    pub shake: CameraShake,
+    /// Smoothing and look-ahead per follow view, views without an entry follow rigidly.
+    pub follow: HashMap<CameraFollowView, CameraFollowConfig>,
+    /// Seconds the camera takes to move into a newly selected mode.
+    pub transition: f32,
+    /// Meters above the car in `CameraMode::TopDown`.
+    pub top_down_height: f32,
+    /// Car the first player's camera follows instead of its own, e.g. a watched opponent.
+    pub follow_target: Option<Entity>,
 }
 
 impl CameraConfig {
@@ -159,8 +316,20 @@ impl CameraConfig {
         Self {
             mode: CameraMode::Follow(view, from, at),
             prev: Transform::IDENTITY,
+            players: 1,
+            split: SplitScreen::Horizontal,
+            collision: true,
+            collision_min_distance: 1.,
+            shake: CameraShake::default(),
+            follow: HashMap::default(),
+            transition: 0.5,
+            top_down_height: 40.,
+            follow_target: None,
         }
     }
+    pub fn follow_config(&self, view: CameraFollowView) -> CameraFollowConfig {
+        self.follow.get(&view).copied().unwrap_or_default()
+    }
     pub fn next_view(&mut self) {
         let mode = match self.mode {
             CameraMode::Follow(ref view, _, _) => {
@@ -176,10 +345,23 @@ impl CameraConfig {
                 let (from, at) = follow_props_by_mode(&next_view);
                 CameraMode::Follow(next_view, from, at)
             }
-            CameraMode::Free => CameraMode::Free,
+            mode => mode,
         };
         self.mode = mode;
     }
+    /// Cycles chase, cockpit, orbit and top-down.
+    pub fn next_mode(&mut self) {
+        match self.mode {
+            CameraMode::Follow(
+                CameraFollowView::Near | CameraFollowView::Mid | CameraFollowView::Far,
+                _,
+                _,
+            ) => self.driver(),
+            CameraMode::Follow(..) => self.orbit(),
+            CameraMode::Orbit => self.top_down(),
+            CameraMode::TopDown | CameraMode::Free => self.near(),
+        }
+    }
     pub fn free(&mut self) {
         self.mode = CameraMode::Free;
     }
@@ -202,6 +384,12 @@ impl CameraConfig {
     pub fn wheel(&mut self) {
         self.follow_view(CameraFollowView::FrontWheel);
     }
+    pub fn orbit(&mut self) {
+        self.mode = CameraMode::Orbit;
+    }
+    pub fn top_down(&mut self) {
+        self.mode = CameraMode::TopDown;
+    }
 }
 
 impl Default for CameraConfig {
@@ -228,115 +416,314 @@ pub fn camera_switch_system(mut config: ResMut<CameraConfig>, input: Res<ButtonI
     if input.just_pressed(KeyCode::Digit0) {
         config.free();
     }
+    if input.just_pressed(KeyCode::KeyV) {
+        config.next_mode();
+    }
+}
+
+fn split_viewport(
+    window_size: UVec2,
+    split: SplitScreen,
+    count: usize,
+    index: usize,
+) -> Option<Viewport> {
+    if count <= 1 || window_size.x == 0 || window_size.y == 0 {
+        return None;
+    }
+    let (count, index) = (count as u32, index.min(count - 1) as u32);
+    let (physical_position, physical_size) = match split {
+        SplitScreen::Horizontal => {
+            let height = window_size.y / count;
+            (
+                UVec2::new(0, height * index),
+                UVec2::new(window_size.x, height),
+            )
+        }
+        SplitScreen::Vertical => {
+            let width = window_size.x / count;
+            (
+                UVec2::new(width * index, 0),
+                UVec2::new(width, window_size.y),
+            )
+        }
+    };
+    Some(Viewport {
+        physical_position,
+        physical_size,
+        ..default()
+    })
+}
+
+pub fn split_screen_system(
+    config: Res<CameraConfig>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    players: Query<(Entity, Option<&LocalPlayer>), With<Player>>,
+    cars: Query<(), With<Car>>,
+    mut cameras: Query<(&mut Camera, &mut CameraController)>,
+) {
+    let window_size = windows
+        .get_single()
+        .map(|window| window.physical_size())
+        .unwrap_or(UVec2::ZERO);
+    let count = config.players.max(1);
+    for (mut camera, mut controller) in cameras.iter_mut() {
+        let player = controller.player;
+        controller.target = config
+            .follow_target
+            .filter(|car| player == 0 && cars.contains(*car))
+            .or_else(|| {
+                players
+                    .iter()
+                    .find(|(_, local)| local.map_or(0, |local| local.index) == player)
+                    .map(|(e, _)| e)
+            });
+        let viewport = split_viewport(window_size, config.split, count, player);
+        let rect =
+            |v: &Option<Viewport>| v.as_ref().map(|v| (v.physical_position, v.physical_size));
+        if rect(&camera.viewport) != rect(&viewport) {
+            camera.viewport = viewport;
+        }
+    }
+}
+
+pub fn camera_impact_system(
+    config: Res<CameraConfig>,
+    mut contact_events: EventReader<ContactForceEvent>,
+    mut cameras: Query<&mut CameraController>,
+) {
+    for event in contact_events.read() {
+        if !config.shake.enabled {
+            continue;
+        }
+        let trauma = event.total_force_magnitude / config.shake.impact_force.max(1.);
+        for mut controller in cameras.iter_mut() {
+            let Some(target) = controller.target else {
+                continue;
+            };
+            if target == event.collider1 || target == event.collider2 {
+                controller.trauma = (controller.trauma + trauma).min(1.);
+            }
+        }
+    }
+}
+
+// keeps the camera slightly in front of the hit surface
+const COLLISION_MARGIN: f32 = 0.2;
+
+const ORBIT_DISTANCE: f32 = 8.;
+const ORBIT_HEIGHT: f32 = 3.;
+/// Radians per second.
+const ORBIT_SPEED: f32 = 0.4;
+
+fn lerp_transform(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: from.translation.lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t),
+        scale: to.scale,
+    }
 }
 
 pub fn camera_controller_system(
     time: Res<Time>,
     config: Res<CameraConfig>,
+    rapier_context: Option<Res<RapierContext>>,
     mut mouse_events: EventReader<MouseMotion>,
     key_input: Res<ButtonInput<KeyCode>>,
     mut pset: ParamSet<(
-        Query<(&mut Transform, &mut CameraController), With<Camera>>,
-        Query<&Transform, With<Player>>,
+        Query<(Entity, &mut Transform, &mut CameraController), With<Camera>>,
+        Query<&Transform, With<Car>>,
         Query<&mut Transform, With<DirectionalLight>>,
     )>,
+    velocities: Query<&Velocity>,
     windows: Query<&Window>,
 ) {
-    let follow_option: Option<Transform> = match config.mode {
-        CameraMode::Free => None,
-        CameraMode::Follow(_, from, at) => {
-            if let Ok(car_tf) = pset.p1().get_single() {
-                let mut tf = car_tf.clone();
-                tf.translation += tf.rotation.mul_vec3(from);
-                // tf.rotate_local_y(std::f32::consts::PI);
-                tf.look_at(car_tf.translation + tf.rotation.mul_vec3(at), *tf.local_y());
-                // tf.look_at(car_tf.translation + tf.rotation.mul_vec3(at), Vec3::Y);
-                Some(tf)
-            } else {
-                None
+    let cameras: Vec<(Entity, Option<Entity>)> = pset
+        .p0()
+        .iter()
+        .map(|(e, _, options)| (e, options.target))
+        .collect();
+    for (camera_entity, target) in cameras {
+        let follow_option: Option<(Transform, CameraFollowConfig)> = match config.mode {
+            CameraMode::Free => None,
+            CameraMode::Orbit => {
+                let car_tf = target.and_then(|target| pset.p1().get(target).ok().copied());
+                car_tf.map(|car_tf| {
+                    let yaw = time.elapsed_seconds() * ORBIT_SPEED;
+                    let offset = Quat::from_rotation_y(yaw).mul_vec3(Vec3::new(
+                        0.,
+                        ORBIT_HEIGHT,
+                        -ORBIT_DISTANCE,
+                    ));
+                    let tf = Transform::from_translation(car_tf.translation + offset)
+                        .looking_at(car_tf.translation, Vec3::Y);
+                    (tf, CameraFollowConfig::default())
+                })
             }
-        }
-    };
-    let tf: Transform = if let Some(tf) = follow_option {
+            CameraMode::TopDown => {
+                let car_tf = target.and_then(|target| pset.p1().get(target).ok().copied());
+                car_tf.map(|car_tf| {
+                    let tf = Transform::from_translation(
+                        car_tf.translation + Vec3::Y * config.top_down_height,
+                    )
+                    .looking_at(car_tf.translation, Vec3::Z);
+                    (tf, CameraFollowConfig::default())
+                })
+            }
+            CameraMode::Follow(view, from, at) => {
+                let car_tf = target.and_then(|target| pset.p1().get(target).ok().copied());
+                if let Some(car_tf) = car_tf {
+                    let follow = config.follow_config(view);
+                    let mut tf = car_tf.clone();
+                    tf.translation += tf.rotation.mul_vec3(from);
+                    // tf.rotate_local_y(std::f32::consts::PI);
+                    let look_at = car_tf.translation + tf.rotation.mul_vec3(at);
+                    let ahead = target
+                        .and_then(|target| velocities.get(target).ok())
+                        .map_or(Vec3::ZERO, |velocity| velocity.linvel * follow.look_ahead);
+                    tf.look_at(look_at + ahead, *tf.local_y());
+                    // tf.look_at(car_tf.translation + tf.rotation.mul_vec3(at), Vec3::Y);
+                    // networked clients render without a physics world to query
+                    if let (true, Some(rapier_context)) = (config.collision, &rapier_context) {
+                        let to_camera = tf.translation - look_at;
+                        let distance = to_camera.length();
+                        let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+                        if distance > config.collision_min_distance {
+                            let dir = to_camera / distance;
+                            if let Some((_, toi)) =
+                                rapier_context.cast_ray(look_at, dir, distance, true, filter)
+                            {
+                                let toi =
+                                    (toi - COLLISION_MARGIN).max(config.collision_min_distance);
+                                tf.translation = look_at + dir * toi;
+                            }
+                        }
+                    }
+                    Some((tf, follow))
+                } else {
+                    None
+                }
+            }
+        };
         let mut p0 = pset.p0();
-        let (_, mut options) = p0.single_mut();
-        let (yaw, pitch, _roll) = tf.rotation.to_euler(EulerRot::YXZ);
-        options.pitch = pitch;
-        options.yaw = yaw;
-        tf
-    } else {
-        let window = windows.single();
-        if window.cursor.grab_mode == CursorGrabMode::None {
-            return;
-        }
-        let dt = time.delta_seconds();
-
-        let mut mouse_delta = Vec2::ZERO;
-        for mouse_event in mouse_events.read() {
-            mouse_delta += mouse_event.delta;
+        let Ok((_, mut camera_tf, mut options)) = p0.get_mut(camera_entity) else {
+            continue;
+        };
+        if options.mode != Some(config.mode) {
+            // blend from where the camera is, a first placement snaps
+            if options.mode.is_some() {
+                options.smoothed = Some(*camera_tf);
+                options.transition = config.transition;
+            }
+            options.mode = Some(config.mode);
         }
+        let tf: Transform = if let Some((tf, follow)) = follow_option {
+            let dt = time.delta_seconds();
+            let mut tf = match options.smoothed {
+                Some(prev) if options.transition > 0. => {
+                    // the share of the gap left for this frame, arrives as the transition ends
+                    let t = (dt / options.transition).min(1.);
+                    options.transition -= dt;
+                    lerp_transform(&prev, &tf, t)
+                }
+                Some(prev) => follow.smooth(&prev, &tf, dt),
+                None => tf,
+            };
+            // shake goes on top, it must not feed back into the smoothing
+            options.smoothed = Some(tf);
+            let (yaw, pitch, _roll) = tf.rotation.to_euler(EulerRot::YXZ);
+            options.pitch = pitch;
+            options.yaw = yaw;
+            if config.shake.enabled {
+                let speed = options
+                    .target
+                    .and_then(|target| velocities.get(target).ok())
+                    .map_or(0., |velocity| velocity.linvel.length());
+                let mut trauma = options.trauma;
+                config.shake.apply(
+                    &mut tf,
+                    &mut trauma,
+                    speed,
+                    time.elapsed_seconds(),
+                    time.delta_seconds(),
+                );
+                options.trauma = trauma;
+            }
+            tf
+        } else {
+            options.smoothed = None;
+            options.transition = 0.;
+            // free flight has a single mouse and keyboard, leave it to the first player
+            if options.player != 0 {
+                continue;
+            }
+            let window = windows.single();
+            if window.cursor.grab_mode == CursorGrabMode::None {
+                continue;
+            }
+            let dt = time.delta_seconds();
 
-        let mut p0 = pset.p0();
-        let (tf, mut options) = p0.single_mut();
+            let mut mouse_delta = Vec2::ZERO;
+            for mouse_event in mouse_events.read() {
+                mouse_delta += mouse_event.delta;
+            }
 
-        let mut axis_input = Vec3::ZERO;
-        if key_input.pressed(options.key_forward) {
-            axis_input.z += 1.0;
-        }
-        if key_input.pressed(options.key_back) {
-            axis_input.z -= 1.0;
-        }
-        if key_input.pressed(options.key_right) {
-            axis_input.x += 1.0;
-        }
-        if key_input.pressed(options.key_left) {
-            axis_input.x -= 1.0;
-        }
-        if key_input.pressed(options.key_up) {
-            axis_input.y += 1.0;
-        }
-        if key_input.pressed(options.key_down) {
-            axis_input.y -= 1.0;
-        }
+            let mut axis_input = Vec3::ZERO;
+            if key_input.pressed(options.key_forward) {
+                axis_input.z += 1.0;
+            }
+            if key_input.pressed(options.key_back) {
+                axis_input.z -= 1.0;
+            }
+            if key_input.pressed(options.key_right) {
+                axis_input.x += 1.0;
+            }
+            if key_input.pressed(options.key_left) {
+                axis_input.x -= 1.0;
+            }
+            if key_input.pressed(options.key_up) {
+                axis_input.y += 1.0;
+            }
+            if key_input.pressed(options.key_down) {
+                axis_input.y -= 1.0;
+            }
 
-        if axis_input != Vec3::ZERO {
-            let max_speed = if key_input.pressed(options.key_run) {
-                options.run_speed
+            if axis_input != Vec3::ZERO {
+                let max_speed = if key_input.pressed(options.key_run) {
+                    options.run_speed
+                } else {
+                    options.walk_speed
+                };
+                options.velocity = axis_input.normalize() * max_speed;
             } else {
-                options.walk_speed
-            };
-            options.velocity = axis_input.normalize() * max_speed;
-        } else {
-            let friction = options.friction.clamp(0.0, 1.0);
-            options.velocity *= 1.0 - friction;
-            if options.velocity.length_squared() < 1e-6 {
-                options.velocity = Vec3::ZERO;
+                let friction = options.friction.clamp(0.0, 1.0);
+                options.velocity *= 1.0 - friction;
+                if options.velocity.length_squared() < 1e-6 {
+                    options.velocity = Vec3::ZERO;
+                }
             }
-        }
 
-        let mut tf = tf.clone();
-        let forward = *tf.forward();
-        let right = *tf.right();
-        tf.translation += options.velocity.x * dt * right
-            + options.velocity.y * dt * Vec3::Y
-            + options.velocity.z * dt * forward;
-
-        if mouse_delta != Vec2::ZERO {
-            let (pitch, yaw) = (
-                (options.pitch - mouse_delta.y * 0.5 * options.sensitivity * dt).clamp(
-                    -0.99 * std::f32::consts::FRAC_PI_2,
-                    0.99 * std::f32::consts::FRAC_PI_2,
-                ),
-                options.yaw - mouse_delta.x * options.sensitivity * dt,
-            );
-            tf.rotation = Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch);
-            options.pitch = pitch;
-            options.yaw = yaw;
-        }
-        tf
-    };
-    let mut p0 = pset.p0();
-    let (mut camera_tf, _) = p0.single_mut();
-    camera_tf.translation = tf.translation;
-    camera_tf.rotation = tf.rotation;
+            let mut tf = camera_tf.clone();
+            let forward = *tf.forward();
+            let right = *tf.right();
+            tf.translation += options.velocity.x * dt * right
+                + options.velocity.y * dt * Vec3::Y
+                + options.velocity.z * dt * forward;
+
+            if mouse_delta != Vec2::ZERO {
+                let (pitch, yaw) = (
+                    (options.pitch - mouse_delta.y * 0.5 * options.sensitivity * dt).clamp(
+                        -0.99 * std::f32::consts::FRAC_PI_2,
+                        0.99 * std::f32::consts::FRAC_PI_2,
+                    ),
+                    options.yaw - mouse_delta.x * options.sensitivity * dt,
+                );
+                tf.rotation = Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch);
+                options.pitch = pitch;
+                options.yaw = yaw;
+            }
+            tf
+        };
+        camera_tf.translation = tf.translation;
+        camera_tf.rotation = tf.rotation;
+    }
 }
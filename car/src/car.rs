@@ -1,15 +1,22 @@
-use crate::{joint::build_joint, spawn_wheel, CarSpec, WheelSpec};
+use crate::{joint::build_joint, spawn_wheel, CarSpec, Settling, WheelSpec};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
 #[derive(Component)]
 pub struct Player;
 
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalPlayer {
+    pub index: usize,
+}
+
 #[derive(Component, Debug)]
 pub struct Car {
     pub gas: f32,
     pub brake: f32,
     pub steering: f32,
+    /// Locks the rear wheels.
+    pub handbrake: bool,
     pub spawn_transform: Transform,
     pub prev_steering: f32,
     pub prev_torque: f32,
@@ -21,6 +28,7 @@ impl Default for Car {
             gas: 0.,
             brake: 0.,
             steering: 0.,
+            handbrake: false,
             prev_steering: 0.,
             prev_torque: 0.,
             prev_dir: 0.,
@@ -28,6 +36,8 @@ impl Default for Car {
         }
     }
 }
+/// Controls for the next esp step. Prefer the setters over the raw fields,
+/// they clamp to the valid ranges and turn NaN into released controls.
 impl Car {
     pub fn new(spawn_transform: Transform) -> Self {
         Self {
@@ -35,15 +45,96 @@ impl Car {
             ..default()
         }
     }
+    /// `gas` and `brake` in 0..=1, `steering` in -1..=1 with left negative.
+    pub fn set_controls(&mut self, gas: f32, brake: f32, steering: f32) {
+        self.set_gas(gas);
+        self.set_brake(brake);
+        self.set_steering(steering);
+    }
+    pub fn set_gas(&mut self, gas: f32) {
+        self.gas = clamp_control(gas, 0.);
+    }
+    pub fn set_brake(&mut self, brake: f32) {
+        self.brake = clamp_control(brake, 0.);
+    }
+    pub fn set_steering(&mut self, steering: f32) {
+        self.steering = clamp_control(steering, -1.);
+    }
+    pub fn set_handbrake(&mut self, handbrake: bool) {
+        self.handbrake = handbrake;
+    }
+    /// Takes every control of `controller` at once.
+    pub fn apply(&mut self, controller: CarController) {
+        self.gas = controller.gas;
+        self.brake = controller.brake;
+        self.steering = controller.steering;
+        self.handbrake = controller.handbrake;
+    }
+    pub fn controller(&self) -> CarController {
+        CarController::new(self.gas, self.brake, self.steering, self.handbrake)
+    }
+    pub fn release_controls(&mut self) {
+        self.apply(CarController::default());
+    }
 }
+
+fn clamp_control(value: f32, min: f32) -> f32 {
+    match value.is_nan() {
+        true => 0.,
+        false => value.clamp(min, 1.),
+    }
+}
+
+/// A full set of car controls, valid by construction, for code driving a car
+/// without touching `Car` fields. Hand it to `Car::apply`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CarController {
+    gas: f32,
+    brake: f32,
+    steering: f32,
+    handbrake: bool,
+}
+
+impl CarController {
+    /// `throttle` and `brake` clamped to 0..=1, `steer` to -1..=1 with left
+    /// negative, NaN releases the control.
+    pub fn new(throttle: f32, brake: f32, steer: f32, handbrake: bool) -> Self {
+        Self {
+            gas: clamp_control(throttle, 0.),
+            brake: clamp_control(brake, 0.),
+            steering: clamp_control(steer, -1.),
+            handbrake,
+        }
+    }
+    pub fn set_controls(&mut self, throttle: f32, brake: f32, steer: f32, handbrake: bool) {
+        *self = Self::new(throttle, brake, steer, handbrake);
+    }
+    pub fn gas(&self) -> f32 {
+        self.gas
+    }
+    pub fn brake(&self) -> f32 {
+        self.brake
+    }
+    pub fn steering(&self) -> f32 {
+        self.steering
+    }
+    pub fn handbrake(&self) -> bool {
+        self.handbrake
+    }
+}
+/// Wheel entities in `CarSpec::wheel_mount` order.
 #[derive(Component, Debug)]
 pub struct CarWheels {
-    pub entities: [Entity; 4],
+    pub entities: Vec<Entity>,
 }
 impl CarWheels {
-    pub fn new(entities: [Entity; 4]) -> Self {
+    pub fn new(entities: Vec<Entity>) -> Self {
         Self { entities }
     }
+    /// Wheels of a four wheeled car as an array, for `Query::get_many` in per-frame loops.
+    pub fn four(&self) -> Option<[Entity; 4]> {
+        self.entities.as_slice().try_into().ok()
+    }
     pub fn despawn(&mut self, cmd: &mut Commands) {
         for e in self.entities.iter() {
             cmd.entity(*e).despawn_recursive();
@@ -52,6 +143,8 @@ impl CarWheels {
 }
 
 pub const STATIC_GROUP: Group = Group::GROUP_1;
+/// Asphalt and kerbs, also in `STATIC_GROUP`, a car touching them is driving, not crashing.
+pub const ROAD_GROUP: Group = Group::GROUP_2;
 pub const CAR_TRAINING_GROUP: Group = Group::GROUP_10;
 
 #[cfg(feature = "graphics")]
@@ -69,8 +162,27 @@ pub fn spawn_car(
     player: bool,
     transform: Transform,
 ) -> Entity {
-    let spec = CarSpec::default();
+    spawn_vehicle(
+        cmd,
+        #[cfg(feature = "graphics")]
+        car_scene,
+        #[cfg(feature = "graphics")]
+        wheel_scene,
+        player,
+        transform,
+        CarSpec::default(),
+    )
+}
 
+/// `spawn_car` with any wheel layout, see `CarSpec::three_wheeler` and `CarSpec::six_wheeler`.
+pub fn spawn_vehicle(
+    cmd: &mut Commands,
+    #[cfg(feature = "graphics")] car_scene: &Handle<Scene>,
+    #[cfg(feature = "graphics")] wheel_scene: &Handle<Scene>,
+    player: bool,
+    transform: Transform,
+    spec: CarSpec,
+) -> Entity {
     let wheel_spec = WheelSpec::new(spec.wheel_radius, spec.wheel_width);
     let mounts = spec.wheel_mount.clone();
     let car_id = spawn_car_body(
@@ -80,20 +192,25 @@ pub fn spawn_car(
         Car::new(transform),
         spec,
     );
-    let wheels = CarWheels::new(mounts.map(|mount| {
-        let joint = ImpulseJoint::new(car_id, build_joint(mount.anchor, mount.left));
-        let wheel_id = spawn_wheel(
-            cmd,
-            #[cfg(feature = "graphics")]
-            wheel_scene,
-            &wheel_spec,
-            &mount,
-            transform,
-            joint,
-        );
-        wheel_id
-    }));
-    cmd.entity(car_id).insert(wheels);
+    let wheels = CarWheels::new(
+        mounts
+            .into_iter()
+            .map(|mount| {
+                let joint = ImpulseJoint::new(car_id, build_joint(mount.anchor, mount.left));
+                let wheel_id = spawn_wheel(
+                    cmd,
+                    #[cfg(feature = "graphics")]
+                    wheel_scene,
+                    &wheel_spec,
+                    &mount,
+                    transform,
+                    joint,
+                );
+                wheel_id
+            })
+            .collect(),
+    );
+    cmd.entity(car_id).insert((wheels, Settling::default()));
     if player {
         cmd.entity(car_id).insert(Player);
     }
@@ -142,7 +259,7 @@ pub fn spawn_car_body(
             Friction::coefficient(0.5),
             Restitution::coefficient(0.),
             CollisionGroups::new(CAR_TRAINING_GROUP, STATIC_GROUP),
-            ActiveEvents::COLLISION_EVENTS,
+            ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS,
             ContactForceEventThreshold(0.1),
         ),
         (
@@ -158,3 +275,44 @@ pub fn spawn_car_body(
     ))
     .id()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller_clamps_out_of_range_inputs() {
+        let controller = CarController::new(1.7, -0.4, -3., true);
+        assert_eq!(controller.gas(), 1.);
+        assert_eq!(controller.brake(), 0.);
+        assert_eq!(controller.steering(), -1.);
+        assert!(controller.handbrake());
+
+        let controller = CarController::new(-2., 5., 2.5, false);
+        assert_eq!(controller.gas(), 0.);
+        assert_eq!(controller.brake(), 1.);
+        assert_eq!(controller.steering(), 1.);
+    }
+
+    #[test]
+    fn controller_releases_nan_inputs() {
+        let controller = CarController::new(f32::NAN, f32::NAN, f32::NAN, false);
+        assert_eq!(controller, CarController::default());
+    }
+
+    #[test]
+    fn car_takes_the_clamped_controls() {
+        let mut car = Car::default();
+        car.apply(CarController::new(0.5, 2., -0.25, true));
+        assert_eq!(
+            (car.gas, car.brake, car.steering, car.handbrake),
+            (0.5, 1., -0.25, true)
+        );
+
+        car.set_controls(f32::INFINITY, f32::NEG_INFINITY, 9.);
+        assert_eq!((car.gas, car.brake, car.steering), (1., 0., 1.));
+
+        car.release_controls();
+        assert_eq!(car.controller(), CarController::default());
+    }
+}
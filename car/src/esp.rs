@@ -4,15 +4,67 @@ use std::f32::consts::PI;
 
 use crate::{Car, CarSpec, CarWheels, Wheel};
 
-pub fn aero_system(mut car_query: Query<(&Velocity, &Transform, &mut ExternalForce), With<Car>>) {
+/// Speed squared forces on the car body, at its center of mass.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct AeroConfig {
+    /// Air density in kg/m³.
+    pub air_density: f32,
+    /// Drag coefficient times frontal area in m², drag is `½·ρ·CdA·v²` against the velocity.
+    pub drag_area: f32,
+    /// Newtons of downforce per (m/s)², pushing the car into the track along its down axis.
+    pub downforce: f32,
+}
+
+impl Default for AeroConfig {
+    fn default() -> Self {
+        Self {
+            air_density: 1.2,
+            drag_area: 0.3,
+            downforce: 2.,
+        }
+    }
+}
+
+impl AeroConfig {
+    /// More grip in fast corners for a lower top speed.
+    pub fn high_downforce() -> Self {
+        Self {
+            drag_area: 0.45,
+            downforce: 5.,
+            ..default()
+        }
+    }
+
+    /// Defaults with `APP_AERO_DRAG_AREA` and `APP_AERO_DOWNFORCE` when set.
+    pub fn from_env() -> Self {
+        fn var(name: &str) -> Option<f32> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+        let default = Self::default();
+        Self {
+            drag_area: var("APP_AERO_DRAG_AREA").unwrap_or(default.drag_area),
+            downforce: var("APP_AERO_DOWNFORCE").unwrap_or(default.downforce),
+            ..default
+        }
+    }
+
+    /// Drag plus downforce in world space for a body moving at `linvel` with `down` as its down axis.
+    pub fn force(&self, linvel: Vec3, down: Vec3) -> Vec3 {
+        let speed = linvel.length();
+        let drag = -linvel * speed * 0.5 * self.air_density * self.drag_area;
+        let downforce = down * speed * speed * self.downforce;
+        drag + downforce
+    }
+}
+
+/// `ExternalForce::force` acts at the center of mass, the aero forces add no torque.
+pub fn aero_system(
+    config: Option<Res<AeroConfig>>,
+    mut car_query: Query<(&Velocity, &Transform, &mut ExternalForce), With<Car>>,
+) {
+    let config = config.map(|c| *c).unwrap_or_default();
     for (velocity, transform, mut force) in car_query.iter_mut() {
-        let car_vector = transform.rotation.mul_vec3(Vec3::Z);
-        let car_vector_norm = car_vector.normalize();
-        let car_mps = velocity.linvel.length();
-        let f_drag = 1. / 2. * 1.2 * car_mps.powi(2) * 0.2 * 1.5;
-        let f_down = car_mps.powi(2) * 2.;
-        // println!("drag:{f_drag:.1} down:{f_down:.1}");
-        force.force = -Vec3::Y * f_down - car_vector_norm * f_drag;
+        force.force = config.force(velocity.linvel, *transform.down());
     }
 }
 
@@ -24,6 +76,9 @@ const WHEEL_RAY_SHIFT: Vec3 = Vec3 {
     z: 0.,
 };
 
+/// Rear wheel braking torque with the handbrake pulled, in `CarSpec::wheel_max_torque`s.
+const HANDBRAKE_TORQUE: f32 = 2.;
+
 pub fn esp_system(
     time: Res<Time>,
     mut car_query: Query<(&mut Car, &CarSpec, &CarWheels, &Velocity, &Transform)>,
@@ -56,11 +111,6 @@ pub fn esp_system(
                 x => 0.7 + 0.3 * (1. - x),
             },
         };
-        let steering_speed_x: f32 = match linvel / spec.max_steering_speed {
-            x if x >= 1. => 0.,
-            x => 1. - x,
-        }
-        .powi(2);
         let pedal = if moving_forward {
             if braking {
                 -car.brake
@@ -89,7 +139,7 @@ pub fn esp_system(
 
         torque = dir * torque;
 
-        let angle: f32 = spec.wheel_max_angle * steering * (0.1 + 0.9 * steering_speed_x);
+        let angle: f32 = spec.steering_lock(linvel) * steering;
         let quat = -Quat::from_axis_angle(Vec3::Y, -angle);
         let torque_vec = Vec3::new(0., torque, 0.);
         let steering_torque_vec = quat.mul_vec3(torque_vec);
@@ -134,6 +184,13 @@ pub fn esp_system(
                     total_torque
                 };
                 f.torque = (transform.rotation.mul_vec3(wheel_torque)).into();
+                if car.handbrake {
+                    // stop the spin around the axle instead of driving it
+                    let axle = transform.rotation.mul_vec3(Vec3::Y);
+                    let max_torque = spec.wheel_max_torque * HANDBRAKE_TORQUE;
+                    let spin = v.angvel.dot(axle);
+                    f.torque = -axle * (spin * max_torque).clamp(-max_torque, max_torque);
+                }
 
                 #[cfg(feature = "graphics")]
                 if car_res.show_rays {
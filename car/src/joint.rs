@@ -1,10 +1,36 @@
-use bevy::prelude::{Quat, Vec3};
+use crate::{CarSpec, CarWheels, RIDE_HEIGHT};
+use bevy::prelude::{DetectChanges, Quat, Query, Ref, Res, Resource, Vec3};
 use bevy_rapier3d::{
-    dynamics::TypedJoint,
+    dynamics::{ImpulseJoint, TypedJoint},
     prelude::GenericJointBuilder,
     rapier::prelude::{JointAxesMask, JointAxis},
 };
 
+pub const SUSPENSION_STIFFNESS: f32 = 1e6;
+pub const SUSPENSION_DAMPING: f32 = 1e3;
+
+/// Wheel joint spring, applied to every car when changed and to new cars on spawn.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SuspensionConfig {
+    pub stiffness: f32,
+    pub damping: f32,
+    /// Gap between the body bottom and the wheel bottom at rest.
+    pub ride_height: f32,
+    /// Wheel travel each way from rest, unlimited with `None`.
+    pub max_travel: Option<f32>,
+}
+
+impl Default for SuspensionConfig {
+    fn default() -> Self {
+        Self {
+            stiffness: SUSPENSION_STIFFNESS,
+            damping: SUSPENSION_DAMPING,
+            ride_height: RIDE_HEIGHT,
+            max_travel: None,
+        }
+    }
+}
+
 pub fn build_joint(anchor: Vec3, is_left: bool) -> TypedJoint {
     let joint = GenericJointBuilder::new(
         JointAxesMask::ANG_Y | JointAxesMask::ANG_Z | JointAxesMask::LIN_X | JointAxesMask::LIN_Z,
@@ -17,7 +43,39 @@ pub fn build_joint(anchor: Vec3, is_left: bool) -> TypedJoint {
     .local_basis1(Quat::from_axis_angle(Vec3::Y, 0.)) // hackfix, prevents jumping on collider edges
     .local_anchor1(anchor)
     .local_anchor2(Vec3::ZERO)
-    .set_motor(JointAxis::LinY, 0., 0., 1e6, 1e3)
+    .set_motor(
+        JointAxis::LinY,
+        0.,
+        0.,
+        SUSPENSION_STIFFNESS,
+        SUSPENSION_DAMPING,
+    )
     .build();
     TypedJoint::GenericJoint(joint)
 }
+
+pub fn suspension_config_system(
+    config: Res<SuspensionConfig>,
+    cars: Query<(&CarSpec, Ref<CarWheels>)>,
+    mut joints: Query<&mut ImpulseJoint>,
+) {
+    for (spec, car_wheels) in cars.iter() {
+        if !config.is_changed() && !car_wheels.is_added() {
+            continue;
+        }
+        for (mount, wheel) in spec.wheel_mount.iter().zip(car_wheels.entities.iter()) {
+            let Ok(mut joint) = joints.get_mut(*wheel) else {
+                continue;
+            };
+            let joint = joint.data.as_mut();
+            joint.set_local_anchor1(mount.anchor - Vec3::Y * (config.ride_height - RIDE_HEIGHT));
+            joint.set_motor(JointAxis::LinY, 0., 0., config.stiffness, config.damping);
+            match config.max_travel {
+                Some(travel) => {
+                    joint.set_limits(JointAxis::LinY, [-travel, travel]);
+                }
+                None => joint.raw.limit_axes.remove(JointAxesMask::LIN_Y),
+            }
+        }
+    }
+}
@@ -1,25 +1,47 @@
 cfg_if::cfg_if! {if #[cfg(feature = "graphics")] {
     pub mod res;
     pub mod sensor;
-    pub use res::CarRes;
+    pub use res::{car_assets_error_system, car_assets_ready, show_rays_toggle_system, CarRes};
 }}
 
 pub mod car;
 pub mod esp;
 pub mod joint;
+pub mod physics;
+pub mod settle;
 pub mod spawn;
 pub mod spec;
+pub mod weather;
 pub mod wheel;
 
 pub use car::*;
 pub use esp::*;
+pub use joint::{suspension_config_system, SuspensionConfig};
+pub use physics::*;
+pub use settle::*;
 pub use spec::*;
+pub use weather::*;
 pub use wheel::*;
 
-use bevy::prelude::SystemSet;
+use bevy::prelude::{App, IntoSystemSetConfigs, SystemSet, Update};
+
+/// Stages of a car update in `Update`, chained in this order by `configure_car_sets`.
+/// Hook in with `.after(CarSet::Input)` to see the controls of this frame,
+/// or `.after(CarSet::Esp)` to see the wheel forces esp applied.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
 pub enum CarSet {
+    /// Everything writing `Car` controls: players, AI, progress along the track.
     Input,
+    /// Network drivers, they read sensors from `Input` and overwrite controls.
     NeuralNetwork,
+    /// Turns controls into wheel torque and steering, `esp_system`.
     Esp,
 }
+
+/// Orders `CarSet` for apps running the car systems, calling it twice is harmless.
+pub fn configure_car_sets(app: &mut App) -> &mut App {
+    app.configure_sets(
+        Update,
+        (CarSet::Input, CarSet::NeuralNetwork, CarSet::Esp).chain(),
+    )
+}
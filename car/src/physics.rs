@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+
+/// Every rapier knob the apps touch, shared so all of them simulate alike.
+/// Rapier has no erp setting anymore, it's derived from the contact frequency and damping.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsParams {
+    /// Longest simulated step, slower frames run several of them.
+    pub max_dt: f32,
+    /// Steps of `max_dt` a long frame may catch up on, the rest of a hitch is
+    /// dropped and plays as slow motion. Substeps grow along, so nothing tunnels.
+    pub max_catch_up: usize,
+    pub substeps: usize,
+    pub solver_iterations: usize,
+    pub warmstart_coefficient: f32,
+    pub contact_natural_frequency: f32,
+    pub contact_damping_ratio: f32,
+    /// Fixed step in seconds for reproducible runs, one step per frame whatever
+    /// the frame time. `None` follows the frame time, see `timestep_mode`.
+    pub fixed: Option<f32>,
+}
+
+impl Default for PhysicsParams {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl PhysicsParams {
+    /// Five substeps per step, the default.
+    pub fn standard() -> Self {
+        Self {
+            max_dt: 1. / 60.,
+            max_catch_up: 4,
+            substeps: 5,
+            solver_iterations: 6,
+            warmstart_coefficient: 0.,
+            contact_natural_frequency: 50.,
+            contact_damping_ratio: 50.,
+            fixed: None,
+        }
+    }
+    /// Twice the substeps, stiff suspension joints stay stable at high speed
+    /// at twice the physics cost.
+    pub fn stable() -> Self {
+        Self {
+            substeps: 10,
+            ..Self::standard()
+        }
+    }
+    /// Fewer iterations for weak devices and many cars,
+    /// expect wheels to wobble in hard impacts.
+    pub fn fast() -> Self {
+        Self {
+            solver_iterations: 4,
+            ..Self::standard()
+        }
+    }
+    /// Preset from `APP_PHYSICS`, `stable` or `fast`, anything else is `standard`.
+    pub fn from_env() -> Self {
+        match std::env::var("APP_PHYSICS").as_deref() {
+            Ok("stable") => Self::stable(),
+            Ok("fast") => Self::fast(),
+            _ => Self::standard(),
+        }
+    }
+    /// `fixed` from `APP_FIXED_DT` in seconds when it's set.
+    pub fn with_fixed_from_env(mut self) -> Self {
+        if let Some(dt) = std::env::var("APP_FIXED_DT")
+            .ok()
+            .and_then(|dt| dt.parse::<f32>().ok())
+            .filter(|dt| dt.is_finite() && *dt > 0.)
+        {
+            self.fixed = Some(dt);
+        }
+        self
+    }
+    /// Variable timestep, substeps grow with `time_scale` to keep the substep dt,
+    /// or the fixed one when `fixed` is set.
+    pub fn timestep_mode(&self, time_scale: f32) -> TimestepMode {
+        if self.fixed.is_some() {
+            return self.fixed_timestep_mode();
+        }
+        self.catch_up_timestep_mode(time_scale, 0.)
+    }
+    /// `timestep_mode` for a frame of `frame_dt` seconds, which may take up to
+    /// `max_catch_up` steps at the same substep dt.
+    pub fn catch_up_timestep_mode(&self, time_scale: f32, frame_dt: f32) -> TimestepMode {
+        let step = self.max_dt * time_scale;
+        let steps = match frame_dt.is_finite() && step > 0. {
+            true => (frame_dt / step)
+                .ceil()
+                .clamp(1., self.max_catch_up.max(1) as f32),
+            false => 1.,
+        };
+        TimestepMode::Variable {
+            max_dt: step * steps,
+            time_scale: 1.,
+            substeps: (self.substeps as f32 * time_scale * steps).round().max(1.) as usize,
+        }
+    }
+    /// Same step every frame regardless of frame time, `fixed` or `max_dt`.
+    pub fn fixed_timestep_mode(&self) -> TimestepMode {
+        TimestepMode::Fixed {
+            dt: self.fixed.unwrap_or(self.max_dt),
+            substeps: self.substeps,
+        }
+    }
+    pub fn apply(&self, integration_parameters: &mut IntegrationParameters) {
+        integration_parameters.num_solver_iterations =
+            NonZeroUsize::new(self.solver_iterations.max(1)).unwrap();
+        integration_parameters.warmstart_coefficient = self.warmstart_coefficient;
+        integration_parameters.contact_natural_frequency = self.contact_natural_frequency;
+        integration_parameters.contact_damping_ratio = self.contact_damping_ratio;
+    }
+}
+
+/// Base of seeded randomness, `APP_SEED` or 0, so fixed step runs repeat exactly.
+pub fn seed_from_env() -> u64 {
+    std::env::var("APP_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Applies `PhysicsParams` to the rapier context at startup and after every change.
+pub fn physics_params_system(params: Res<PhysicsParams>, mut context: ResMut<RapierContext>) {
+    if !params.is_changed() {
+        return;
+    }
+    params.apply(&mut context.integration_parameters);
+    debug!(
+        "physics: {} solver iterations, {} substeps",
+        params.solver_iterations, params.substeps
+    );
+}
+
+/// Resizes the variable timestep to the frame which is about to be simulated,
+/// fixed timesteps are left alone.
+pub fn physics_catch_up_system(
+    time: Res<Time<Virtual>>,
+    params: Res<PhysicsParams>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if !matches!(rapier_config.timestep_mode, TimestepMode::Variable { .. }) {
+        return;
+    }
+    let mode = params.catch_up_timestep_mode(time.relative_speed(), time.delta_seconds());
+    if rapier_config.timestep_mode != mode {
+        rapier_config.timestep_mode = mode;
+    }
+}
+
+/// Frozen single player simulation, physics, car input and training stop.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Paused(pub bool);
+
+/// Run condition for systems which stop while `Paused`, apps without it never pause.
+pub fn not_paused(paused: Option<Res<Paused>>) -> bool {
+    !paused.is_some_and(|paused| paused.0)
+}
+
+/// Space pauses and resumes, Shift+Space is left to respawning.
+/// Virtual time pauses along, so the first variable step after resuming is
+/// one frame long rather than the whole pause.
+pub fn pause_toggle_system(
+    input: Res<ButtonInput<KeyCode>>,
+    mut paused: ResMut<Paused>,
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if !input.just_pressed(KeyCode::Space)
+        || input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    {
+        return;
+    }
+    paused.0 = !paused.0;
+    rapier_config.physics_pipeline_active = !paused.0;
+    match paused.0 {
+        true => time.pause(),
+        false => time.unpause(),
+    }
+    println!("{}", if paused.0 { "paused" } else { "resumed" });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    const HITCH: Duration = Duration::from_millis(500);
+
+    fn substep_dt(mode: TimestepMode) -> f32 {
+        match mode {
+            TimestepMode::Variable {
+                max_dt, substeps, ..
+            } => max_dt / substeps as f32,
+            _ => unreachable!("catch up only resizes variable timesteps"),
+        }
+    }
+
+    #[test]
+    fn catch_up_keeps_the_substep_dt() {
+        let params = PhysicsParams::default();
+        let normal = params.catch_up_timestep_mode(1., params.max_dt);
+        let hitch = params.catch_up_timestep_mode(1., HITCH.as_secs_f32());
+        assert!((substep_dt(normal) - substep_dt(hitch)).abs() < 1e-6);
+        let TimestepMode::Variable { max_dt, .. } = hitch else {
+            unreachable!()
+        };
+        // the rest of the hitch is dropped, not simulated in bigger steps
+        assert!((max_dt - params.max_dt * params.max_catch_up as f32).abs() < 1e-6);
+    }
+
+    #[test]
+    fn car_falling_through_a_long_frame_stays_on_the_ground() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+        ));
+        app.insert_resource(PhysicsParams::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(HITCH));
+        app.world_mut()
+            .resource_mut::<Time<Virtual>>()
+            .set_max_delta(Duration::from_secs(1));
+        app.add_systems(Update, physics_catch_up_system);
+
+        // thin ground, a frame long step would put the car far below it
+        app.world_mut().spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(50., 0.05, 50.),
+            TransformBundle::from_transform(Transform::from_xyz(0., 0., 0.)),
+        ));
+        let car = app
+            .world_mut()
+            .spawn((
+                RigidBody::Dynamic,
+                Collider::cuboid(1., 0.5, 2.),
+                Velocity::linear(Vec3::new(0., -40., 0.)),
+                TransformBundle::from_transform(Transform::from_xyz(0., 1.5, 0.)),
+            ))
+            .id();
+
+        for _ in 0..5 {
+            app.update();
+            let y = app.world().get::<Transform>(car).unwrap().translation.y;
+            assert!(y > 0.4, "car center at {y}, below the ground top at 0.05");
+        }
+        let mode = app.world().resource::<RapierConfiguration>().timestep_mode;
+        let expected = PhysicsParams::default().catch_up_timestep_mode(1., HITCH.as_secs_f32());
+        assert_eq!(mode, expected);
+    }
+}
@@ -16,3 +16,52 @@ impl Default for CarRes {
         }
     }
 }
+
+impl CarRes {
+    /// Both scenes and everything they reference finished loading.
+    pub fn scenes_loaded(&self, asset_server: &AssetServer) -> bool {
+        [&self.car_scene, &self.wheel_scene].iter().all(|scene| {
+            scene
+                .as_ref()
+                .is_some_and(|scene| asset_server.is_loaded_with_dependencies(scene))
+        })
+    }
+}
+
+/// Run condition for systems which spawn cars.
+pub fn car_assets_ready(car_res: Res<CarRes>, asset_server: Res<AssetServer>) -> bool {
+    car_res.scenes_loaded(&asset_server)
+}
+
+/// Logs once when a car scene fails to load, spawns gated on `car_assets_ready` never run then.
+/// Bevy reads assets from the `assets` folder next to the binary, or under
+/// `BEVY_ASSET_ROOT` or `CARGO_MANIFEST_DIR` when set.
+pub fn car_assets_error_system(
+    car_res: Res<CarRes>,
+    asset_server: Res<AssetServer>,
+    mut reported: Local<bool>,
+) {
+    if *reported {
+        return;
+    }
+    for scene in [&car_res.car_scene, &car_res.wheel_scene]
+        .into_iter()
+        .flatten()
+    {
+        if let Some(bevy::asset::LoadState::Failed(error)) = asset_server.get_load_state(scene) {
+            let path = asset_server
+                .get_path(scene)
+                .map_or("car scene".to_string(), |path| path.to_string());
+            error!(
+                "{path} failed to load, cars will not spawn, put the assets folder next to the binary or set BEVY_ASSET_ROOT: {error}"
+            );
+            *reported = true;
+        }
+    }
+}
+
+pub fn show_rays_toggle_system(input: Res<ButtonInput<KeyCode>>, mut car_res: ResMut<CarRes>) {
+    if input.just_pressed(KeyCode::F4) {
+        car_res.show_rays = !car_res.show_rays;
+    }
+}
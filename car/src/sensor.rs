@@ -1,78 +1,135 @@
-use crate::{CarRes, CarSize};
+use crate::{CarRes, CarSize, CarSpec};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_8, PI};
 
-pub const FRAC_PI_16: f32 = FRAC_PI_8 / 2.;
+/// Rays of the default `SensorConfig`. The network input size is built from it at
+/// compile time, so a `SensorConfig::count` other than this needs it changed too.
 pub const SENSOR_COUNT: usize = 31;
 
+/// Fan of rays `sensor_system` casts around each car with `CarSensors`.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SensorConfig {
+    pub count: usize,
+    /// Meters, farther hits read as nothing hit.
+    pub max_distance: f32,
+    /// Degrees the fan spans, centered on the car's forward direction,
+    /// 360 spreads the rays evenly all around.
+    pub fov_degrees: f32,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            count: SENSOR_COUNT,
+            max_distance: 100.,
+            fov_degrees: 360.,
+        }
+    }
+}
+
+impl SensorConfig {
+    /// `APP_SENSOR_FOV` degrees and `APP_SENSOR_DISTANCE` meters when set.
+    pub fn from_env() -> Self {
+        let env = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<f32>().ok())
+                .filter(|value| value.is_finite() && *value > 0.)
+        };
+        let default = Self::default();
+        Self {
+            max_distance: env("APP_SENSOR_DISTANCE").unwrap_or(default.max_distance),
+            fov_degrees: env("APP_SENSOR_FOV").map_or(default.fov_degrees, |fov| fov.min(360.)),
+            ..default
+        }
+    }
+
+    /// Ray angles in radians from forward, left positive.
+    pub fn angles(&self) -> Vec<f32> {
+        let fov = self.fov_degrees.clamp(0., 360.).to_radians();
+        let gaps = match self.fov_degrees >= 360. {
+            // the last ray would point where the first one does
+            true => self.count,
+            false => self.count.saturating_sub(1),
+        };
+        if gaps == 0 {
+            return vec![0.; self.count];
+        }
+        let step = fov / gaps as f32;
+        (0..self.count)
+            .map(|i| match self.fov_degrees >= 360. {
+                true => i as f32 * step,
+                false => -fov / 2. + i as f32 * step,
+            })
+            .collect()
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct CarSensors {
     pub max_toi: f32,
-    pub sensor_config: [(Vec3, Quat); SENSOR_COUNT],
+    /// Ray origin on the car body and direction, `SensorConfig::angles` order.
+    pub sensor_config: Vec<(Vec3, Quat)>,
+    /// Hit distance divided by `max_toi` per ray, 1 when nothing was hit.
     pub sensor_inputs: Vec<f32>,
+    /// What `sensor_config` was built from, a changed resource rebuilds it.
+    pub config: SensorConfig,
 }
 
 impl CarSensors {
     pub fn new(car_size: &CarSize) -> Self {
+        Self::with_config(car_size, &SensorConfig::default())
+    }
+    pub fn with_config(car_size: &CarSize, config: &SensorConfig) -> Self {
         let (hw, hl) = (car_size.hw, car_size.hl);
         Self {
-            max_toi: 100.,
-            sensor_inputs: vec![0.; SENSOR_COUNT],
-            sensor_config: [
-                // front
-                (hw, hl, 0.),
-                (0., hl, 0.),
-                (-hw, hl, 0.),
-                (hw, hl, FRAC_PI_16 / 2.),
-                (-hw, hl, -FRAC_PI_16 / 2.),
-                (hw, hl, FRAC_PI_16),
-                (-hw, hl, -FRAC_PI_16),
-                (hw, hl, FRAC_PI_16 + FRAC_PI_16 / 2.),
-                (-hw, hl, -FRAC_PI_16 - FRAC_PI_16 / 2.),
-                (hw, hl, FRAC_PI_8),
-                (-hw, hl, -FRAC_PI_8),
-                (hw, hl, FRAC_PI_8 + FRAC_PI_16),
-                (-hw, hl, -FRAC_PI_8 - FRAC_PI_16),
-                (hw, hl, FRAC_PI_4),
-                (-hw, hl, -FRAC_PI_4),
-                // front > PI/4
-                (hw, hl, FRAC_PI_4 + FRAC_PI_16),
-                (-hw, hl, -FRAC_PI_4 - FRAC_PI_16),
-                (hw, hl, FRAC_PI_4 + FRAC_PI_8),
-                (-hw, hl, -FRAC_PI_4 - FRAC_PI_8),
-                (hw, hl, FRAC_PI_4 + FRAC_PI_8 + FRAC_PI_16),
-                (-hw, hl, -FRAC_PI_4 - FRAC_PI_8 - FRAC_PI_16),
-                (hw, hl, FRAC_PI_2),
-                (-hw, hl, -FRAC_PI_2),
-                // side
-                (hw, 0., FRAC_PI_2),
-                (-hw, 0., -FRAC_PI_2),
-                // back
-                (hw, -hl, PI),
-                (-hw, -hl, PI),
-                (hw, -hl, PI - FRAC_PI_4),
-                (-hw, -hl, PI + FRAC_PI_4),
-                (hw, -hl, PI - FRAC_PI_2),
-                (-hw, -hl, PI + FRAC_PI_2),
-            ]
-            .map(|(w, l, r)| (Vec3::new(w, -0.1, l), Quat::from_rotation_y(r))),
+            max_toi: config.max_distance,
+            sensor_inputs: vec![1.; config.count],
+            sensor_config: config
+                .angles()
+                .into_iter()
+                .map(|angle| {
+                    // where the ray leaves the body outline
+                    let (x, z) = angle.sin_cos();
+                    let scale = (hw / x.abs()).min(hl / z.abs());
+                    (
+                        Vec3::new(x * scale, -0.1, z * scale),
+                        Quat::from_rotation_y(angle),
+                    )
+                })
+                .collect(),
+            config: config.clone(),
         }
     }
 }
 
+/// Red for a hit right at the car fading to green at `max_toi`.
+pub fn ray_color(reading: f32) -> Color {
+    let reading = reading.clamp(0., 1.);
+    Color::srgba(1. - reading, reading, 0.2, 0.6)
+}
+
+/// Casts the rays of every car with `CarSensors`, `CarRes::show_rays` draws the hits
+/// colored by `ray_color`, F4 toggles it with `show_rays_toggle_system`.
 pub fn sensor_system(
     rapier_context: Res<RapierContext>,
     config: Res<CarRes>,
-    mut q_car: Query<(&mut CarSensors, &Transform)>,
+    sensor_config: Option<Res<SensorConfig>>,
+    mut q_car: Query<(&mut CarSensors, &Transform, Option<&CarSpec>)>,
     mut gizmos: Gizmos,
 ) {
     let sensor_filter = QueryFilter::<'_>::exclude_dynamic().exclude_sensors();
-    for (mut car, t) in q_car.iter_mut() {
+    for (mut car, t, spec) in q_car.iter_mut() {
+        if let (Some(sensor_config), Some(spec)) = (sensor_config.as_ref(), spec) {
+            if car.config != **sensor_config {
+                *car = CarSensors::with_config(&spec.size, sensor_config);
+            }
+        }
+        let count = car.sensor_config.len();
         let dir = Vec3::Z * car.max_toi;
         let mut origins: Vec<Vec3> = Vec::new();
         let mut dirs: Vec<Vec3> = Vec::new();
-        for a in 0..SENSOR_COUNT {
+        for a in 0..count {
             let (pos, far_quat) = car.sensor_config[a];
             let origin = t.translation + t.rotation.mul_vec3(pos);
             origins.push(origin);
@@ -81,8 +138,8 @@ pub fn sensor_system(
             dirs.push(origin + dir_vec);
         }
 
-        let mut inputs: Vec<f32> = vec![0.; SENSOR_COUNT];
-        let mut hit_points: Vec<Vec3> = vec![Vec3::ZERO; SENSOR_COUNT];
+        let mut inputs: Vec<f32> = vec![1.; count];
+        let mut hit_points: Vec<Vec3> = vec![Vec3::ZERO; count];
         for (i, &ray_dir_pos) in dirs.iter().enumerate() {
             let ray_pos = origins[i];
             let ray_dir = (ray_dir_pos - ray_pos).normalize();
@@ -91,13 +148,9 @@ pub fn sensor_system(
                 rapier_context.cast_ray(ray_pos, ray_dir, car.max_toi, false, sensor_filter)
             {
                 hit_points[i] = ray_pos + ray_dir * toi;
-                if toi > 0. {
-                    inputs[i] = 1. - toi / car.max_toi;
-                    if config.show_rays {
-                        gizmos.line(ray_pos, hit_points[i], Color::srgba(0.5, 0.3, 0.3, 0.5));
-                    }
-                } else {
-                    inputs[i] = 0.;
+                inputs[i] = (toi / car.max_toi).clamp(0., 1.);
+                if toi > 0. && config.show_rays {
+                    gizmos.line(ray_pos, hit_points[i], ray_color(inputs[i]));
                 }
             }
         }
@@ -0,0 +1,88 @@
+use crate::{Car, CarSpec, CarWheels, Wheel};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+#[derive(Resource, Debug, Clone)]
+pub struct SettleConfig {
+    /// Gap between the lowest wheel point and the surface below the spawn point.
+    pub clearance: f32,
+    /// Seconds after spawn during which car controls are held at zero.
+    pub duration: f32,
+    /// How far above and below the spawn point the surface is searched.
+    pub probe: f32,
+}
+
+impl Default for SettleConfig {
+    fn default() -> Self {
+        Self {
+            clearance: 0.05,
+            duration: 0.5,
+            probe: 5.,
+        }
+    }
+}
+
+#[derive(Component, Debug, Default)]
+pub struct Settling {
+    pub placed: bool,
+    pub elapsed: f32,
+}
+
+/// Drops freshly spawned cars onto the surface below them instead of letting them fall,
+/// run it after input and before `esp_system`.
+pub fn car_settle_system(
+    mut cmd: Commands,
+    time: Res<Time>,
+    config: Option<Res<SettleConfig>>,
+    rapier_context: Res<RapierContext>,
+    mut cars: Query<
+        (
+            Entity,
+            &mut Car,
+            &mut Settling,
+            &CarSpec,
+            &CarWheels,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        Without<Wheel>,
+    >,
+    mut wheels: Query<(&mut Transform, &mut Velocity), With<Wheel>>,
+) {
+    let config = config.map(|c| c.clone()).unwrap_or_default();
+    for (entity, mut car, mut settling, spec, car_wheels, mut transform, mut velocity) in
+        cars.iter_mut()
+    {
+        if !settling.placed {
+            settling.placed = true;
+            let origin = transform.translation + Vec3::Y * config.probe;
+            let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+            if let Some((_, toi)) =
+                rapier_context.cast_ray(origin, -Vec3::Y, config.probe * 2., true, filter)
+            {
+                let bottom = spec
+                    .wheel_mount
+                    .iter()
+                    .map(|mount| spec.wheel_radius - mount.anchor.y)
+                    .fold(0., f32::max);
+                let surface = origin.y - toi;
+                let shift =
+                    Vec3::Y * (surface + bottom + config.clearance - transform.translation.y);
+                transform.translation += shift;
+                for wheel in car_wheels.entities.iter() {
+                    if let Ok((mut wheel_transform, mut wheel_velocity)) = wheels.get_mut(*wheel) {
+                        wheel_transform.translation += shift;
+                        *wheel_velocity = Velocity::zero();
+                    }
+                }
+            }
+            *velocity = Velocity::zero();
+        }
+
+        car.release_controls();
+        settling.elapsed += time.delta_seconds();
+        if settling.elapsed >= config.duration {
+            cmd.entity(entity).remove::<Settling>();
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use bevy::prelude::{Component, Vec3};
 use std::f32::consts::FRAC_PI_4;
 
+/// Gap between the body bottom and the wheel bottom the mounts are built for.
+pub const RIDE_HEIGHT: f32 = 0.06;
+/// Half height of the default car body, `CarSize::hh`.
+pub const CAR_HALF_HEIGHT: f32 = 0.35;
+
 #[derive(Debug, Clone)]
 pub struct CarSize {
     pub hw: f32,
@@ -13,23 +18,30 @@ pub struct CarSpec {
     pub size: CarSize,
     pub wheel_radius: f32,
     pub wheel_width: f32,
-    pub wheel_mount: [WheelMount; 4],
+    /// Any number of wheels, `front` ones steer and `left` ones spin mirrored.
+    pub wheel_mount: Vec<WheelMount>,
 
     pub wheel_max_torque: f32,
+    /// Steering lock at standstill.
     pub wheel_max_angle: f32,
     pub max_speed: f32,
+    /// Speed at which the steering lock bottoms out at `high_speed_steering`.
     pub max_steering_speed: f32,
+    /// Fraction of `wheel_max_angle` left at `max_steering_speed`.
+    pub high_speed_steering: f32,
+    /// Shape of the lock reduction between standstill and `max_steering_speed`, 1 is linear.
+    pub steering_curve: f32,
 }
 
 impl Default for CarSpec {
     fn default() -> Self {
-        let ride_height = 0.06;
+        let ride_height = RIDE_HEIGHT;
         let wheel_radius: f32 = 0.35;
         let wheel_width: f32 = 0.34;
 
         let size = CarSize {
             hw: 1.,
-            hh: 0.35,
+            hh: CAR_HALF_HEIGHT,
             hl: 2.2,
         };
 
@@ -39,7 +51,7 @@ impl Default for CarSpec {
             size.hl - wheel_radius - 0.5,
         );
 
-        let anchors: [(Vec3, bool, bool); 4] = [
+        let anchors = [
             (Vec3::new(shift.x, shift.y, shift.z), true, false), // front right
             (Vec3::new(-shift.x, shift.y, shift.z), true, true), // front left
             (Vec3::new(shift.x, shift.y, -shift.z), false, false), // rear right
@@ -50,19 +62,72 @@ impl Default for CarSpec {
             size,
             max_speed: 300. * 1000. / 3600.,
             max_steering_speed: 270. * 1000. / 3600.,
+            high_speed_steering: 0.1,
+            steering_curve: 2.,
             wheel_max_torque: 1200.,
             wheel_max_angle: FRAC_PI_4,
             wheel_radius,
             wheel_width,
-            wheel_mount: anchors.map(|a| WheelMount {
-                anchor: a.0,
-                front: a.1,
-                left: a.2,
-            }),
+            wheel_mount: anchors
+                .into_iter()
+                .map(|(anchor, front, left)| WheelMount {
+                    anchor,
+                    front,
+                    left,
+                })
+                .collect(),
         }
     }
 }
 
+impl CarSpec {
+    /// Single steered front wheel on the center line, rear axle as the default car.
+    pub fn three_wheeler() -> Self {
+        let mut spec = Self::default();
+        let front = spec.wheel_mount[0].anchor;
+        spec.wheel_mount.drain(..2);
+        spec.wheel_mount.insert(
+            0,
+            WheelMount {
+                anchor: Vec3::new(0., front.y, front.z),
+                front: true,
+                left: false,
+            },
+        );
+        spec
+    }
+    /// Default car with a second driven rear axle behind the first one.
+    pub fn six_wheeler() -> Self {
+        let mut spec = Self::default();
+        spec.size.hl += spec.wheel_radius * 1.2;
+        let rear: Vec<WheelMount> = spec
+            .wheel_mount
+            .iter()
+            .filter(|mount| !mount.front)
+            .cloned()
+            .collect();
+        for mount in spec.wheel_mount.iter_mut() {
+            let shift = match mount.front {
+                true => spec.wheel_radius * 1.2,
+                false => -spec.wheel_radius * 1.2,
+            };
+            mount.anchor.z += shift;
+        }
+        for mut mount in rear {
+            mount.anchor.z += spec.wheel_radius * 1.2;
+            spec.wheel_mount.push(mount);
+        }
+        spec
+    }
+    /// Maximum front wheel angle at `speed` m/s.
+    pub fn steering_lock(&self, speed: f32) -> f32 {
+        let low_speed_x = (1. - speed / self.max_steering_speed).clamp(0., 1.);
+        let low_speed_x = low_speed_x.powf(self.steering_curve.max(0.));
+        let min = self.high_speed_steering.clamp(0., 1.);
+        self.wheel_max_angle * (min + (1. - min) * low_speed_x)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct WheelMount {
     pub anchor: Vec3,
@@ -0,0 +1,55 @@
+use crate::Wheel;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::Friction;
+use serde::{Deserialize, Serialize};
+
+/// Wheel friction coefficient in dry conditions.
+pub const WHEEL_FRICTION: f32 = 5.;
+
+/// Track condition, scales the grip of every wheel.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weather {
+    #[default]
+    Dry,
+    Wet,
+    Icy,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 3] = [Weather::Dry, Weather::Wet, Weather::Icy];
+
+    /// Multiplier of `WHEEL_FRICTION`.
+    pub fn grip(self) -> f32 {
+        match self {
+            Weather::Dry => 1.,
+            Weather::Wet => 0.6,
+            Weather::Icy => 0.25,
+        }
+    }
+    pub fn next(self) -> Self {
+        match self {
+            Weather::Dry => Weather::Wet,
+            Weather::Wet => Weather::Icy,
+            Weather::Icy => Weather::Dry,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Dry => "dry",
+            Weather::Wet => "wet",
+            Weather::Icy => "icy",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|weather| weather.name() == name)
+    }
+}
+
+/// Sets wheel friction from `Weather` on every change and on new wheels.
+pub fn weather_system(weather: Res<Weather>, mut wheels: Query<(Ref<Wheel>, &mut Friction)>) {
+    for (wheel, mut friction) in wheels.iter_mut() {
+        if weather.is_changed() || wheel.is_added() {
+            friction.coefficient = WHEEL_FRICTION * weather.grip();
+        }
+    }
+}
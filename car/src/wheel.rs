@@ -1,4 +1,4 @@
-use crate::{WheelMount, CAR_TRAINING_GROUP, STATIC_GROUP};
+use crate::{WheelMount, CAR_TRAINING_GROUP, STATIC_GROUP, WHEEL_FRICTION};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::f32::consts::PI;
@@ -85,7 +85,7 @@ pub fn spawn_wheel(
             },
             Friction {
                 combine_rule: CoefficientCombineRule::Average,
-                coefficient: 5.0,
+                coefficient: WHEEL_FRICTION,
                 ..default()
             },
             // Restitution::coefficient(0.7),
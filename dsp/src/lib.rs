@@ -1,9 +1,12 @@
-use bevy_garage_car::Car;
+use bevy_garage_car::{Car, LocalPlayer, Player};
 use bevy_rapier3d::prelude::Velocity;
 use {bevy::prelude::*, bevy_fundsp::prelude::*, uuid::Uuid};
 
 // https://github.com/harudagondi/bevy_fundsp/blob/main/examples/bevy_audio/pitch.rs
 
+mod profile;
+pub use profile::{EngineProfile, EngineProfiles};
+
 pub struct EngineSoundPlugin;
 struct PianoDsp<F>(F);
 
@@ -24,6 +27,8 @@ struct PianoId(Uuid);
 struct CarSound {
     pitch: Shared<f32>,
     vol: Shared<f32>,
+    /// Oscillator gains in `EngineProfile::gains` order.
+    mix: [Shared<f32>; 5],
 }
 
 impl CarSound {
@@ -33,9 +38,33 @@ impl CarSound {
     fn set_vol(&self, vol: f32) {
         self.vol.set_value(vol.into());
     }
+    fn set_profile(&self, profile: &EngineProfile) {
+        for (shared, gain) in self.mix.iter().zip(profile.gains()) {
+            shared.set_value(gain);
+        }
+    }
 }
 
 const VELOCITY_PITCH_K: f32 = 30.;
+
+/// How the engine note follows the car, idle pitch and slope come from `EngineProfile`.
+#[derive(Debug, Clone, Resource)]
+pub struct EngineSoundConfig {
+    /// Highest pitch in Hz, the square and saw voices alias above a few hundred Hz.
+    pub max_pitch: f32,
+    /// Rate per second the pitch closes in on the target, lower glides longer
+    /// and avoids zipper noise from per frame jumps.
+    pub smoothing: f32,
+}
+
+impl Default for EngineSoundConfig {
+    fn default() -> Self {
+        Self {
+            max_pitch: 400.,
+            smoothing: 8.,
+        }
+    }
+}
 impl Plugin for EngineSoundPlugin {
     fn build(&self, app: &mut App) {
         let pitch = shared(VELOCITY_PITCH_K);
@@ -44,28 +73,75 @@ impl Plugin for EngineSoundPlugin {
         let vol = shared(0.5);
         let vol_clone = vol.clone();
 
-        let piano = move || var(&pitch2) >> var(&vol_clone) * square() >> split::<U2>() * 0.2;
+        let profile = EngineProfile::inline_4();
+        let mix = profile.gains().map(shared);
+        let [square_g, saw_g, sine_g, harmonic_g, sub_g] = mix.clone();
+
+        let piano = move || {
+            var(&pitch2)
+                >> var(&vol_clone)
+                    * (var(&square_g) * square()
+                        & var(&saw_g) * saw()
+                        & var(&sine_g) * sine()
+                        & var(&harmonic_g) * (mul(2.) >> sine())
+                        & var(&sub_g) * (mul(0.5) >> square()))
+                >> split::<U2>() * 0.2
+        };
         let piano_dsp = PianoDsp(piano.clone());
         let piano_id = piano_dsp.id();
 
         app.add_plugins((DspPlugin::default(),))
             .add_dsp_source(piano_dsp, SourceType::Dynamic)
-            .insert_resource(CarSound { pitch, vol })
+            .insert_resource(CarSound { pitch, vol, mix })
             .insert_resource(PianoId(piano_id))
+            .init_resource::<EngineProfiles>()
+            .init_resource::<EngineSoundConfig>()
             .add_systems(PostStartup, engine_sound_start)
-            .add_systems(Update, (engine_sound, engine_sound_vol));
+            .add_systems(
+                Update,
+                (engine_sound, engine_sound_vol, engine_profile_system),
+            );
     }
 }
 
-fn engine_sound(mut car_query: Query<&Velocity, With<Car>>, car_sound: Res<CarSound>) {
-    for velocity in car_query.iter_mut() {
-        let vel = velocity.linvel.length();
-        let pitch: f32 = if vel < 0.1 {
-            VELOCITY_PITCH_K
-        } else {
-            VELOCITY_PITCH_K + vel * 2.
-        };
-        car_sound.set_pitch(pitch);
+/// Pitch of the first local player's car speed, clamped and smoothed.
+fn engine_sound(
+    time: Res<Time>,
+    cars: Query<(&Velocity, Option<&LocalPlayer>), (With<Car>, With<Player>)>,
+    car_sound: Res<CarSound>,
+    profiles: Res<EngineProfiles>,
+    config: Res<EngineSoundConfig>,
+    mut pitch: Local<Option<f32>>,
+) {
+    let profile = profiles.current();
+    let Some((velocity, _)) = cars
+        .iter()
+        .find(|(_, local_player)| local_player.map_or(0, |local_player| local_player.index) == 0)
+    else {
+        return;
+    };
+    let target = profile
+        .pitch(velocity.linvel.length())
+        .clamp(0., config.max_pitch.max(profile.pitch_base));
+    let blend = 1. - (-config.smoothing.max(0.) * time.delta_seconds()).exp();
+    let smoothed = match *pitch {
+        Some(pitch) => pitch + (target - pitch) * blend,
+        None => target,
+    };
+    *pitch = Some(smoothed);
+    car_sound.set_pitch(smoothed);
+}
+fn engine_profile_system(
+    input: Res<ButtonInput<KeyCode>>,
+    car_sound: Res<CarSound>,
+    mut profiles: ResMut<EngineProfiles>,
+) {
+    if input.just_pressed(KeyCode::KeyX) {
+        profiles.next();
+        println!("engine {}", profiles.current().name);
+    }
+    if profiles.is_changed() {
+        car_sound.set_profile(profiles.current());
     }
 }
 fn engine_sound_vol(input: Res<ButtonInput<KeyCode>>, car_sound: Res<CarSound>) {
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+
+/// Oscillator mix of an engine voice, gains are relative and normalized when applied.
+#[derive(Debug, Clone)]
+pub struct EngineProfile {
+    pub name: String,
+    pub square: f32,
+    pub saw: f32,
+    pub sine: f32,
+    /// Sine one octave above the fundamental.
+    pub second_harmonic: f32,
+    /// Square one octave below the fundamental.
+    pub sub_octave: f32,
+    /// Idle pitch in Hz.
+    pub pitch_base: f32,
+    /// Added Hz per m/s of car speed.
+    pub pitch_per_velocity: f32,
+}
+
+impl EngineProfile {
+    pub fn inline_4() -> Self {
+        Self {
+            name: "inline-4".to_string(),
+            square: 1.,
+            saw: 0.,
+            sine: 0.,
+            second_harmonic: 0.,
+            sub_octave: 0.,
+            pitch_base: 30.,
+            pitch_per_velocity: 2.,
+        }
+    }
+    pub fn v8() -> Self {
+        Self {
+            name: "V8".to_string(),
+            square: 0.3,
+            saw: 1.,
+            sine: 0.,
+            second_harmonic: 0.,
+            sub_octave: 0.6,
+            pitch_base: 20.,
+            pitch_per_velocity: 1.5,
+        }
+    }
+    pub fn electric() -> Self {
+        Self {
+            name: "electric".to_string(),
+            square: 0.,
+            saw: 0.,
+            sine: 1.,
+            second_harmonic: 0.4,
+            sub_octave: 0.,
+            pitch_base: 60.,
+            pitch_per_velocity: 4.,
+        }
+    }
+    pub fn pitch(&self, velocity: f32) -> f32 {
+        if velocity < 0.1 {
+            self.pitch_base
+        } else {
+            self.pitch_base + velocity * self.pitch_per_velocity
+        }
+    }
+    /// `[square, saw, sine, second_harmonic, sub_octave]` summing to 1.
+    pub fn gains(&self) -> [f32; 5] {
+        let gains = [
+            self.square,
+            self.saw,
+            self.sine,
+            self.second_harmonic,
+            self.sub_octave,
+        ]
+        .map(|g| g.max(0.));
+        let sum: f32 = gains.iter().sum();
+        match sum > 0. {
+            true => gains.map(|g| g / sum),
+            false => gains,
+        }
+    }
+}
+
+/// Known engine profiles, register new ones in code and `select` one by name.
+#[derive(Resource, Debug)]
+pub struct EngineProfiles {
+    profiles: Vec<EngineProfile>,
+    selected: usize,
+}
+
+impl Default for EngineProfiles {
+    fn default() -> Self {
+        Self {
+            profiles: vec![
+                EngineProfile::inline_4(),
+                EngineProfile::v8(),
+                EngineProfile::electric(),
+            ],
+            selected: 0,
+        }
+    }
+}
+
+impl EngineProfiles {
+    /// Adds a profile, replacing the one with the same name.
+    pub fn register(&mut self, profile: EngineProfile) {
+        match self.profiles.iter().position(|p| p.name == profile.name) {
+            Some(i) => self.profiles[i] = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<&EngineProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|p| p.name.as_str())
+    }
+    /// Returns false and keeps the current profile for unknown names.
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.profiles.iter().position(|p| p.name == name) {
+            Some(i) => {
+                self.selected = i;
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % self.profiles.len();
+    }
+    pub fn current(&self) -> &EngineProfile {
+        &self.profiles[self.selected]
+    }
+}
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+use bevy_garage::car_app;
+use bevy_garage_camera::CarCameraPlugin;
+use bevy_garage_car::{Car, CarSet, LocalPlayer};
+use bevy_rapier3d::prelude::{ExternalForce, Velocity};
+
+/// The full game plus two systems hooked into the car update through `CarSet`.
+fn main() {
+    let mut app = App::new();
+    app.add_plugins((DefaultPlugins, CarCameraPlugin));
+    car_app(&mut app)
+        .add_systems(
+            Update,
+            (
+                // controls of this frame are final, esp has not turned them into forces yet
+                speed_limiter_system
+                    .after(CarSet::Input)
+                    .before(CarSet::Esp),
+                // esp has set this frame's forces
+                log_controls_system.after(CarSet::Esp),
+            ),
+        )
+        .run();
+}
+
+const SPEED_LIMIT: f32 = 20.;
+
+/// Cuts the gas of local players above `SPEED_LIMIT` m/s, whatever drives them.
+fn speed_limiter_system(mut cars: Query<(&mut Car, &Velocity), With<LocalPlayer>>) {
+    for (mut car, velocity) in cars.iter_mut() {
+        if velocity.linvel.length() > SPEED_LIMIT {
+            car.set_gas(0.);
+        }
+    }
+}
+
+fn log_controls_system(
+    time: Res<Time>,
+    mut elapsed: Local<f32>,
+    cars: Query<(&Car, &LocalPlayer, &Velocity, &ExternalForce)>,
+) {
+    *elapsed += time.delta_seconds();
+    if *elapsed < 1. {
+        return;
+    }
+    *elapsed = 0.;
+    for (car, local, velocity, force) in cars.iter() {
+        println!(
+            "player {}: gas {:.2} brake {:.2} steering {:.2}, {:.1} m/s, aero {:.0} N",
+            local.index + 1,
+            car.gas,
+            car.brake,
+            car.steering,
+            velocity.linvel.length(),
+            force.force.length(),
+        );
+    }
+}
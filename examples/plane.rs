@@ -1,5 +1,11 @@
 use bevy::prelude::*;
-use bevy_garage_car::{car_start_system, esp_system, spawn_car, Car, CarRes};
+use bevy_garage_car::{
+    car_assets_error_system, car_assets_ready, car_start_system, esp_system, not_paused,
+    pause_toggle_system,
+    sensor::{sensor_system, CarSensors},
+    show_rays_toggle_system, spawn_car, Car, CarRes, CarSpec, Paused,
+};
+use bevy_garage_track::{spawn_ground, GroundConfig, GroundShape};
 use bevy_rapier3d::prelude::*;
 
 fn main() {
@@ -22,14 +28,24 @@ fn main() {
         })
         .add_systems(
             Startup,
+            (rapier_config_start_system, plane_start, car_start_system),
+        )
+        .insert_resource(TestGround::from_args())
+        .init_resource::<Paused>()
+        .add_systems(
+            Update,
             (
-                rapier_config_start_system,
-                plane_start,
-                car_start_system,
-                spawn_car_system.after(car_start_system),
+                input_system.run_if(not_paused),
+                gamepad_system.after(input_system).run_if(not_paused),
+                esp_system.after(gamepad_system),
+                test_ground_system,
+                sensor_system,
+                show_rays_toggle_system,
+                pause_toggle_system,
+                spawn_car_system.run_if(car_assets_ready.and_then(run_once())),
+                car_assets_error_system,
             ),
         )
-        .add_systems(Update, (input_system, esp_system.after(input_system)))
         .run();
 }
 
@@ -41,7 +57,7 @@ fn rapier_config_start_system(mut c: ResMut<RapierContext>) {
 }
 
 fn spawn_car_system(mut cmd: Commands, car_res: Res<CarRes>) {
-    spawn_car(
+    let car = spawn_car(
         &mut cmd,
         &car_res.car_scene.as_ref().unwrap(),
         &car_res.wheel_scene.as_ref().unwrap(),
@@ -52,6 +68,8 @@ fn spawn_car_system(mut cmd: Commands, car_res: Res<CarRes>) {
             z: 0.,
         }),
     );
+    cmd.entity(car)
+        .insert(CarSensors::new(&CarSpec::default().size));
 }
 
 fn plane_start(
@@ -59,21 +77,13 @@ fn plane_start(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let size = 100.;
-    let (cols, rows) = (10, 10);
-
-    cmd.spawn((
-        PbrBundle {
-            mesh: meshes.add(Plane3d::default().mesh().size(size, size)),
-            material: materials.add(Color::srgb(0.3, 0.5, 0.3)),
-            ..default()
-        },
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        Friction::coefficient(3.),
-        Restitution::coefficient(0.),
-        Collider::heightfield(vec![0.; rows * cols], rows, cols, Vec3::new(size, 0., size)),
-    ));
+    cmd.spawn(PbrBundle {
+        mesh: meshes.add(Plane3d::default().mesh().size(100., 100.)),
+        material: materials.add(Color::srgb(0.3, 0.5, 0.3)),
+        // bumps poke through, the debug render shows the collider
+        transform: Transform::from_xyz(0., -0.01, 0.),
+        ..default()
+    });
 
     cmd.spawn(PointLightBundle {
         point_light: PointLight {
@@ -91,6 +101,71 @@ fn plane_start(
     });
 }
 
+/// Ground collider the test car drives on, rebuilt whenever the config changes.
+#[derive(Resource)]
+struct TestGround {
+    config: GroundConfig,
+    entity: Option<Entity>,
+}
+
+impl TestGround {
+    /// `cargo run --example plane -- --flat` starts on a cuboid instead of the heightfield.
+    fn from_args() -> Self {
+        let shape = match std::env::args().any(|arg| arg == "--flat") {
+            true => GroundShape::Flat,
+            false => GroundShape::Heightfield,
+        };
+        Self {
+            config: GroundConfig {
+                shape,
+                subdivisions: 50,
+                bump_amplitude: 0.1,
+                ..default()
+            },
+            entity: None,
+        }
+    }
+}
+
+/// G toggles flat and bumpy ground, +/- the bump amplitude, [ and ] the heightfield resolution.
+fn test_ground_system(
+    mut cmd: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut ground: ResMut<TestGround>,
+) {
+    let mut config = ground.config.clone();
+    if input.just_pressed(KeyCode::KeyG) {
+        config.shape = match config.shape {
+            GroundShape::Flat => GroundShape::Heightfield,
+            GroundShape::Heightfield => GroundShape::Flat,
+        };
+    }
+    if input.just_pressed(KeyCode::Equal) {
+        config.bump_amplitude += 0.05;
+    }
+    if input.just_pressed(KeyCode::Minus) {
+        config.bump_amplitude = (config.bump_amplitude - 0.05).max(0.);
+    }
+    if input.just_pressed(KeyCode::BracketRight) {
+        config.subdivisions = (config.subdivisions * 2).min(400);
+    }
+    if input.just_pressed(KeyCode::BracketLeft) {
+        config.subdivisions = (config.subdivisions / 2).max(2);
+    }
+    if ground.entity.is_some() && config == ground.config {
+        return;
+    }
+    if let Some(entity) = ground.entity.take() {
+        cmd.entity(entity).despawn();
+    }
+    println!(
+        "ground: {:?}, amplitude {:.2}, subdivisions {}",
+        config.shape, config.bump_amplitude, config.subdivisions
+    );
+    ground.entity = Some(spawn_ground(&mut cmd, &config));
+    ground.config = config;
+}
+
 fn input_system(input: Res<ButtonInput<KeyCode>>, mut cars: Query<&mut Car>) {
     for mut car in cars.iter_mut() {
         if input.pressed(KeyCode::ArrowUp) {
@@ -121,3 +196,34 @@ fn input_system(input: Res<ButtonInput<KeyCode>>, mut cars: Query<&mut Car>) {
         }
     }
 }
+
+/// First gamepad, left stick steers, right trigger is gas and left trigger brake.
+/// It lets go once centered, so the arrow keys keep working.
+fn gamepad_system(
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    mut driving: Local<bool>,
+    mut cars: Query<&mut Car>,
+) {
+    // an unplugged pad reads as centered
+    let gamepad = gamepads.iter().next();
+    let trigger = |button_type| {
+        gamepad
+            .and_then(|gamepad| button_axes.get(GamepadButton::new(gamepad, button_type)))
+            .unwrap_or(0.)
+    };
+    let steering = gamepad
+        .and_then(|gamepad| axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)))
+        .unwrap_or(0.);
+    let gas = trigger(GamepadButtonType::RightTrigger2);
+    let brake = trigger(GamepadButtonType::LeftTrigger2);
+    let active = steering != 0. || gas != 0. || brake != 0.;
+    if !active && !*driving {
+        return;
+    }
+    *driving = active;
+    for mut car in cars.iter_mut() {
+        car.set_controls(gas, brake, steering);
+    }
+}
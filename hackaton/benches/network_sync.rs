@@ -0,0 +1,70 @@
+//! Cost of the per-frame snapshot in `server_network_sync`: the car and wheel
+//! query pass plus bincode serialization, at the car counts races are run with.
+//!
+//! `cargo bench -p hackaton --bench network_sync`
+
+use bevy::{
+    ecs::system::SystemState,
+    prelude::{Entity, GlobalTransform, Quat, Query, Transform, Vec3, With, World},
+};
+use bevy_garage_car::{CarWheels, Wheel};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use hackaton::{Player, sync::sync_message};
+
+const CAR_COUNTS: [usize; 4] = [1, 4, 8, 12];
+const WHEELS_PER_CAR: usize = 4;
+
+type SyncState = SystemState<(
+    Query<'static, 'static, (Entity, &'static Transform, &'static CarWheels), With<Player>>,
+    Query<'static, 'static, &'static GlobalTransform, With<Wheel>>,
+)>;
+
+fn world_with_cars(count: usize) -> World {
+    let mut world = World::new();
+    for i in 0..count {
+        let transform = Transform::from_xyz(i as f32 * 10., 0.5, 0.)
+            .with_rotation(Quat::from_rotation_y(i as f32));
+        let wheels = (0..WHEELS_PER_CAR)
+            .map(|w| {
+                let offset = Vec3::new((w % 2) as f32 - 0.5, 0., (w / 2) as f32 - 0.5);
+                let pose = transform.mul_transform(Transform::from_translation(offset));
+                world
+                    .spawn((
+                        Wheel {
+                            radius: 0.35,
+                            width: 0.25,
+                            front: w < 2,
+                            left: w % 2 == 0,
+                            border_radius: 0.1,
+                        },
+                        GlobalTransform::from(pose),
+                    ))
+                    .id()
+            })
+            .collect();
+        world.spawn((Player { id: i as u64 }, transform, CarWheels::new(wheels)));
+    }
+    world
+}
+
+fn network_sync(c: &mut Criterion) {
+    let mut group = c.benchmark_group("server_network_sync");
+    for count in CAR_COUNTS {
+        let mut world = world_with_cars(count);
+        let mut state: SyncState = SystemState::new(&mut world);
+        let (cars, wheels) = state.get(&world);
+        let bytes = sync_message(&cars, &wheels, 0, 0, |_| 0).len();
+        println!("{count} cars: {bytes} bytes per snapshot");
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                let (cars, wheels) = state.get(&world);
+                sync_message(&cars, &wheels, 0, 0, |_| 0)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, network_sync);
+criterion_main!(benches);
@@ -1,35 +1,50 @@
 use std::{
+    collections::VecDeque,
     net::UdpSocket,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use bevy::{
     DefaultPlugins,
     app::{App, Startup, Update},
+    asset::{AssetServer, Handle},
+    color::palettes::css,
     diagnostic::FrameTimeDiagnosticsPlugin,
-    input::ButtonInput,
-    math::Quat,
+    input::{
+        Axis, ButtonInput,
+        gamepad::{
+            Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType,
+            GamepadConnectionEvent, Gamepads,
+        },
+    },
+    math::{Quat, Vec3},
     prelude::{
-        Commands, Component, Entity, IntoSystemConfigs, KeyCode, Local, Query, Res, ResMut,
-        Resource, Transform, With,
+        Commands, Component, DespawnRecursiveExt, DetectChanges, Entity, EventReader,
+        GlobalTransform, IntoSystemConfigs, KeyCode, Local, Parent, Query, Res, ResMut, Resource,
+        TextBundle, Time, Transform, With, Without, default, not,
     },
+    text::{Font, Text, TextSection, TextStyle},
+    ui::{PositionType, Style, Val},
     utils::HashMap,
 };
-use bevy_egui::{EguiContexts, EguiPlugin};
-use bevy_garage_camera::CarCameraPlugin;
-use bevy_garage_car::{spawn_car, CarWheels, Wheel};
+use bevy_egui::{EguiContexts, EguiPlugin, egui};
+use bevy_garage_camera::{CameraConfig, CarCameraPlugin};
+use bevy_garage_car::{CarWheels, PhysicsParams, Weather, Wheel, spawn_car};
 use bevy_garage_track::{TrackPlugin, track_start_system};
 use bevy_renet::{
     RenetClientPlugin, client_connected,
     renet::{
         RenetClient,
-        transport::{ClientAuthentication, NetcodeClientTransport},
+        transport::{ClientAuthentication, ConnectToken, NetcodeClientTransport},
     },
     transport::NetcodeClientPlugin,
 };
 use hackaton::{
-    ClientChannel, NetworkedEntities, PlayerInput, SERVER_PROTOCOL_ID, ServerChannel,
-    ServerMessages, connection_config, shared_systems::setup_level,
+    ClientChannel, ClientMessages, ConnectMode, InterpolationConfig, LapRecord, LocalClientId,
+    MatchInfo, NetworkMode, NetworkedEntities, PlayerInput, PredictionConfig, RaceState,
+    SERVER_PROTOCOL_ID, SequencedInput, ServerChannel, ServerMessages, admin_token,
+    connection_config, netcode_private_key, player_name,
+    shared_systems::{LevelConfig, setup_level},
 };
 use renet_visualizer::{RenetClientVisualizer, RenetVisualizerStyle};
 
@@ -39,6 +54,144 @@ struct ControlledPlayer;
 #[derive(Default, Resource)]
 struct NetworkMapping(HashMap<Entity, Entity>);
 
+/// Next tick to send input for and the tick the server last reported waiting on.
+#[derive(Default, Resource)]
+struct LockstepClock {
+    next: Option<u64>,
+    server_tick: u64,
+}
+
+/// Snapshots kept per car for interpolation, well over `InterpolationConfig::delay`
+/// at the server send rate.
+const SNAPSHOT_HISTORY: usize = 8;
+
+/// Car and wheel poses of one snapshot, wheels in world space and `CarWheels` order.
+#[derive(Debug, Clone)]
+struct TimedPose {
+    received_at: f32,
+    transform: Transform,
+    wheels: Vec<Transform>,
+}
+
+/// Last snapshot pose of a car and the velocity between the two latest ones,
+/// lets the car keep moving through a short snapshot gap.
+#[derive(Debug, Default, Component)]
+struct SnapshotMotion {
+    /// `NetworkedEntities::sequence` of the newest applied snapshot.
+    sequence: Option<u64>,
+    /// Last own input the server applied before the newest snapshot.
+    acked_input: u64,
+    /// Latest snapshots, oldest first.
+    history: VecDeque<TimedPose>,
+    /// `Time::elapsed_seconds` when the snapshot arrived, `None` before the first one.
+    received_at: Option<f32>,
+    transform: Transform,
+    linvel: Vec3,
+    /// Scaled axis in rad/s.
+    angvel: Vec3,
+    /// World poses of unparented wheels, `CarWheels` order.
+    wheels: Vec<Transform>,
+}
+
+impl SnapshotMotion {
+    /// A snapshot sent before the newest applied one, it arrived out of order.
+    fn is_stale(&self, sequence: u64) -> bool {
+        sequence != 0 && self.sequence.is_some_and(|newest| sequence <= newest)
+    }
+
+    fn record(&mut self, transform: Transform, wheels: Vec<Transform>, now: f32, sequence: u64) {
+        if sequence != 0 {
+            self.sequence = Some(sequence);
+        }
+        if self.history.len() == SNAPSHOT_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(TimedPose {
+            received_at: now,
+            transform,
+            wheels: wheels.clone(),
+        });
+        if let Some(received_at) = self.received_at {
+            let dt = now - received_at;
+            // several snapshots in one frame keep the previous velocity estimate
+            if dt > 0. {
+                self.linvel = (transform.translation - self.transform.translation) / dt;
+                let turn = transform.rotation * self.transform.rotation.inverse();
+                // the shorter way around, both signs encode the same rotation
+                let turn = if turn.w < 0. { -turn } else { turn };
+                self.angvel = turn.to_scaled_axis() / dt;
+            }
+        }
+        self.received_at = Some(now);
+        self.transform = transform;
+        self.wheels = wheels;
+    }
+
+    /// Pose `seconds` after the snapshot along the last known velocity.
+    fn extrapolate(&self, seconds: f32) -> Transform {
+        Transform {
+            translation: self.transform.translation + self.linvel * seconds,
+            rotation: Quat::from_scaled_axis(self.angvel * seconds) * self.transform.rotation,
+            scale: self.transform.scale,
+        }
+    }
+
+    /// Car and world space wheel poses at `time`, between the two snapshots around it.
+    /// Past the newest one the car is extrapolated for at most `max_extrapolation`
+    /// seconds, before the oldest it stays there. Lost snapshots widen the gap.
+    fn sample(&self, time: f32, max_extrapolation: f32) -> Option<(Transform, Vec<Transform>)> {
+        let newest = self.history.back()?;
+        if time >= newest.received_at {
+            let age = (time - newest.received_at).min(max_extrapolation);
+            let transform = self.extrapolate(age);
+            let delta = transform.compute_affine() * self.transform.compute_affine().inverse();
+            let wheels = self
+                .wheels
+                .iter()
+                .map(|wheel| Transform::from_matrix((delta * wheel.compute_affine()).into()))
+                .collect();
+            return Some((transform, wheels));
+        }
+        let Some(after) = self.history.iter().position(|pose| pose.received_at > time) else {
+            return Some((newest.transform, newest.wheels.clone()));
+        };
+        let b = &self.history[after];
+        let Some(a) = after.checked_sub(1).map(|before| &self.history[before]) else {
+            return Some((b.transform, b.wheels.clone()));
+        };
+        let s = (time - a.received_at) / (b.received_at - a.received_at);
+        let lerp = |a: &Transform, b: &Transform| Transform {
+            translation: a.translation.lerp(b.translation, s),
+            rotation: a.rotation.slerp(b.rotation, s),
+            scale: a.scale,
+        };
+        let wheels = a
+            .wheels
+            .iter()
+            .zip(&b.wheels)
+            .map(|(a, b)| lerp(a, b))
+            .collect();
+        Some((lerp(&a.transform, &b.transform), wheels))
+    }
+}
+
+/// Most pending inputs kept, older ones are dropped when the server stops acking.
+const PREDICTION_PENDING: usize = 240;
+
+/// Own car inputs the server has not applied yet, replayed on the newest snapshot.
+#[derive(Debug, Default, Component)]
+struct Prediction {
+    /// Input sequence, input and the seconds it was held.
+    pending: VecDeque<(u64, PlayerInput, f32)>,
+    /// Snapshot the shown pose was predicted from.
+    base_sequence: Option<u64>,
+    /// Pose shown last frame.
+    shown: Option<Transform>,
+    /// Shown minus predicted pose, blended out over `PredictionConfig::correction`.
+    error_translation: Vec3,
+    error_rotation: Quat,
+}
+
 #[derive(Debug)]
 struct PlayerInfo {
     client_entity: Entity,
@@ -50,6 +203,42 @@ struct ClientLobby {
     players: HashMap<u64, PlayerInfo>,
 }
 
+/// Seconds a secure connect token stays valid for connecting.
+const CONNECT_TOKEN_EXPIRE_SECONDS: u64 = 300;
+/// Seconds without packets before a secure connection times out.
+const CONNECT_TOKEN_TIMEOUT_SECONDS: i32 = 15;
+
+/// Seconds between reconnect attempts after the connection dropped.
+const RECONNECT_INTERVAL_SECONDS: f32 = 2.;
+
+/// `CLIENT_ID`, or an id generated once and cached in `APP_CLIENT_ID_FILE`
+/// (`client_id.txt` in the working directory), so the server recognizes a reconnect.
+/// Clients started from the same directory need their own `CLIENT_ID`.
+fn stable_client_id(current_time: Duration) -> u64 {
+    if let Some(id) = std::env::var("CLIENT_ID")
+        .ok()
+        .and_then(|id| id.parse().ok())
+    {
+        return id;
+    }
+    let path = std::env::var("APP_CLIENT_ID_FILE").unwrap_or_else(|_| "client_id.txt".to_string());
+    if let Some(id) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|id| id.trim().parse().ok())
+    {
+        return id;
+    }
+    let id = current_time.as_millis() as u64;
+    if let Err(e) = std::fs::write(&path, id.to_string()) {
+        println!("Could not cache client id in {}: {}", path, e);
+    }
+    id
+}
+
+/// Connects to `APP_SERVER`. With `NETCODE_PRIVATE_KEY` set the client signs its
+/// own connect token with the shared key, which encrypts the traffic but
+/// authenticates nobody: anyone with the key can claim any client id, so it is
+/// meant for a trusted LAN only.
 fn start_renet_client() -> (RenetClient, NetcodeClientTransport) {
     let client = RenetClient::new(connection_config());
     let addr = match std::env::var("APP_SERVER") {
@@ -60,12 +249,29 @@ fn start_renet_client() -> (RenetClient, NetcodeClientTransport) {
     let server_addr = addr.parse().unwrap();
     let socket = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket addr");
     let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let client_id = current_time.as_millis() as u64;
-    let authentication = ClientAuthentication::Unsecure {
-        protocol_id: SERVER_PROTOCOL_ID,
-        client_id,
-        server_addr,
-        user_data: None,
+    let client_id = stable_client_id(current_time);
+    let user_data = ConnectMode::from_env().to_user_data();
+    let authentication = match netcode_private_key() {
+        // shared secret mode, the client signs its own token, no token service
+        Some(private_key) => ClientAuthentication::Secure {
+            connect_token: ConnectToken::generate(
+                current_time,
+                SERVER_PROTOCOL_ID,
+                CONNECT_TOKEN_EXPIRE_SECONDS,
+                client_id,
+                CONNECT_TOKEN_TIMEOUT_SECONDS,
+                vec![server_addr],
+                Some(&user_data),
+                &private_key,
+            )
+            .expect("Could not generate connect token"),
+        },
+        None => ClientAuthentication::Unsecure {
+            protocol_id: SERVER_PROTOCOL_ID,
+            client_id,
+            server_addr,
+            user_data: Some(user_data),
+        },
     };
 
     let transport = NetcodeClientTransport::new(current_time, authentication, socket)
@@ -96,9 +302,27 @@ pub fn main() {
     ));
     app.insert_resource(ClientLobby::default());
     app.insert_resource(NetworkMapping::default());
+    app.insert_resource(NetworkMode::from_env());
+    app.insert_resource(ConnectMode::from_env());
+    app.insert_resource(LockstepClock::default());
+    // cars follow the server and prediction is the kinematic `PredictionConfig::step`,
+    // nothing simulates with these, the server's only matter for the mismatch warning
+    app.insert_resource(PhysicsParams::default());
+    app.insert_resource(Weather::default());
+    app.insert_resource(TrackRecord::default());
+    app.insert_resource(CurrentMatch::default());
+    app.insert_resource(Standings::default());
+    app.insert_resource(ServerRejection::default());
+    app.init_resource::<LevelConfig>();
+    app.insert_resource(InterpolationConfig::from_env());
+    app.insert_resource(PredictionConfig::from_env());
+    #[cfg(feature = "telemetry")]
+    app.insert_resource(TelemetryView::default())
+        .add_systems(Update, telemetry_gizmos_system);
 
     let (client, transport) = start_renet_client();
     app.insert_resource(client);
+    app.insert_resource(LocalClientId(transport.client_id()));
     app.insert_resource(transport);
     app.add_systems(
         Startup,
@@ -106,15 +330,47 @@ pub fn main() {
             setup_level,
             bevy_garage_car::car_start_system,
             track_start_system,
+            standings_hud_start_system,
         ),
     );
 
     app.insert_resource(PlayerInput::default());
-    app.add_systems(Update, update_visulizer_system);
+    app.insert_resource(ActiveGamepad::default());
+    app.add_systems(
+        Update,
+        (
+            update_visulizer_system,
+            bevy_garage_car::show_rays_toggle_system,
+            bevy_garage_car::car_assets_error_system,
+            input_display_system,
+            weather_display_system,
+            client_reconnect_system,
+            follow_camera_system,
+            gamepad_connection_system,
+            standings_hud_system,
+        ),
+    );
 
     app.add_systems(
         Update,
-        ((client_sync_players, client_send_input, player_input).run_if(client_connected),),
+        ((
+            // messages wait in the channel until the car scenes are loaded
+            client_sync_players.run_if(bevy_garage_car::car_assets_ready),
+            extrapolate_cars_system.after(client_sync_players),
+            predict_player_system
+                .after(client_sync_players)
+                .after(client_send_input),
+            interpolate_entities.after(client_sync_players),
+            client_send_input.run_if(not(spectating)),
+            client_request_resync,
+            client_restart_race,
+            client_reset_records,
+            client_reset_to_track.run_if(not(spectating)),
+            client_send_name,
+            player_input,
+            gamepad_input_system.after(player_input),
+        )
+            .run_if(client_connected),),
     );
 
     app.run();
@@ -136,6 +392,226 @@ fn update_visulizer_system(
     }
 }
 
+/// F3 toggles an overlay with the input this client sends, which is all the server acts on,
+/// so a car ignoring it points at the network or the server rather than the keyboard.
+fn input_display_system(
+    mut egui_contexts: EguiContexts,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player_input: Res<PlayerInput>,
+    mut show: Local<bool>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        *show = !*show;
+    }
+    if !*show {
+        return;
+    }
+    let (gas, brake) = player_input.pedals();
+    let steering = player_input.steering();
+    egui::Area::new(egui::Id::new("input_display"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10., -10.))
+        .show(egui_contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label("sent input");
+                ui.add(egui::ProgressBar::new(gas).text("gas"));
+                ui.add(egui::ProgressBar::new(brake).text("brake"));
+                ui.label(format!("steering {:+.2}", steering));
+            });
+        });
+}
+
+/// All-time best lap as last sent by the server.
+#[derive(Debug, Default, Resource)]
+struct TrackRecord(Option<LapRecord>);
+
+/// Match the server last described, `None` until its first `MatchInfo`.
+#[derive(Debug, Default, Resource)]
+struct CurrentMatch(Option<MatchInfo>);
+
+/// Race order as last sent by the server, `(client id, laps, meters)` leader first.
+#[derive(Debug, Default, Resource)]
+struct Standings(Vec<(u64, u32, f32)>);
+
+/// Why the server turned this client away, it stays disconnected then.
+#[derive(Debug, Default, Resource)]
+struct ServerRejection(Option<String>);
+
+/// Connects again with the same client id after the connection dropped,
+/// the server hands the car back within `RECONNECT_GRACE`.
+fn client_reconnect_system(
+    mut cmd: Commands,
+    client: Res<RenetClient>,
+    time: Res<Time>,
+    rejection: Res<ServerRejection>,
+    mut elapsed: Local<f32>,
+) {
+    // a full server would turn the client away again
+    if !client.is_disconnected() || rejection.0.is_some() {
+        *elapsed = 0.;
+        return;
+    }
+    *elapsed += time.delta_seconds();
+    if *elapsed < RECONNECT_INTERVAL_SECONDS {
+        return;
+    }
+    *elapsed = 0.;
+    println!(
+        "Disconnected: {:?}, reconnecting.",
+        client.disconnect_reason()
+    );
+    let (client, transport) = start_renet_client();
+    cmd.insert_resource(client);
+    cmd.insert_resource(transport);
+}
+
+fn spectating(mode: Res<ConnectMode>) -> bool {
+    mode.is_spectator()
+}
+
+/// `Tab` and `Shift+Tab` move the camera on to the next and previous car by client id,
+/// a client without a car of its own, e.g. a spectator, watches the first one.
+/// A disconnected car hands the camera over to the next one.
+fn follow_camera_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    local_client_id: Res<LocalClientId>,
+    lobby: Res<ClientLobby>,
+    mut camera_config: ResMut<CameraConfig>,
+    mut followed: Local<Option<u64>>,
+) {
+    let mut ids: Vec<u64> = lobby.players.keys().copied().collect();
+    ids.sort();
+    let own = local_client_id.0.raw();
+    let mut current = match *followed {
+        Some(id) if lobby.players.contains_key(&id) => Some(id),
+        // a race restart empties the lobby for a moment, keep waiting for the car
+        Some(gone) if !ids.is_empty() => {
+            let next = ids.iter().copied().find(|id| *id > gone).unwrap_or(ids[0]);
+            println!("Player {} left, watching player {}.", gone, next);
+            Some(next)
+        }
+        _ => None,
+    };
+    if current.is_none() {
+        current = match lobby.players.contains_key(&own) {
+            true => Some(own),
+            false => ids.first().copied(),
+        };
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) && !ids.is_empty() {
+        let i = current
+            .and_then(|current| ids.iter().position(|id| *id == current))
+            .unwrap_or(0);
+        let i = match keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            true => (i + ids.len() - 1) % ids.len(),
+            false => (i + 1) % ids.len(),
+        };
+        current = Some(ids[i]);
+        println!("Watching player {}.", ids[i]);
+    }
+    if !ids.is_empty() {
+        *followed = current;
+    }
+    // the own car is what the camera follows anyway
+    let follow_target = current
+        .filter(|id| *id != own)
+        .and_then(|id| lobby.players.get(&id))
+        .map(|info| info.client_entity);
+    if camera_config.follow_target != follow_target {
+        camera_config.follow_target = follow_target;
+    }
+}
+
+/// Match, track condition and track record as last sent by the server.
+fn weather_display_system(
+    mut egui_contexts: EguiContexts,
+    weather: Res<Weather>,
+    track_record: Res<TrackRecord>,
+    current_match: Res<CurrentMatch>,
+    rejection: Res<ServerRejection>,
+) {
+    egui::Area::new(egui::Id::new("weather_display"))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., 10.))
+        .show(egui_contexts.ctx_mut(), |ui| {
+            if let Some(reason) = &rejection.0 {
+                ui.colored_label(egui::Color32::RED, format!("rejected: {}", reason));
+            }
+            if let Some(info) = &current_match.0 {
+                let laps = match info.laps {
+                    Some(laps) => format!("{} laps", laps),
+                    None => "practice".to_string(),
+                };
+                ui.label(format!("{}, {}", info.track_name, laps));
+                if let Some(RaceState::Finished { winner }) = info.race_state {
+                    ui.label(format!("race won by player {}", winner));
+                }
+            }
+            ui.label(format!(
+                "{} track, grip {:.0}%",
+                weather.name(),
+                weather.grip() * 100.
+            ));
+            if let Some(record) = &track_record.0 {
+                ui.label(format!(
+                    "track record {:.3}s by {}",
+                    record.time, record.name
+                ));
+            }
+        });
+}
+
+#[derive(Component)]
+struct StandingsText;
+
+/// Race order in the top left corner, plain UI text in the dash font.
+fn standings_hud_start_system(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    let medium: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    cmd.spawn(TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        },
+        text: Text {
+            sections: vec![TextSection {
+                value: "".to_string(),
+                style: TextStyle {
+                    font: medium,
+                    font_size: 16.0,
+                    color: css::SALMON.into(),
+                },
+            }],
+            ..default()
+        },
+        ..default()
+    })
+    .insert(StandingsText);
+}
+
+/// One line per car from the last `Standings`, the own car marked with `>`.
+fn standings_hud_system(
+    standings: Res<Standings>,
+    local_client_id: Res<LocalClientId>,
+    mut texts: Query<&mut Text, With<StandingsText>>,
+) {
+    if !standings.is_changed() {
+        return;
+    }
+    let own = local_client_id.0.raw();
+    let lines: Vec<String> = standings
+        .0
+        .iter()
+        .enumerate()
+        .map(|(i, (id, lap, meters))| {
+            let marker = if *id == own { ">" } else { " " };
+            format!("{}P{} {} lap {} {:.0}m", marker, i + 1, id, lap, meters)
+        })
+        .collect();
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
 fn player_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut player_input: ResMut<PlayerInput>) {
     player_input.left = keyboard_input.pressed(KeyCode::ArrowLeft);
     player_input.right = keyboard_input.pressed(KeyCode::ArrowRight);
@@ -143,22 +619,249 @@ fn player_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut player_input: Res
     player_input.brake = keyboard_input.pressed(KeyCode::ArrowDown);
 }
 
-fn client_send_input(player_input: Res<PlayerInput>, mut client: ResMut<RenetClient>) {
-    let input_message = bincode::serialize(&*player_input).unwrap();
-    client.send_message(ClientChannel::Input, input_message);
+/// Gamepad driving, `None` while no pad is connected.
+#[derive(Debug, Default, Resource)]
+struct ActiveGamepad(Option<Gamepad>);
+
+/// Drives with the first gamepad connected, moving on to another one when it's unplugged.
+fn gamepad_connection_system(
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    gamepads: Res<Gamepads>,
+    mut active: ResMut<ActiveGamepad>,
+) {
+    for event in connection_events.read() {
+        if event.connected() {
+            println!("Gamepad {} connected.", event.gamepad.id);
+        } else {
+            println!("Gamepad {} disconnected.", event.gamepad.id);
+        }
+    }
+    if active.0.is_none_or(|gamepad| !gamepads.contains(gamepad)) {
+        active.0 = gamepads.iter().next();
+    }
+}
+
+/// Left stick steers, right trigger is gas and left trigger brake, written into
+/// the analog fields so the arrow keys take over while the pad is idle or absent.
+fn gamepad_input_system(
+    active: Res<ActiveGamepad>,
+    axes: Res<Axis<GamepadAxis>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    mut player_input: ResMut<PlayerInput>,
+) {
+    let Some(gamepad) = active.0 else {
+        player_input.steering = 0.;
+        player_input.throttle = 0.;
+        return;
+    };
+    let trigger = |button_type| {
+        button_axes
+            .get(GamepadButton::new(gamepad, button_type))
+            .unwrap_or(0.)
+    };
+    player_input.steering = axes
+        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+        .unwrap_or(0.);
+    player_input.throttle =
+        trigger(GamepadButtonType::RightTrigger2) - trigger(GamepadButtonType::LeftTrigger2);
+}
+
+fn client_send_input(
+    player_input: Res<PlayerInput>,
+    mut client: ResMut<RenetClient>,
+    network_mode: Res<NetworkMode>,
+    mut clock: ResMut<LockstepClock>,
+    mut sequence: Local<u64>,
+    time: Res<Time>,
+    mut predictions: Query<&mut Prediction, With<ControlledPlayer>>,
+) {
+    let NetworkMode::Lockstep { input_delay } = *network_mode else {
+        *sequence += 1;
+        for mut prediction in predictions.iter_mut() {
+            if prediction.pending.len() == PREDICTION_PENDING {
+                prediction.pending.pop_front();
+            }
+            prediction
+                .pending
+                .push_back((*sequence, *player_input, time.delta_seconds()));
+        }
+        let input_message = bincode::serialize(&SequencedInput {
+            sequence: *sequence,
+            input: *player_input,
+        })
+        .unwrap();
+        client.send_message(ClientChannel::Input, input_message);
+        return;
+    };
+    let Some(tick) = clock.next else {
+        return;
+    };
+    // do not run ahead of a server which is slower than this client
+    if tick > clock.server_tick + 2 * input_delay {
+        return;
+    }
+    let message = bincode::serialize(&ClientMessages::TickInput {
+        tick,
+        input: *player_input,
+    })
+    .unwrap();
+    client.send_message(ClientChannel::Command, message);
+    clock.next = Some(tick + 1);
+}
+
+fn client_request_resync(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut client: ResMut<RenetClient>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        let message = bincode::serialize(&ClientMessages::RequestResync).unwrap();
+        client.send_message(ClientChannel::Command, message);
+    }
+}
+
+/// F6 asks the server for a race restart, needs the server's `APP_ADMIN_TOKEN`.
+fn client_restart_race(keyboard_input: Res<ButtonInput<KeyCode>>, mut client: ResMut<RenetClient>) {
+    if !keyboard_input.just_pressed(KeyCode::F6) {
+        return;
+    }
+    let Some(token) = admin_token() else {
+        println!("Set APP_ADMIN_TOKEN to restart the race.");
+        return;
+    };
+    let message = bincode::serialize(&ClientMessages::RestartRace { token }).unwrap();
+    client.send_message(ClientChannel::Command, message);
+}
+
+/// R asks the server to put the own car back on the track, e.g. after a flip.
+fn client_reset_to_track(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut client: ResMut<RenetClient>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyR) {
+        let message = bincode::serialize(&ClientMessages::ResetToTrack).unwrap();
+        client.send_message(ClientChannel::Command, message);
+    }
+}
+
+/// F7 asks the server to clear the lap records, needs the server's `APP_ADMIN_TOKEN`.
+fn client_reset_records(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut client: ResMut<RenetClient>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F7) {
+        return;
+    }
+    let Some(token) = admin_token() else {
+        println!("Set APP_ADMIN_TOKEN to reset the records.");
+        return;
+    };
+    let message = bincode::serialize(&ClientMessages::ResetRecords { token }).unwrap();
+    client.send_message(ClientChannel::Command, message);
+}
+
+/// Sends `APP_PLAYER_NAME` once after connecting.
+fn client_send_name(mut client: ResMut<RenetClient>, mut sent: Local<bool>) {
+    if *sent {
+        return;
+    }
+    *sent = true;
+    let name = player_name();
+    if name.is_empty() {
+        return;
+    }
+    let message = bincode::serialize(&ClientMessages::SetName { name }).unwrap();
+    client.send_message(ClientChannel::Command, message);
+}
+
+/// Latest sensor readings of the own car.
+#[cfg(feature = "telemetry")]
+#[derive(Default, Resource)]
+struct TelemetryView {
+    sensors: Vec<f32>,
+}
+
+/// Draws the sensor fan of the own car from the last telemetry, rays end at the hits.
+#[cfg(feature = "telemetry")]
+fn telemetry_gizmos_system(
+    telemetry: Res<TelemetryView>,
+    cars: Query<&Transform, With<ControlledPlayer>>,
+    mut gizmos: bevy::prelude::Gizmos,
+    mut sensors: Local<Option<bevy_garage_car::sensor::CarSensors>>,
+) {
+    let Ok(tf) = cars.get_single() else {
+        return;
+    };
+    let sensors = sensors.get_or_insert_with(|| {
+        bevy_garage_car::sensor::CarSensors::new(&bevy_garage_car::CarSpec::default().size)
+    });
+    for ((pos, far_quat), input) in sensors.sensor_config.iter().zip(&telemetry.sensors) {
+        if *input >= 1. {
+            continue;
+        }
+        let origin = tf.translation + tf.rotation.mul_vec3(*pos);
+        let mut dir = tf.rotation.mul_vec3(far_quat.mul_vec3(bevy::math::Vec3::Z));
+        dir.y = 0.;
+        let toi = input * sensors.max_toi;
+        gizmos.line(
+            origin,
+            origin + dir.normalize_or_zero() * toi,
+            bevy::color::Color::srgba(0.3, 0.5, 0.3, 0.5),
+        );
+    }
+}
+
+/// Wheels are bodies of their own, not children of the car.
+fn despawn_car(cmd: &mut Commands, car_wheels: &Query<&CarWheels>, car: Entity) {
+    if let Ok(wheels) = car_wheels.get(car) {
+        for wheel in wheels.entities.iter() {
+            cmd.entity(*wheel).despawn_recursive();
+        }
+    }
+    cmd.entity(car).despawn_recursive();
+}
+
+fn remove_player(
+    cmd: &mut Commands,
+    car_wheels: &Query<&CarWheels>,
+    lobby: &mut ClientLobby,
+    network_mapping: &mut NetworkMapping,
+    id: u64,
+) {
+    if let Some(PlayerInfo {
+        server_entity,
+        client_entity,
+    }) = lobby.players.remove(&id)
+    {
+        despawn_car(cmd, car_wheels, client_entity);
+        network_mapping.0.remove(&server_entity);
+    }
 }
 
 fn client_sync_players(
     mut cmd: Commands,
     mut client: ResMut<RenetClient>,
-    transport: Res<NetcodeClientTransport>,
+    local_client_id: Res<LocalClientId>,
     mut lobby: ResMut<ClientLobby>,
     mut network_mapping: ResMut<NetworkMapping>,
     car_res: Res<bevy_garage_car::CarRes>,
     car_wheels: Query<&CarWheels>,
-    mut wheel_query: Query<&mut Transform, With<Wheel>>,
+    mut wheel_query: Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+    parents: Query<&GlobalTransform>,
+    mut motions: Query<&mut SnapshotMotion>,
+    time: Res<Time>,
+    mut clock: ResMut<LockstepClock>,
+    mut physics_params: ResMut<PhysicsParams>,
+    // grouped to stay within the system parameter limit
+    (mut weather, mut track_record, mut current_match, mut standings, mut rejection): (
+        ResMut<Weather>,
+        ResMut<TrackRecord>,
+        ResMut<CurrentMatch>,
+        ResMut<Standings>,
+        ResMut<ServerRejection>,
+    ),
+    #[cfg(feature = "telemetry")] mut telemetry: ResMut<TelemetryView>,
 ) {
-    let client_id = transport.client_id();
+    let client_id = local_client_id.0;
     while let Some(message) = client.receive_message(ServerChannel::ServerMessages) {
         let server_message = bincode::deserialize(&message).unwrap();
         match server_message {
@@ -167,12 +870,24 @@ fn client_sync_players(
                 position,
                 entity,
             } => {
+                let transform: Transform =
+                    Transform::from_xyz(position[0], position[1], position[2]);
+                if let Some(player_info) = lobby.players.get_mut(&id) {
+                    // duplicate announce, keep the car and follow the server entity
+                    println!("Player {} already exists, updating its car.", id);
+                    if player_info.server_entity != entity {
+                        network_mapping.0.remove(&player_info.server_entity);
+                        network_mapping.0.insert(entity, player_info.client_entity);
+                        player_info.server_entity = entity;
+                    }
+                    cmd.entity(player_info.client_entity)
+                        .insert((transform, SnapshotMotion::default()));
+                    continue;
+                }
                 println!("Player {} connected.", id);
 
                 let is_player = client_id.raw() == id;
 
-                let transform: Transform =
-                    Transform::from_xyz(position[0], position[1], position[2]);
                 let client_entity = spawn_car(
                     &mut cmd,
                     car_res.car_scene.as_ref().unwrap(),
@@ -181,8 +896,10 @@ fn client_sync_players(
                     transform,
                 );
 
+                cmd.entity(client_entity).insert(SnapshotMotion::default());
                 if is_player {
-                    cmd.entity(client_entity).insert(ControlledPlayer);
+                    cmd.entity(client_entity)
+                        .insert((ControlledPlayer, Prediction::default()));
                 }
 
                 let player_info = PlayerInfo {
@@ -194,44 +911,571 @@ fn client_sync_players(
             }
             ServerMessages::PlayerRemove { id } => {
                 println!("Player {} disconnected.", id);
-                if let Some(PlayerInfo {
-                    server_entity,
-                    client_entity,
-                }) = lobby.players.remove(&id)
-                {
-                    cmd.entity(client_entity).despawn();
-                    network_mapping.0.remove(&server_entity);
+                remove_player(&mut cmd, &car_wheels, &mut lobby, &mut network_mapping, id);
+            }
+            ServerMessages::Lobby { ids } => {
+                let gone: Vec<u64> = lobby
+                    .players
+                    .keys()
+                    .filter(|id| !ids.contains(*id))
+                    .copied()
+                    .collect();
+                for id in gone {
+                    println!("Player {} left while disconnected.", id);
+                    remove_player(&mut cmd, &car_wheels, &mut lobby, &mut network_mapping, id);
+                }
+            }
+            ServerMessages::PhysicsConfig { params } => {
+                if *physics_params != params {
+                    println!(
+                        "WARNING: server physics differ, adopting them.\n  local:  {:?}\n  server: {:?}",
+                        *physics_params, params
+                    );
+                    *physics_params = params;
+                }
+            }
+            ServerMessages::RaceRestart => {
+                println!("Race restart.");
+                for (_, player_info) in lobby.players.drain() {
+                    despawn_car(&mut cmd, &car_wheels, player_info.client_entity);
+                }
+                network_mapping.0.clear();
+            }
+            ServerMessages::LockstepStart { tick } => {
+                clock.next = Some(tick);
+                clock.server_tick = clock.server_tick.max(tick);
+            }
+            #[cfg(feature = "telemetry")]
+            ServerMessages::Telemetry { id, sensors, .. } => {
+                if id == client_id.raw() {
+                    telemetry.sensors = sensors;
+                }
+            }
+            #[cfg(not(feature = "telemetry"))]
+            ServerMessages::Telemetry { .. } => {}
+            ServerMessages::Weather {
+                weather: server_weather,
+            } => {
+                println!("Weather: {}.", server_weather.name());
+                *weather = server_weather;
+            }
+            ServerMessages::TrackRecord { record } => {
+                track_record.0 = record;
+            }
+            ServerMessages::Rammed { rammer, victim } => {
+                println!("Player {} rammed {}.", rammer, victim);
+            }
+            ServerMessages::MatchInfo { info } => {
+                if let Some(server_weather) = info.weather {
+                    *weather = server_weather;
                 }
+                current_match.0 = Some(info);
+            }
+            ServerMessages::Standings { entries } => {
+                standings.0 = entries;
+            }
+            ServerMessages::Rejected { reason } => {
+                println!("Rejected by the server: {}", reason);
+                rejection.0 = Some(reason);
+            }
+            ServerMessages::WorldState { snapshot } => {
+                apply_networked_entities(
+                    &mut cmd,
+                    &network_mapping,
+                    &car_wheels,
+                    &mut wheel_query,
+                    &parents,
+                    &mut motions,
+                    time.elapsed_seconds(),
+                    &snapshot,
+                );
             }
         }
     }
 
     while let Some(message) = client.receive_message(ServerChannel::NetworkedEntities) {
         let networked_entities: NetworkedEntities = bincode::deserialize(&message).unwrap();
+        clock.server_tick = clock.server_tick.max(networked_entities.tick);
+        apply_networked_entities(
+            &mut cmd,
+            &network_mapping,
+            &car_wheels,
+            &mut wheel_query,
+            &parents,
+            &mut motions,
+            time.elapsed_seconds(),
+            &networked_entities,
+        );
+    }
+}
 
-        for i in 0..networked_entities.entities.len() {
-            if let Some(entity) = network_mapping.0.get(&networked_entities.entities[i]) {
-                let translation = networked_entities.positions[i].into();
-                let rotation: Quat = Quat::from_array(networked_entities.orientations[i]);
-                let transform = Transform {
-                    translation,
-                    rotation,
-                    ..Default::default()
-                };
-                cmd.entity(*entity).insert(transform);
+fn apply_networked_entities(
+    cmd: &mut Commands,
+    network_mapping: &NetworkMapping,
+    car_wheels: &Query<&CarWheels>,
+    wheel_query: &mut Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+    parents: &Query<&GlobalTransform>,
+    motions: &mut Query<&mut SnapshotMotion>,
+    now: f32,
+    networked_entities: &NetworkedEntities,
+) {
+    let sequence = networked_entities.sequence;
+    for i in 0..networked_entities.entities.len() {
+        if let Some(entity) = network_mapping.0.get(&networked_entities.entities[i]) {
+            if motions
+                .get(*entity)
+                .is_ok_and(|motion| motion.is_stale(sequence))
+            {
+                continue;
+            }
+            let translation = networked_entities.positions[i].into();
+            let rotation: Quat = Quat::from_array(networked_entities.orientations[i]);
+            let transform = Transform {
+                translation,
+                rotation,
+                ..Default::default()
+            };
+            cmd.entity(*entity).insert(transform);
 
-                let translations = networked_entities.wheel_positions[i];
-                let rotations = networked_entities.wheel_orientations[i];
+            let translations = &networked_entities.wheel_positions[i];
+            let rotations = &networked_entities.wheel_orientations[i];
 
-                let car_wheels = car_wheels.get(*entity);
-                if let Ok(car_wheels) = car_wheels {
-                    for (i, e) in car_wheels.entities.iter().enumerate() {
-                        let mut wheel_transform = wheel_query.get_mut(*e).unwrap();
-                        wheel_transform.translation = translations[i].into();
-                        wheel_transform.rotation = Quat::from_array(rotations[i]);
-                    }
+            if let Ok(mut motion) = motions.get_mut(*entity) {
+                if let Some(acked) = networked_entities.acked_inputs.get(i) {
+                    motion.acked_input = *acked;
+                }
+                let wheels = translations
+                    .iter()
+                    .zip(rotations)
+                    .map(|(translation, rotation)| Transform {
+                        translation: (*translation).into(),
+                        rotation: Quat::from_array(*rotation),
+                        ..Default::default()
+                    })
+                    .collect();
+                motion.record(transform, wheels, now, sequence);
+            }
+
+            let car_wheels = car_wheels.get(*entity);
+            if let Ok(car_wheels) = car_wheels {
+                let poses = translations.iter().zip(rotations);
+                for (e, (translation, rotation)) in car_wheels.entities.iter().zip(poses) {
+                    let (mut wheel_transform, parent) = wheel_query.get_mut(*e).unwrap();
+                    let world = Transform {
+                        translation: (*translation).into(),
+                        rotation: Quat::from_array(*rotation),
+                        scale: wheel_transform.scale,
+                    };
+                    // wheel poses arrive in world space, a parented wheel needs them local
+                    *wheel_transform = match parent.and_then(|p| parents.get(p.get()).ok()) {
+                        Some(parent) => GlobalTransform::from(world).reparented_to(parent),
+                        None => world,
+                    };
                 }
             }
         }
     }
 }
+
+/// Moves the own car along its last snapshot velocity while no newer snapshot arrived,
+/// for at most `InterpolationConfig::max_extrapolation`. The next snapshot snaps it back.
+fn extrapolate_cars_system(
+    time: Res<Time>,
+    config: Res<InterpolationConfig>,
+    prediction: Res<PredictionConfig>,
+    mut cars: Query<
+        (&mut Transform, &SnapshotMotion, Option<&CarWheels>),
+        (Without<Wheel>, With<ControlledPlayer>),
+    >,
+    mut wheel_query: Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+) {
+    if prediction.enabled {
+        return;
+    }
+    let max = config.max_extrapolation.as_secs_f32();
+    for (mut transform, motion, car_wheels) in cars.iter_mut() {
+        let Some(received_at) = motion.received_at else {
+            continue;
+        };
+        let age = (time.elapsed_seconds() - received_at).min(max);
+        if age <= 0. {
+            continue;
+        }
+        *transform = motion.extrapolate(age);
+        // parented wheels follow the body, world space ones get the same rigid motion
+        let delta = transform.compute_affine() * motion.transform.compute_affine().inverse();
+        let Some(car_wheels) = car_wheels else {
+            continue;
+        };
+        for (e, snapshot) in car_wheels.entities.iter().zip(&motion.wheels) {
+            if let Ok((mut wheel_transform, None)) = wheel_query.get_mut(*e) {
+                let world = Transform::from_matrix((delta * snapshot.compute_affine()).into());
+                wheel_transform.translation = world.translation;
+                wheel_transform.rotation = world.rotation;
+            }
+        }
+    }
+}
+
+/// Shows other players' cars `InterpolationConfig::delay` in the past, between the
+/// snapshots around that time, so uneven or lost snapshots don't make them jitter.
+/// The own car stays on the newest snapshot.
+fn interpolate_entities(
+    time: Res<Time>,
+    config: Res<InterpolationConfig>,
+    mut cars: Query<
+        (&mut Transform, &SnapshotMotion, Option<&CarWheels>),
+        (Without<Wheel>, Without<ControlledPlayer>),
+    >,
+    mut wheel_query: Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+) {
+    let render_time = time.elapsed_seconds() - config.delay.as_secs_f32();
+    let max = config.max_extrapolation.as_secs_f32();
+    for (mut transform, motion, car_wheels) in cars.iter_mut() {
+        let Some((body, wheels)) = motion.sample(render_time, max) else {
+            continue;
+        };
+        *transform = body;
+        let Some(car_wheels) = car_wheels else {
+            continue;
+        };
+        let body_inverse = body.compute_affine().inverse();
+        for (e, world) in car_wheels.entities.iter().zip(&wheels) {
+            let Ok((mut wheel_transform, parent)) = wheel_query.get_mut(*e) else {
+                continue;
+            };
+            // a parented wheel hangs off the car body
+            let pose = match parent {
+                Some(_) => Transform::from_matrix((body_inverse * world.compute_affine()).into()),
+                None => *world,
+            };
+            wheel_transform.translation = pose.translation;
+            wheel_transform.rotation = pose.rotation;
+        }
+    }
+}
+
+/// Shows the own car where its inputs take it before the server confirms them.
+/// Every frame starts from the newest snapshot and replays the inputs it has not
+/// acked yet, the jump a new snapshot causes is blended out instead of snapped.
+fn predict_player_system(
+    time: Res<Time>,
+    config: Res<PredictionConfig>,
+    weather: Res<Weather>,
+    mut cars: Query<
+        (
+            &mut Transform,
+            &SnapshotMotion,
+            &mut Prediction,
+            Option<&CarWheels>,
+        ),
+        (Without<Wheel>, With<ControlledPlayer>),
+    >,
+    mut wheel_query: Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+) {
+    if !config.enabled {
+        return;
+    }
+    for (mut transform, motion, mut prediction, car_wheels) in cars.iter_mut() {
+        if motion.received_at.is_none() {
+            continue;
+        }
+        let acked = motion.acked_input;
+        prediction
+            .pending
+            .retain(|(sequence, ..)| *sequence > acked);
+        let mut predicted = motion.transform;
+        let mut speed = motion.linvel.dot(predicted.rotation * Vec3::Z);
+        for (_, input, dt) in prediction.pending.iter() {
+            config.step(&mut predicted, &mut speed, input, weather.grip(), *dt);
+        }
+        if prediction.base_sequence != motion.sequence {
+            prediction.base_sequence = motion.sequence;
+            if let Some(shown) = prediction.shown {
+                prediction.error_translation = shown.translation - predicted.translation;
+                prediction.error_rotation = shown.rotation * predicted.rotation.inverse();
+            }
+        }
+        let decay = (-time.delta_seconds() / config.correction.max(0.001)).exp();
+        prediction.error_translation *= decay;
+        prediction.error_rotation = Quat::IDENTITY.slerp(prediction.error_rotation, decay);
+        *transform = Transform {
+            translation: predicted.translation + prediction.error_translation,
+            rotation: prediction.error_rotation * predicted.rotation,
+            scale: transform.scale,
+        };
+        prediction.shown = Some(*transform);
+        // parented wheels follow the body, world space ones get the same rigid motion
+        let delta = transform.compute_affine() * motion.transform.compute_affine().inverse();
+        let Some(car_wheels) = car_wheels else {
+            continue;
+        };
+        for (e, snapshot) in car_wheels.entities.iter().zip(&motion.wheels) {
+            if let Ok((mut wheel_transform, None)) = wheel_query.get_mut(*e) {
+                let world = Transform::from_matrix((delta * snapshot.compute_affine()).into());
+                wheel_transform.translation = world.translation;
+                wheel_transform.rotation = world.rotation;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{BuildWorldChildren, World};
+    use bevy_garage_car::{Car, CarRes, WheelSpec};
+    use bevy_renet::renet::{ClientId, RenetServer};
+    use hackaton::{Player, sync::networked_entities};
+
+    const STEERING: f32 = 0.35;
+
+    /// Wheel offsets from the car body, front wheels first, left before right.
+    const WHEEL_OFFSETS: [(Vec3, bool, bool); 4] = [
+        (Vec3::new(0.8, -0.3, 1.4), true, true),
+        (Vec3::new(-0.8, -0.3, 1.4), true, false),
+        (Vec3::new(0.8, -0.3, -1.4), false, true),
+        (Vec3::new(-0.8, -0.3, -1.4), false, false),
+    ];
+
+    fn wheel(front: bool, left: bool) -> Wheel {
+        Wheel::new(&WheelSpec::new(0.35, 0.2), front, left)
+    }
+
+    #[test]
+    fn steered_wheels_match_on_server_and_client() {
+        let car_transform =
+            Transform::from_xyz(12., 0.5, -30.).with_rotation(Quat::from_rotation_y(0.8));
+
+        // server side, wheels are rapier bodies posed in world space
+        let mut server = World::new();
+        let server_wheels: Vec<Entity> = WHEEL_OFFSETS
+            .iter()
+            .map(|(offset, front, left)| {
+                let steering = if *front { STEERING } else { 0. };
+                let pose = Transform::from_translation(car_transform.transform_point(*offset))
+                    .with_rotation(car_transform.rotation * Quat::from_rotation_y(steering));
+                server
+                    .spawn((wheel(*front, *left), GlobalTransform::from(pose)))
+                    .id()
+            })
+            .collect();
+        let server_car = server
+            .spawn((
+                Player { id: 1 },
+                car_transform,
+                CarWheels::new(server_wheels.clone()),
+            ))
+            .id();
+        let snapshot = server.run_system_once(
+            |cars: Query<(Entity, &Transform, &CarWheels), With<Player>>,
+             wheels: Query<&GlobalTransform, With<Wheel>>| {
+                networked_entities(&cars, &wheels)
+            },
+        );
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        let snapshot: NetworkedEntities = bincode::deserialize(&bytes).unwrap();
+
+        // client side, front wheels hang off the car body, rear ones are free
+        let mut client = World::new();
+        let client_car = client
+            .spawn((car_transform, GlobalTransform::from(car_transform)))
+            .id();
+        let client_wheels: Vec<Entity> = WHEEL_OFFSETS
+            .iter()
+            .map(|(_, front, left)| {
+                let mut wheel = client.spawn((wheel(*front, *left), Transform::default()));
+                if *front {
+                    wheel.set_parent(client_car);
+                }
+                wheel.id()
+            })
+            .collect();
+        client
+            .entity_mut(client_car)
+            .insert(CarWheels::new(client_wheels.clone()));
+        let mut mapping = NetworkMapping::default();
+        mapping.0.insert(server_car, client_car);
+        client.insert_resource(mapping);
+        client.run_system_once(
+            move |mut cmd: Commands,
+                  mapping: Res<NetworkMapping>,
+                  car_wheels: Query<&CarWheels>,
+                  mut wheel_query: Query<(&mut Transform, Option<&Parent>), With<Wheel>>,
+                  parents: Query<&GlobalTransform>,
+                  mut motions: Query<&mut SnapshotMotion>| {
+                apply_networked_entities(
+                    &mut cmd,
+                    &mapping,
+                    &car_wheels,
+                    &mut wheel_query,
+                    &parents,
+                    &mut motions,
+                    0.,
+                    &snapshot,
+                );
+            },
+        );
+
+        for (server_wheel, client_wheel) in server_wheels.iter().zip(&client_wheels) {
+            let expected = server.get::<GlobalTransform>(*server_wheel).unwrap();
+            let local = *client.get::<Transform>(*client_wheel).unwrap();
+            let world = match client.get::<Parent>(*client_wheel) {
+                Some(_) => GlobalTransform::from(car_transform).mul_transform(local),
+                None => GlobalTransform::from(local),
+            };
+            assert!(
+                world.translation().distance(expected.translation()) < 1e-4,
+                "wheel at {} instead of {}",
+                world.translation(),
+                expected.translation()
+            );
+            assert!(
+                world.rotation().angle_between(expected.rotation()) < 1e-4,
+                "wheel turned {:?} instead of {:?}",
+                world.rotation(),
+                expected.rotation()
+            );
+        }
+    }
+
+    /// Client world with everything `client_sync_players` reads, car scenes stand in empty.
+    fn client_world(client_id: u64) -> World {
+        let mut world = World::new();
+        let mut client = RenetClient::new(connection_config());
+        client.set_connected();
+        world.insert_resource(client);
+        world.insert_resource(LocalClientId(ClientId::from_raw(client_id)));
+        world.insert_resource(CarRes {
+            car_scene: Some(Handle::default()),
+            wheel_scene: Some(Handle::default()),
+            show_rays: false,
+        });
+        world.insert_resource(Time::<()>::default());
+        world.insert_resource(PhysicsParams::default());
+        world.insert_resource(Weather::default());
+        world.init_resource::<ClientLobby>();
+        world.init_resource::<NetworkMapping>();
+        world.init_resource::<LockstepClock>();
+        world.init_resource::<TrackRecord>();
+        world.init_resource::<CurrentMatch>();
+        world.init_resource::<Standings>();
+        world.init_resource::<ServerRejection>();
+        #[cfg(feature = "telemetry")]
+        world.init_resource::<TelemetryView>();
+        world
+    }
+
+    /// Sends `message` from `server` and lets the client world receive it.
+    fn deliver(
+        server: &mut RenetServer,
+        client_id: u64,
+        world: &mut World,
+        message: &ServerMessages,
+    ) {
+        let client_id = ClientId::from_raw(client_id);
+        let message = bincode::serialize(message).unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+        for packet in server.get_packets_to_send(client_id).unwrap() {
+            world.resource_mut::<RenetClient>().process_packet(&packet);
+        }
+        world.run_system_once(client_sync_players);
+    }
+
+    #[test]
+    fn duplicate_player_create_moves_the_one_car() {
+        let mut server = RenetServer::new(connection_config());
+        server.add_connection(ClientId::from_raw(1));
+        let mut world = client_world(1);
+        let first = Entity::from_raw(40);
+        let second = Entity::from_raw(41);
+
+        for (entity, position) in [(first, [0., 1., 0.]), (second, [5., 1., 5.])] {
+            let create = ServerMessages::PlayerCreate {
+                entity,
+                id: 7,
+                position,
+            };
+            deliver(&mut server, 1, &mut world, &create);
+        }
+
+        let cars: Vec<Transform> = world
+            .query_filtered::<&Transform, With<Car>>()
+            .iter(&world)
+            .copied()
+            .collect();
+        assert_eq!(cars.len(), 1);
+        assert_eq!(cars[0].translation, Vec3::new(5., 1., 5.));
+        assert_eq!(world.resource::<ClientLobby>().players.len(), 1);
+        let mapping = &world.resource::<NetworkMapping>().0;
+        assert!(mapping.contains_key(&second));
+        assert!(!mapping.contains_key(&first));
+    }
+
+    #[test]
+    fn lobby_drops_cars_of_players_gone_meanwhile() {
+        let mut server = RenetServer::new(connection_config());
+        server.add_connection(ClientId::from_raw(1));
+        let mut world = client_world(1);
+        for (id, raw) in [(1, 40), (7, 41)] {
+            let create = ServerMessages::PlayerCreate {
+                entity: Entity::from_raw(raw),
+                id,
+                position: [0., 1., 0.],
+            };
+            deliver(&mut server, 1, &mut world, &create);
+        }
+        assert_eq!(
+            world.query_filtered::<(), With<Car>>().iter(&world).count(),
+            2
+        );
+        let wheels = world
+            .query_filtered::<(), With<Wheel>>()
+            .iter(&world)
+            .count();
+        assert_eq!(wheels, 8);
+
+        deliver(
+            &mut server,
+            1,
+            &mut world,
+            &ServerMessages::Lobby { ids: vec![1] },
+        );
+
+        let lobby = &world.resource::<ClientLobby>().players;
+        assert_eq!(lobby.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            world.query_filtered::<(), With<Car>>().iter(&world).count(),
+            1
+        );
+        assert_eq!(
+            world
+                .query_filtered::<(), With<Wheel>>()
+                .iter(&world)
+                .count(),
+            4
+        );
+        let mapping = &world.resource::<NetworkMapping>().0;
+        assert!(!mapping.contains_key(&Entity::from_raw(41)));
+    }
+
+    #[test]
+    fn server_physics_are_kept_for_the_mismatch_warning() {
+        let mut server = RenetServer::new(connection_config());
+        server.add_connection(ClientId::from_raw(1));
+        let mut world = client_world(1);
+        let params = PhysicsParams {
+            fixed: Some(1. / 120.),
+            ..PhysicsParams::fast()
+        };
+        assert_ne!(params, PhysicsParams::default());
+
+        let config = ServerMessages::PhysicsConfig {
+            params: params.clone(),
+        };
+        deliver(&mut server, 1, &mut world, &config);
+
+        assert_eq!(*world.resource::<PhysicsParams>(), params);
+    }
+}
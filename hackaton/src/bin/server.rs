@@ -1,34 +1,60 @@
 use std::{
+    collections::VecDeque,
     net::UdpSocket,
-    num::NonZeroUsize,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use bevy::{
-    app::{App, Startup, Update}, asset::{AssetServer, Handle}, diagnostic::LogDiagnosticsPlugin, math::Vec3, prelude::{
-        Camera3dBundle, Commands, Entity, EventReader, EventWriter, IntoSystemConfigs, ParamSet, Query, Res, ResMut, Transform, With
-    }, scene::Scene, DefaultPlugins
+    DefaultPlugins,
+    app::{App, First, Last, Startup, Update},
+    asset::{AssetServer, Handle},
+    diagnostic::LogDiagnosticsPlugin,
+    math::Vec3,
+    prelude::{
+        Added, ButtonInput, Camera3dBundle, Commands, Component, DespawnRecursiveExt,
+        DetectChanges, DetectChangesMut, Entity, Event, EventReader, EventWriter, GlobalTransform,
+        Has, IntoSystemConfigs, KeyCode, Local, Query, Res, ResMut, Resource, Transform, With,
+    },
+    scene::Scene,
+    time::{Time, Timer, TimerMode},
+    utils::{HashMap, HashSet},
 };
 use bevy_garage_camera::CarCameraPlugin;
-use bevy_garage_car::{Car, CarRes, CarWheels, Wheel, esp_system, spawn_car};
+use bevy_garage_car::{
+    CAR_TRAINING_GROUP, Car, CarRes, CarSet, CarWheels, PhysicsParams, SettleConfig,
+    SuspensionConfig, Weather, Wheel, car_assets_error_system, car_assets_ready, car_settle_system,
+    configure_car_sets, esp_system, physics_catch_up_system, physics_params_system, spawn_car,
+    suspension_config_system, weather_system,
+};
 use bevy_garage_track::{
+    AiDriver, CarTrack, LapEvent, LapState, RacePosition, ResetCarOnTrackEvent,
     SpawnCarOnTrackEvent, TrackConfig, TrackPlugin, spawn_car_on_track, track_start_system,
 };
 use bevy_rapier3d::{
-    plugin::{NoUserData, RapierConfiguration, RapierContext, RapierPhysicsPlugin, TimestepMode},
+    dynamics::Velocity,
+    geometry::CollisionGroups,
+    pipeline::ContactForceEvent,
+    plugin::{NoUserData, RapierConfiguration, RapierPhysicsPlugin},
     render::RapierDebugRenderPlugin,
 };
 use bevy_renet::{
     RenetServerPlugin,
     renet::{
-        RenetServer, ServerEvent,
+        ClientId, RenetServer, ServerEvent,
         transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
     },
     transport::NetcodeServerPlugin,
 };
 use hackaton::{
-    ClientChannel, NetworkedEntities, Player, PlayerInput, SERVER_PROTOCOL_ID, ServerChannel,
-    ServerLobby, ServerMessages, connection_config, shared_systems::setup_level,
+    AckedInput, BotConfig, ClientChannel, ClientMessages, ConnectMode, LapRecord, Leaderboard,
+    LockstepBuffer, MAX_INPUTS_PER_TICK, MatchInfo, NetworkMode, PLAYER_NAME_MAX, Penalized,
+    Player, PlayerInput, RECONNECT_GRACE, REJECT_GRACE, RESET_COOLDOWN, RaceState, RamPenalty,
+    SERVER_PROTOCOL_ID, SequencedInput, ServerChannel, ServerLobby, ServerMessages, ServerTick,
+    SnapshotHistory, WeatherSchedule, bot_id, connection_config, generate_netcode_key, max_clients,
+    metrics::{MetricsEndpoint, TickMetrics, tick_end_system, tick_start_system},
+    netcode_private_key,
+    shared_systems::{LevelConfig, setup_level},
+    sync::{networked_entities, sync_message},
 };
 
 pub fn start_server() -> (RenetServer, NetcodeServerTransport) {
@@ -46,12 +72,20 @@ pub fn start_server() -> (RenetServer, NetcodeServerTransport) {
 
     let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
+    let authentication = match netcode_private_key() {
+        Some(private_key) => ServerAuthentication::Secure { private_key },
+        None => {
+            println!("NETCODE_PRIVATE_KEY unset, accepting unsecure connections");
+            ServerAuthentication::Unsecure
+        }
+    };
     let server_configuration = ServerConfig {
         current_time,
-        max_clients: 12,
+        // one spare slot, a client past the limit connects long enough to hear why it's dropped
+        max_clients: max_clients() + 1,
         protocol_id: SERVER_PROTOCOL_ID,
         public_addresses: vec![public_addr],
-        authentication: ServerAuthentication::Unsecure,
+        authentication,
     };
 
     let transport = NetcodeServerTransport::new(server_configuration, socket)
@@ -61,6 +95,10 @@ pub fn start_server() -> (RenetServer, NetcodeServerTransport) {
 }
 
 pub fn main() {
+    if std::env::args().any(|arg| arg == "--generate-key") {
+        println!("NETCODE_PRIVATE_KEY={}", generate_netcode_key());
+        return;
+    }
     let mut app = App::new();
     app.insert_resource(bevy_garage_car::CarRes {
         show_rays: true,
@@ -83,12 +121,11 @@ pub fn main() {
         CarCameraPlugin,
     ));
 
+    let network_mode = NetworkMode::from_env();
+    println!("Network mode {network_mode:?}");
+    let physics_params = PhysicsParams::from_env().with_fixed_from_env();
     app.insert_resource(RapierConfiguration {
-        timestep_mode: TimestepMode::Variable {
-            max_dt: 1. / 60.,
-            time_scale: 1.,
-            substeps: 10,
-        },
+        timestep_mode: network_mode.timestep_mode(&physics_params),
         gravity: Vec3::new(0., -9.8, 0.),
         physics_pipeline_active: true,
         query_pipeline_active: true,
@@ -96,8 +133,45 @@ pub fn main() {
         force_update_from_transform_changes: true,
     });
 
+    // a tick slower than max_dt turns into slow motion
+    app.insert_resource(TickMetrics::from_env(Duration::from_secs_f32(
+        physics_params.max_dt,
+    )));
+    if let Some(endpoint) = MetricsEndpoint::from_env() {
+        app.insert_resource(endpoint);
+    }
+    app.add_systems(First, tick_start_system);
+    app.add_systems(Last, tick_end_system);
+    app.insert_resource(physics_params);
+    configure_car_sets(&mut app);
     app.insert_resource(ServerLobby::default());
+    app.insert_resource(network_mode);
+    app.insert_resource(LockstepBuffer::default());
+    app.insert_resource(SnapshotHistory::default());
+    app.insert_resource(RamPenalty::from_env());
+    app.insert_resource(BotConfig::from_env());
+    let weather_schedule = WeatherSchedule::from_env();
+    let track_config = TrackConfig::from_env();
+    app.insert_resource(MatchInfo::new(
+        track_name(&track_config),
+        MatchInfo::laps_from_env(),
+        weather_schedule.first(),
+    ));
+    app.insert_resource(weather_schedule.first());
+    app.insert_resource(weather_schedule);
+    app.insert_resource(Leaderboard::load(Leaderboard::path_from_env()));
+    app.insert_resource(track_config);
+    app.init_resource::<PlayerNames>();
+    app.add_event::<ResetRecordsEvent>();
+    app.init_resource::<SettleConfig>();
+    app.init_resource::<LevelConfig>();
+    app.init_resource::<ServerTick>();
+    app.init_resource::<PendingConnects>();
+    app.init_resource::<PendingBaselines>();
+    app.init_resource::<SuspensionConfig>();
     app.add_event::<SpawnCarOnTrackEvent>();
+    app.add_event::<RestartRaceEvent>();
+    app.insert_resource(AdminToken(admin_token()));
 
     let (server, transport) = start_server();
     app.insert_resource(server).insert_resource(transport);
@@ -107,25 +181,72 @@ pub fn main() {
     app.add_systems(
         Update,
         (
-            move_players_system,
-            esp_system.after(move_players_system),
+            move_players_system.in_set(CarSet::Input),
+            ram_penalty_system
+                .in_set(CarSet::Input)
+                .after(move_players_system),
+            car_settle_system
+                .after(CarSet::NeuralNetwork)
+                .before(CarSet::Esp),
+            esp_system.in_set(CarSet::Esp),
+            ram_groups_system,
+            ram_detection_system,
             server_update_system,
+            server_input_system.after(server_update_system),
             server_network_sync,
-            spawn_car_system,
+            server_command_system.after(server_update_system),
+            snapshot_history_system.after(server_network_sync),
+            spawn_car_system.run_if(car_assets_ready),
+            car_assets_error_system,
             update_visulizer_system,
+            bevy_garage_car::show_rays_toggle_system,
+            physics_params_system,
+            suspension_config_system,
+            restart_race_key_system,
+            restart_race_system
+                .after(server_command_system)
+                .run_if(car_assets_ready),
         ),
     );
 
     app.add_systems(
-        Startup,
+        Update,
         (
-            setup_level,
-            rapier_config_start_system,
-            car_start_system,
-            track_start_system,
+            weather_system,
+            physics_catch_up_system,
+            weather_broadcast_system,
+            match_info_system,
+            race_state_system,
+            lap_record_system,
+            reset_records_system.after(server_command_system),
+            standings_broadcast_system,
+            reconnect_timeout_system,
+            reject_timeout_system,
+            bot_spawn_system.run_if(car_assets_ready),
+            server_welcome_system
+                .after(server_update_system)
+                .before(server_input_system)
+                .run_if(car_assets_ready),
+            server_baseline_system.before(server_welcome_system),
+            lockstep_step_system
+                .after(server_input_system)
+                .before(move_players_system)
+                .run_if(lockstep_mode),
         ),
     );
 
+    #[cfg(feature = "telemetry")]
+    app.add_systems(
+        Update,
+        (
+            telemetry_sensors_system,
+            bevy_garage_car::sensor::sensor_system,
+            telemetry_system.after(bevy_garage_car::sensor::sensor_system),
+        ),
+    );
+
+    app.add_systems(Startup, (setup_level, car_start_system, track_start_system));
+
     app.run();
 }
 
@@ -145,76 +266,72 @@ pub fn car_start_system(mut config: ResMut<CarRes>, asset_server: Res<AssetServe
     config.car_scene = Some(car_gl.clone());
 }
 
-fn rapier_config_start_system(mut c: ResMut<RapierContext>) {
-    c.integration_parameters.num_solver_iterations = NonZeroUsize::new(6).unwrap();
-    c.integration_parameters.warmstart_coefficient = 0.;
-    c.integration_parameters.contact_natural_frequency = 50.;
-    c.integration_parameters.contact_damping_ratio = 50.;
-    // c.integration_parameters.num_internal_pgs_iterations = 16;
-    // c.integration_parameters.num_additional_friction_iterations = 8;
-    dbg!(c.integration_parameters);
-}
+/// Connected clients waiting for their welcome, held until the car scenes are loaded.
+#[derive(Debug, Default, Resource)]
+struct PendingConnects(Vec<ClientId>);
+
+/// Clients welcomed last frame, waiting for their `WorldState` baseline.
+#[derive(Debug, Default, Resource)]
+struct PendingBaselines(Vec<ClientId>);
+
 fn server_update_system(
     mut server_events: EventReader<ServerEvent>,
     mut cmd: Commands,
     mut lobby: ResMut<ServerLobby>,
     mut server: ResMut<RenetServer>,
-    players: Query<(Entity, &Player, &Transform)>,
-    #[cfg(feature = "graphics")] car_res: Res<bevy_garage_car::CarRes>,
     #[cfg(feature = "graphics")] mut visualizer: ResMut<
         renet_visualizer::RenetServerVisualizer<200>,
     >,
-    track_config: ResMut<TrackConfig>,
+    transport: Res<NetcodeServerTransport>,
+    // connects wait for server_welcome_system, server events don't
+    mut pending: ResMut<PendingConnects>,
 ) {
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                println!("Player {} connected.", client_id);
                 #[cfg(feature = "graphics")]
                 visualizer.add_client(*client_id);
-
-                for (entity, player, transform) in players.iter() {
-                    let translation: [f32; 3] = transform.translation.into();
-                    let message = bincode::serialize(&ServerMessages::PlayerCreate {
-                        id: player.id,
-                        entity,
-                        position: translation,
+                let max = max_clients();
+                if server.connected_clients() > max {
+                    println!("Client {} rejected, {} clients connected.", client_id, max);
+                    let message = bincode::serialize(&ServerMessages::Rejected {
+                        reason: format!("server full, {} of {} players", max, max),
                     })
                     .unwrap();
                     server.send_message(*client_id, ServerChannel::ServerMessages, message);
+                    lobby
+                        .rejected
+                        .insert(client_id.raw(), Timer::new(REJECT_GRACE, TimerMode::Once));
+                    continue;
                 }
-                let (translation, quat) = track_config.get_transform_by_meter(0.);
-                let transform = Transform::from_translation(translation).with_rotation(quat);
-                let player_entity = spawn_car(
-                    &mut cmd,
-                    car_res.car_scene.as_ref().unwrap(),
-                    car_res.wheel_scene.as_ref().unwrap(),
-                    false,
-                    transform,
-                );
-                cmd.entity(player_entity)
-                    .insert(Player {
-                        id: client_id.raw(),
-                    })
-                    .insert(PlayerInput::default());
-
-                lobby.players.insert(client_id.raw(), player_entity);
-
-                let translation: [f32; 3] = transform.translation.into();
-                let message = bincode::serialize(&ServerMessages::PlayerCreate {
-                    id: client_id.raw(),
-                    entity: player_entity,
-                    position: translation,
-                })
-                .unwrap();
-                server.broadcast_message(ServerChannel::ServerMessages, message);
+                // spectators still get the world messages on the pending path, just no car
+                if ConnectMode::from_user_data(transport.user_data(*client_id)).is_spectator() {
+                    println!("Spectator {} connected.", client_id);
+                    lobby.spectators.insert(client_id.raw());
+                } else {
+                    println!("Player {} connected.", client_id);
+                }
+                pending.0.push(*client_id);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 println!("Player {} disconnected: {}", client_id, reason);
                 #[cfg(feature = "graphics")]
                 visualizer.remove_client(*client_id);
+                pending.0.retain(|id| id != client_id);
+                if lobby.rejected.remove(&client_id.raw()).is_some()
+                    || lobby.spectators.remove(&client_id.raw())
+                {
+                    continue;
+                }
                 if let Some(player_entity) = lobby.players.remove(&client_id.raw()) {
-                    cmd.entity(player_entity).despawn();
+                    // parked with idle input, everyone keeps seeing it until the grace runs out
+                    cmd.entity(player_entity)
+                        .insert((PlayerInput::default(), AckedInput::default()));
+                    lobby.disconnected.insert(
+                        client_id.raw(),
+                        (player_entity, Timer::new(RECONNECT_GRACE, TimerMode::Once)),
+                    );
+                    continue;
                 }
 
                 let message = bincode::serialize(&ServerMessages::PlayerRemove {
@@ -225,16 +342,174 @@ fn server_update_system(
             }
         }
     }
+}
 
-    for client_id in server.clients_id() {
+/// Sends every pending client the server settings and the cars on the track, then
+/// gives each player its car, a parked one back or a new one on the start grid.
+/// Run it after `server_update_system`, once the car scenes are loaded.
+fn server_welcome_system(
+    mut cmd: Commands,
+    mut lobby: ResMut<ServerLobby>,
+    mut server: ResMut<RenetServer>,
+    players: Query<(Entity, &Player, &Transform)>,
+    cars: Query<&Transform, With<Car>>,
+    car_res: Res<CarRes>,
+    track_config: Res<TrackConfig>,
+    network_mode: Res<NetworkMode>,
+    mut lockstep: ResMut<LockstepBuffer>,
+    // grouped to stay within the system parameter limit
+    (physics_params, weather, leaderboard): (Res<PhysicsParams>, Res<Weather>, Res<Leaderboard>),
+    (mut pending, mut baselines): (ResMut<PendingConnects>, ResMut<PendingBaselines>),
+) {
+    let mut occupied: Vec<Vec3> = cars.iter().map(|tf| tf.translation).collect();
+    let mut spawned: Vec<(u64, Entity, Vec3)> = vec![];
+    for client_id in pending.0.drain(..) {
+        let message = bincode::serialize(&ServerMessages::PhysicsConfig {
+            params: physics_params.clone(),
+        })
+        .unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+        let message = bincode::serialize(&ServerMessages::Weather { weather: *weather }).unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+        let record = leaderboard.best(&track_name(&track_config)).cloned();
+        let message = bincode::serialize(&ServerMessages::TrackRecord { record }).unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+
+        // cars from earlier frames, the ones spawned this frame are announced below
+        for (entity, player, transform) in players.iter() {
+            announce_player(
+                &mut server,
+                Some(client_id),
+                player.id,
+                entity,
+                transform.translation,
+            );
+        }
+        let ids = players.iter().map(|(_, player, _)| player.id).collect();
+        let message = bincode::serialize(&ServerMessages::Lobby { ids }).unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+        baselines.0.push(client_id);
+        if lobby.spectators.contains(&client_id.raw()) {
+            continue;
+        }
+        let player_entity = match lobby.disconnected.remove(&client_id.raw()) {
+            // the parked car was announced to this client above, the others never lost it
+            Some((player_entity, _)) => {
+                println!("Player {} reconnected.", client_id);
+                player_entity
+            }
+            None => {
+                let (transform, meters) = track_config.get_free_transform_by_meter(0., &occupied);
+                occupied.push(transform.translation);
+                let player_entity = spawn_car(
+                    &mut cmd,
+                    car_res.car_scene.as_ref().unwrap(),
+                    car_res.wheel_scene.as_ref().unwrap(),
+                    false,
+                    transform,
+                );
+                cmd.entity(player_entity)
+                    .insert(Player {
+                        id: client_id.raw(),
+                    })
+                    .insert(PlayerInput::default())
+                    .insert(CarTrack {
+                        start_shift: meters,
+                        ..Default::default()
+                    });
+                spawned.push((client_id.raw(), player_entity, transform.translation));
+                player_entity
+            }
+        };
+
+        lobby.players.insert(client_id.raw(), player_entity);
+
+        if let NetworkMode::Lockstep { input_delay } = *network_mode {
+            let tick = lockstep.join(client_id.raw(), input_delay);
+            let message = bincode::serialize(&ServerMessages::LockstepStart { tick }).unwrap();
+            server.send_message(client_id, ServerChannel::ServerMessages, message);
+        }
+    }
+
+    // after all connects, so every client gets each new car once, itself included
+    for (id, entity, translation) in spawned {
+        if lobby.players.get(&id) == Some(&entity) {
+            announce_player(&mut server, None, id, entity, translation);
+        }
+    }
+}
+
+/// Applies the newest input of every client, older sequences arriving late
+/// over an unreliable input channel are dropped.
+fn server_input_system(
+    mut cmd: Commands,
+    mut server: ResMut<RenetServer>,
+    lobby: Res<ServerLobby>,
+    network_mode: Res<NetworkMode>,
+    mut sequences: Local<HashMap<u64, u64>>,
+    // clients told about once that they send more than `MAX_INPUTS_PER_TICK`
+    mut flooding: Local<HashSet<u64>>,
+) {
+    let clients = server.clients_id();
+    sequences.retain(|id, _| clients.iter().any(|client_id| client_id.raw() == *id));
+    flooding.retain(|id| clients.iter().any(|client_id| client_id.raw() == *id));
+    for client_id in clients {
+        let mut messages = VecDeque::with_capacity(MAX_INPUTS_PER_TICK);
+        let mut dropped = 0;
         while let Some(message) = server.receive_message(client_id, ClientChannel::Input) {
-            let input: PlayerInput = bincode::deserialize(&message).unwrap();
+            if messages.len() == MAX_INPUTS_PER_TICK {
+                messages.pop_front();
+                dropped += 1;
+            }
+            messages.push_back(message);
+        }
+        if dropped > 0 && flooding.insert(client_id.raw()) {
+            println!(
+                "Client {} sends more than {} inputs per tick, dropping the oldest.",
+                client_id, MAX_INPUTS_PER_TICK
+            );
+        }
+        for message in messages {
+            let Ok(SequencedInput { sequence, input }) = bincode::deserialize(&message) else {
+                println!("Invalid input from {}, ignored.", client_id);
+                continue;
+            };
+            if *network_mode != NetworkMode::Authoritative {
+                // lockstep inputs only count with a tick
+                continue;
+            }
+            let last = sequences.entry(client_id.raw()).or_default();
+            if sequence <= *last {
+                continue;
+            }
+            *last = sequence;
             if let Some(player_entity) = lobby.players.get(&client_id.raw()) {
-                cmd.entity(*player_entity).insert(input);
+                cmd.entity(*player_entity)
+                    .insert((input, AckedInput(sequence)));
             }
         }
     }
 }
+/// Sends `PlayerCreate` for one car to a single client, or to everyone connected with `None`.
+fn announce_player(
+    server: &mut RenetServer,
+    to: Option<ClientId>,
+    id: u64,
+    entity: Entity,
+    translation: Vec3,
+) {
+    let message = bincode::serialize(&ServerMessages::PlayerCreate {
+        id,
+        entity,
+        position: translation.into(),
+    })
+    .unwrap();
+    match to {
+        Some(client_id) => server.send_message(client_id, ServerChannel::ServerMessages, message),
+        None => server.broadcast_message(ServerChannel::ServerMessages, message),
+    }
+}
+
 pub fn setup_simple_camera(mut commands: Commands) {
     commands.spawn(Camera3dBundle {
         transform: Transform::from_xyz(-20.5, 30.0, 20.5).looking_at(Vec3::ZERO, Vec3::Y),
@@ -243,42 +518,449 @@ pub fn setup_simple_camera(mut commands: Commands) {
 }
 fn server_network_sync(
     mut server: ResMut<RenetServer>,
-    mut tr_set: ParamSet<(
-        Query<(Entity, &Transform, &CarWheels), With<Player>>,
-        Query<&Transform, With<Wheel>>,
-    )>,
+    lockstep: Res<LockstepBuffer>,
+    cars: Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: Query<&GlobalTransform, With<Wheel>>,
+    acks: Query<&AckedInput>,
+    mut server_tick: ResMut<ServerTick>,
+) {
+    server_tick.0 += 1;
+    let message = sync_message(&cars, &wheels, lockstep.tick, server_tick.0, |entity| {
+        acks.get(entity).map_or(0, |ack| ack.0)
+    });
+    server.broadcast_message(ServerChannel::NetworkedEntities, message);
+}
+
+/// Sends the reliable world baseline to the clients welcomed last frame. Their cars
+/// are spawned and placed by now and every `PlayerCreate` went out before it on the
+/// same ordered channel, so the client maps every entity of the baseline.
+fn server_baseline_system(
+    mut server: ResMut<RenetServer>,
+    mut baselines: ResMut<PendingBaselines>,
+    cars: Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: Query<&GlobalTransform, With<Wheel>>,
+) {
+    if baselines.0.is_empty() {
+        return;
+    }
+    let message = bincode::serialize(&ServerMessages::WorldState {
+        snapshot: networked_entities(&cars, &wheels),
+    })
+    .unwrap();
+    for client_id in baselines.0.drain(..) {
+        if server.is_connected(client_id) {
+            server.send_message(client_id, ServerChannel::ServerMessages, message.clone());
+        }
+    }
+}
+
+/// Handles the command channel and sends the reliable world baseline to clients
+/// which asked for it.
+fn server_command_system(
+    mut server: ResMut<RenetServer>,
+    mut lockstep: ResMut<LockstepBuffer>,
+    mut restart_events: EventWriter<RestartRaceEvent>,
+    mut reset_records_events: EventWriter<ResetRecordsEvent>,
+    mut names: ResMut<PlayerNames>,
+    admin_token: Res<AdminToken>,
+    cars: Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: Query<&GlobalTransform, With<Wheel>>,
+    lobby: Res<ServerLobby>,
+    time: Res<Time>,
+    mut reset_events: EventWriter<ResetCarOnTrackEvent>,
+    mut last_resets: Local<HashMap<u64, f32>>,
+) {
+    let mut resync: Vec<ClientId> = vec![];
+    for client_id in server.clients_id() {
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Command) {
+            match bincode::deserialize(&message) {
+                Ok(ClientMessages::RequestResync) => resync.push(client_id),
+                Ok(ClientMessages::TickInput { tick, input }) => {
+                    lockstep.insert(tick, client_id.raw(), input)
+                }
+                Ok(ClientMessages::RestartRace { token }) => {
+                    if admin_token.0.as_ref() == Some(&token) {
+                        restart_events.send(RestartRaceEvent);
+                    } else {
+                        println!("Unauthorized race restart from {}", client_id);
+                    }
+                }
+                Ok(ClientMessages::ResetRecords { token }) => {
+                    if admin_token.0.as_ref() == Some(&token) {
+                        reset_records_events.send(ResetRecordsEvent);
+                    } else {
+                        println!("Unauthorized records reset from {}", client_id);
+                    }
+                }
+                Ok(ClientMessages::SetName { name }) => {
+                    let name: String = name.trim().chars().take(PLAYER_NAME_MAX).collect();
+                    if !name.is_empty() {
+                        names.0.insert(client_id.raw(), name);
+                    }
+                }
+                Ok(ClientMessages::ResetToTrack) => {
+                    let Some(&entity) = lobby.players.get(&client_id.raw()) else {
+                        continue;
+                    };
+                    let now = time.elapsed_seconds();
+                    let last = last_resets.get(&client_id.raw()).copied();
+                    if last.is_some_and(|last| now - last < RESET_COOLDOWN) {
+                        continue;
+                    }
+                    last_resets.insert(client_id.raw(), now);
+                    reset_events.send(ResetCarOnTrackEvent { entity });
+                }
+                Err(e) => println!("Invalid command from {}: {}", client_id, e),
+            }
+        }
+    }
+    if resync.is_empty() {
+        return;
+    }
+    resync.sort_by_key(|client_id| client_id.raw());
+    resync.dedup();
+
+    let message = bincode::serialize(&ServerMessages::WorldState {
+        snapshot: networked_entities(&cars, &wheels),
+    })
+    .unwrap();
+    for client_id in resync {
+        server.send_message(client_id, ServerChannel::ServerMessages, message.clone());
+    }
+}
+
+#[derive(Debug, Event)]
+struct RestartRaceEvent;
+
+#[derive(Resource)]
+struct AdminToken(Option<String>);
+
+#[derive(Debug, Event)]
+struct ResetRecordsEvent;
+
+/// Names sent by clients, players without one show up by id.
+#[derive(Debug, Default, Resource)]
+struct PlayerNames(HashMap<u64, String>);
+
+impl PlayerNames {
+    fn get(&self, id: u64) -> String {
+        self.0
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("player {}", id))
+    }
+}
+
+/// Leaderboard key, the reversed track keeps its own records.
+fn track_name(track_config: &TrackConfig) -> String {
+    match track_config.reverse {
+        true => format!("{}-reverse", track_config.source.name),
+        false => track_config.source.name.clone(),
+    }
+}
+
+fn broadcast_track_record(server: &mut RenetServer, leaderboard: &Leaderboard, track: &str) {
+    let record = leaderboard.best(track).cloned();
+    let message = bincode::serialize(&ServerMessages::TrackRecord { record }).unwrap();
+    server.broadcast_message(ServerChannel::ServerMessages, message);
+}
+
+fn save_leaderboard(leaderboard: &Leaderboard) {
+    if let Err(e) = leaderboard.save() {
+        println!("Could not save the leaderboard: {}", e);
+    }
+}
+
+/// Adds finished laps of players to the leaderboard and announces new track records.
+fn lap_record_system(
+    mut lap_events: EventReader<LapEvent>,
+    players: Query<&Player>,
+    names: Res<PlayerNames>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut server: ResMut<RenetServer>,
+    track_config: Res<TrackConfig>,
 ) {
-    let mut networked_entities = NetworkedEntities::default();
-    let mut wheels_all: Vec<[Entity; 4]> = vec![];
-    for (entity, transform, wheels) in tr_set.p0().iter() {
-        networked_entities.entities.push(entity);
-        networked_entities
-            .positions
-            .push(transform.translation.into());
-        networked_entities
-            .orientations
-            .push(transform.rotation.into());
-
-        wheels_all.push(wheels.entities);
-    }
-
-    for wheels in wheels_all {
-        networked_entities.wheel_positions.push([
-            tr_set.p1().get(wheels[0]).unwrap().translation.into(),
-            tr_set.p1().get(wheels[1]).unwrap().translation.into(),
-            tr_set.p1().get(wheels[2]).unwrap().translation.into(),
-            tr_set.p1().get(wheels[3]).unwrap().translation.into(),
-        ]);
-        networked_entities.wheel_orientations.push([
-            tr_set.p1().get(wheels[0]).unwrap().rotation.into(),
-            tr_set.p1().get(wheels[1]).unwrap().rotation.into(),
-            tr_set.p1().get(wheels[2]).unwrap().rotation.into(),
-            tr_set.p1().get(wheels[3]).unwrap().rotation.into(),
-        ]);
-    }
-
-    let sync_message = bincode::serialize(&networked_entities).unwrap();
-    server.broadcast_message(ServerChannel::NetworkedEntities, sync_message);
+    let track = track_name(&track_config);
+    let mut changed = false;
+    let best = leaderboard.best(&track).cloned();
+    for event in lap_events.read() {
+        let Ok(player) = players.get(event.entity) else {
+            continue;
+        };
+        let name = names.get(player.id);
+        println!("{} finished lap {} in {:.3}s.", name, event.lap, event.time);
+        changed |= leaderboard.submit(
+            &track,
+            LapRecord {
+                name,
+                time: event.time,
+            },
+        );
+    }
+    if !changed {
+        return;
+    }
+    save_leaderboard(&leaderboard);
+    if leaderboard.best(&track) != best.as_ref() {
+        broadcast_track_record(&mut server, &leaderboard, &track);
+    }
+}
+
+fn reset_records_system(
+    mut reset_events: EventReader<ResetRecordsEvent>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut server: ResMut<RenetServer>,
+    track_config: Res<TrackConfig>,
+) {
+    if reset_events.is_empty() {
+        return;
+    }
+    reset_events.clear();
+    println!("Lap records reset.");
+    let track = track_name(&track_config);
+    leaderboard.reset(&track);
+    save_leaderboard(&leaderboard);
+    broadcast_track_record(&mut server, &leaderboard, &track);
+}
+
+/// F5 on the server window restarts the race.
+fn restart_race_key_system(
+    input: Res<ButtonInput<KeyCode>>,
+    mut restart_events: EventWriter<RestartRaceEvent>,
+) {
+    if input.just_pressed(KeyCode::F5) {
+        restart_events.send(RestartRaceEvent);
+    }
+}
+
+/// Removes the cars of players who didn't reconnect within `RECONNECT_GRACE`.
+fn reconnect_timeout_system(
+    mut cmd: Commands,
+    mut server: ResMut<RenetServer>,
+    mut lobby: ResMut<ServerLobby>,
+    mut cars: Query<&mut CarWheels>,
+    time: Res<Time>,
+) {
+    lobby.disconnected.retain(|id, (entity, timer)| {
+        if !timer.tick(time.delta()).finished() {
+            return true;
+        }
+        println!("Player {} did not reconnect.", id);
+        if let Ok(mut wheels) = cars.get_mut(*entity) {
+            wheels.despawn(&mut cmd);
+        }
+        cmd.entity(*entity).despawn_recursive();
+        let message = bincode::serialize(&ServerMessages::PlayerRemove { id: *id }).unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+        false
+    });
+}
+
+/// Drops rejected clients once their `Rejected` message had time to arrive,
+/// they leave `ServerLobby::rejected` with the disconnect event.
+fn reject_timeout_system(
+    mut server: ResMut<RenetServer>,
+    mut lobby: ResMut<ServerLobby>,
+    time: Res<Time>,
+) {
+    for (id, timer) in lobby.rejected.iter_mut() {
+        if timer.tick(time.delta()).just_finished() {
+            server.disconnect(ClientId::from_raw(*id));
+        }
+    }
+}
+
+/// Respawns every connected player on the start grid with fresh cars.
+fn restart_race_system(
+    mut restart_events: EventReader<RestartRaceEvent>,
+    mut cmd: Commands,
+    mut server: ResMut<RenetServer>,
+    mut lobby: ResMut<ServerLobby>,
+    mut cars: Query<&mut CarWheels, With<Player>>,
+    bots: Query<Entity, With<Bot>>,
+    car_res: Res<CarRes>,
+    track_config: Res<TrackConfig>,
+    schedule: Res<WeatherSchedule>,
+    mut weather: ResMut<Weather>,
+    mut match_info: ResMut<MatchInfo>,
+) {
+    if restart_events.is_empty() {
+        return;
+    }
+    restart_events.clear();
+    println!("Race restart.");
+    let race_state = Some(MatchInfo::start_state(match_info.laps));
+    if match_info.race_state != race_state {
+        match_info.race_state = race_state;
+    }
+    // only an actual change is broadcast by weather_broadcast_system
+    let next = schedule.next(*weather);
+    weather.set_if_neq(next);
+
+    let message = bincode::serialize(&ServerMessages::RaceRestart).unwrap();
+    server.broadcast_message(ServerChannel::ServerMessages, message);
+
+    // cars waiting for a reconnect are gone with the old race, a reconnect spawns a fresh one
+    let parked: Vec<Entity> = lobby
+        .disconnected
+        .drain()
+        .map(|(_, (entity, _))| entity)
+        .collect();
+    // bots line up behind the players, bot_spawn_system brings them back under the same ids
+    for entity in parked.into_iter().chain(bots.iter()) {
+        if let Ok(mut wheels) = cars.get_mut(entity) {
+            wheels.despawn(&mut cmd);
+        }
+        cmd.entity(entity).despawn_recursive();
+    }
+
+    let mut ids: Vec<u64> = lobby.players.keys().copied().collect();
+    ids.sort();
+    let mut occupied: Vec<Vec3> = vec![];
+    for id in ids {
+        let old_entity = lobby.players[&id];
+        if let Ok(mut wheels) = cars.get_mut(old_entity) {
+            wheels.despawn(&mut cmd);
+        }
+        cmd.entity(old_entity).despawn_recursive();
+
+        let (transform, meters) = track_config.get_free_transform_by_meter(0., &occupied);
+        occupied.push(transform.translation);
+        let player_entity = spawn_car(
+            &mut cmd,
+            car_res.car_scene.as_ref().unwrap(),
+            car_res.wheel_scene.as_ref().unwrap(),
+            false,
+            transform,
+        );
+        cmd.entity(player_entity)
+            .insert(Player { id })
+            .insert(PlayerInput::default())
+            .insert(CarTrack {
+                start_shift: meters,
+                ..Default::default()
+            });
+        lobby.players.insert(id, player_entity);
+        announce_player(&mut server, None, id, player_entity, transform.translation);
+    }
+}
+
+/// A server driven car, `Player` for the network but steered by `AiDriver`.
+/// Not the DQN brain, the `nn` crate isn't in the workspace for the server to link.
+#[derive(Debug, Component)]
+struct Bot {
+    index: usize,
+}
+
+/// Keeps `BotConfig::count` bots on the track and announces them like joining players.
+fn bot_spawn_system(
+    mut cmd: Commands,
+    mut server: ResMut<RenetServer>,
+    config: Res<BotConfig>,
+    bots: Query<&Bot>,
+    cars: Query<&Transform, With<Car>>,
+    car_res: Res<CarRes>,
+    track_config: Res<TrackConfig>,
+    mut names: ResMut<PlayerNames>,
+) {
+    if bots.iter().len() >= config.count {
+        return;
+    }
+    let present: HashSet<usize> = bots.iter().map(|bot| bot.index).collect();
+    let mut occupied: Vec<Vec3> = cars.iter().map(|tf| tf.translation).collect();
+    for index in (0..config.count).filter(|index| !present.contains(index)) {
+        let id = bot_id(index);
+        let (transform, meters) = track_config.get_free_transform_by_meter(0., &occupied);
+        occupied.push(transform.translation);
+        let bot_entity = spawn_car(
+            &mut cmd,
+            car_res.car_scene.as_ref().unwrap(),
+            car_res.wheel_scene.as_ref().unwrap(),
+            false,
+            transform,
+        );
+        cmd.entity(bot_entity).insert((
+            Player { id },
+            Bot { index },
+            AiDriver::default(),
+            CarTrack {
+                start_shift: meters,
+                ..Default::default()
+            },
+        ));
+        names.0.insert(id, format!("bot {}", index + 1));
+        announce_player(&mut server, None, id, bot_entity, transform.translation);
+    }
+}
+
+#[cfg(feature = "telemetry")]
+fn telemetry_sensors_system(
+    mut cmd: Commands,
+    cars: Query<(Entity, &bevy_garage_car::CarSpec), Added<Player>>,
+) {
+    for (entity, spec) in cars.iter() {
+        cmd.entity(entity)
+            .insert(bevy_garage_car::sensor::CarSensors::new(&spec.size));
+    }
+}
+
+/// Sends every client the sensor readings of its own car at `TELEMETRY_INTERVAL`.
+#[cfg(feature = "telemetry")]
+fn telemetry_system(
+    mut server: ResMut<RenetServer>,
+    mut elapsed: bevy::prelude::Local<f32>,
+    time: Res<Time>,
+    cars: Query<(&Player, &bevy_garage_car::sensor::CarSensors, &Velocity)>,
+) {
+    *elapsed += time.delta_seconds();
+    if *elapsed < hackaton::TELEMETRY_INTERVAL {
+        return;
+    }
+    *elapsed = 0.;
+    for (player, sensors, velocity) in cars.iter() {
+        let message = bincode::serialize(&ServerMessages::Telemetry {
+            id: player.id,
+            sensors: sensors.sensor_inputs.clone(),
+            speed: velocity.linvel.length(),
+        })
+        .unwrap();
+        server.send_message(
+            ClientId::from_raw(player.id),
+            ServerChannel::ServerMessages,
+            message,
+        );
+    }
+}
+
+fn lockstep_mode(network_mode: Res<NetworkMode>) -> bool {
+    matches!(*network_mode, NetworkMode::Lockstep { .. })
+}
+
+/// Lockstep gate, physics only steps once every player sent input for the current tick.
+fn lockstep_step_system(
+    mut cmd: Commands,
+    lobby: Res<ServerLobby>,
+    mut lockstep: ResMut<LockstepBuffer>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let clients: Vec<u64> = lobby.players.keys().copied().collect();
+    let Some(inputs) = lockstep.pop_ready(&clients) else {
+        rapier_config.physics_pipeline_active = false;
+        return;
+    };
+    for (client_id, input) in inputs {
+        if let Some(player_entity) = lobby.players.get(&client_id) {
+            cmd.entity(*player_entity).insert(input);
+        }
+    }
+    rapier_config.physics_pipeline_active = true;
+}
+
+fn snapshot_history_system(
+    mut history: ResMut<SnapshotHistory>,
+    players: Query<(Entity, &Transform), With<Player>>,
+) {
+    history.push(players.iter().map(|(e, tf)| (e, *tf)).collect());
 }
 
 pub fn spawn_car_start_system(mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>) {
@@ -294,17 +976,19 @@ pub fn spawn_car_system(
     mut cmd: Commands,
     track_config: ResMut<TrackConfig>,
     car_res: ResMut<CarRes>,
+    cars: Query<&Transform, With<Car>>,
 ) {
+    let mut occupied: Vec<Vec3> = cars.iter().map(|tf| tf.translation).collect();
     for spawn_event in events.read() {
         dbg!(spawn_event);
 
-        let (transform, init_meters) = if let Some(init_meters) = spawn_event.position {
-            let (translate, quat) = track_config.get_transform_by_meter(init_meters);
-            let transform = Transform::from_translation(translate).with_rotation(quat);
-            (transform, init_meters)
-        } else {
-            track_config.get_transform_random()
+        let requested_meters = match spawn_event.position {
+            Some(init_meters) => init_meters,
+            None => track_config.get_transform_random().1,
         };
+        let (transform, init_meters) =
+            track_config.get_free_transform_by_meter(requested_meters, &occupied);
+        occupied.push(transform.translation);
 
         spawn_car_on_track(
             &mut cmd,
@@ -318,26 +1002,529 @@ pub fn spawn_car_system(
     }
 }
 
-fn move_players_system(mut query: Query<(&PlayerInput, &mut Car)>) {
+fn weather_broadcast_system(weather: Res<Weather>, mut server: ResMut<RenetServer>) {
+    if !weather.is_changed() {
+        return;
+    }
+    println!("Weather: {}.", weather.name());
+    let message = bincode::serialize(&ServerMessages::Weather { weather: *weather }).unwrap();
+    server.broadcast_message(ServerChannel::ServerMessages, message);
+}
+
+/// Follows the weather into `MatchInfo`, sends it to every new client
+/// and to everyone whenever it changes.
+fn match_info_system(
+    mut server_events: EventReader<ServerEvent>,
+    mut server: ResMut<RenetServer>,
+    weather: Res<Weather>,
+    mut match_info: ResMut<MatchInfo>,
+) {
+    if match_info.weather != Some(*weather) {
+        match_info.weather = Some(*weather);
+    }
+    let message = || {
+        bincode::serialize(&ServerMessages::MatchInfo {
+            info: match_info.clone(),
+        })
+        .unwrap()
+    };
+    if match_info.is_changed() {
+        server_events.clear();
+        server.broadcast_message(ServerChannel::ServerMessages, message());
+        return;
+    }
+    for event in server_events.read() {
+        if let ServerEvent::ClientConnected { client_id } = event {
+            server.send_message(*client_id, ServerChannel::ServerMessages, message());
+        }
+    }
+}
+
+/// Sends everyone the race order at `STANDINGS_INTERVAL`, from the lap and
+/// position `TrackPlugin` keeps on every car.
+fn standings_broadcast_system(
+    mut server: ResMut<RenetServer>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+    cars: Query<(&Player, &CarTrack, &LapState, &RacePosition)>,
+) {
+    *elapsed += time.delta_seconds();
+    if *elapsed < hackaton::STANDINGS_INTERVAL {
+        return;
+    }
+    *elapsed = 0.;
+    let mut standings: Vec<(u32, (u64, u32, f32))> = cars
+        .iter()
+        .map(|(player, track, lap_state, position)| {
+            let meters = track.start_shift + track.total_meters;
+            let meters = if meters.is_finite() { meters } else { 0. };
+            (position.place, (player.id, lap_state.lap, meters))
+        })
+        .collect();
+    standings.sort_by_key(|(place, _)| *place);
+    let message = bincode::serialize(&ServerMessages::Standings {
+        entries: standings.into_iter().map(|(_, entry)| entry).collect(),
+    })
+    .unwrap();
+    server.broadcast_message(ServerChannel::ServerMessages, message);
+}
+
+/// Finishes the race once a player completes the lap target.
+fn race_state_system(
+    mut lap_events: EventReader<LapEvent>,
+    players: Query<&Player>,
+    mut match_info: ResMut<MatchInfo>,
+) {
+    for event in lap_events.read() {
+        let (Some(laps), Some(RaceState::Running)) = (match_info.laps, match_info.race_state)
+        else {
+            continue;
+        };
+        let Ok(player) = players.get(event.entity) else {
+            continue;
+        };
+        if event.lap >= laps as i32 {
+            println!("Player {} won after {} laps.", player.id, laps);
+            match_info.race_state = Some(RaceState::Finished { winner: player.id });
+        }
+    }
+}
+
+fn move_players_system(weather: Res<Weather>, mut query: Query<(&PlayerInput, &mut Car)>) {
     for (input, mut car) in query.iter_mut() {
-        if input.forward {
-            car.gas = 1.;
-        } else {
-            car.gas = 0.;
+        let (mut gas, brake) = input.pedals();
+        if input.analog_throttle().is_none() {
+            // full throttle from a key only spins the wheels on low grip
+            gas *= weather.grip();
+        }
+        car.set_controls(gas, brake, input.steering());
+    }
+}
+
+/// Player bodies only collide with static geometry unless the ram rule is on.
+fn ram_groups_system(
+    penalty: Res<RamPenalty>,
+    mut cars: Query<&mut CollisionGroups, (With<Car>, Added<Player>)>,
+) {
+    if !penalty.enabled {
+        return;
+    }
+    for mut groups in cars.iter_mut() {
+        groups.filters |= CAR_TRAINING_GROUP;
+    }
+}
+
+/// A contact between two players reaching `RamPenalty::min_force` is a ram, it blames
+/// the car which drove harder into the other one along the line between them.
+fn ram_detection_system(
+    mut cmd: Commands,
+    mut contact_forces: EventReader<ContactForceEvent>,
+    mut server: ResMut<RenetServer>,
+    penalty: Res<RamPenalty>,
+    players: Query<(&Player, &Transform, &Velocity, Has<Penalized>)>,
+) {
+    if !penalty.enabled {
+        contact_forces.clear();
+        return;
+    }
+    // contact forces keep coming while the cars touch, a ram is blamed once
+    let mut rammers = HashSet::new();
+    for event in contact_forces.read() {
+        if event.total_force_magnitude < penalty.min_force {
+            continue;
+        }
+        let (a, b) = (&event.collider1, &event.collider2);
+        let (Ok((player_a, tf_a, vel_a, penalized_a)), Ok((player_b, tf_b, vel_b, penalized_b))) =
+            (players.get(*a), players.get(*b))
+        else {
+            continue;
+        };
+        let dir = (tf_b.translation - tf_a.translation).normalize_or_zero();
+        let approach_a = vel_a.linvel.dot(dir);
+        let approach_b = vel_b.linvel.dot(-dir);
+        if approach_a + approach_b < penalty.min_closing_speed {
+            continue;
+        }
+        let (rammer, rammer_id, victim_id, penalized) = match approach_a >= approach_b {
+            true => (*a, player_a.id, player_b.id, penalized_a),
+            false => (*b, player_b.id, player_a.id, penalized_b),
+        };
+        if penalized || !rammers.insert(rammer) {
+            continue;
+        }
+        println!("Player {} rammed {}.", rammer_id, victim_id);
+        cmd.entity(rammer)
+            .insert(Penalized(Timer::new(penalty.duration, TimerMode::Once)));
+        let message = bincode::serialize(&ServerMessages::Rammed {
+            rammer: rammer_id,
+            victim: victim_id,
+        })
+        .unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+    }
+}
+
+fn ram_penalty_system(
+    mut cmd: Commands,
+    time: Res<Time>,
+    penalty: Res<RamPenalty>,
+    mut cars: Query<(Entity, &mut Car, &mut Penalized)>,
+) {
+    for (entity, mut car, mut penalized) in cars.iter_mut() {
+        if penalized.0.tick(time.delta()).finished() {
+            cmd.entity(entity).remove::<Penalized>();
+            continue;
+        }
+        let gas = car.gas * penalty.gas_scale;
+        car.set_gas(gas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{Events, Schedule, World};
+    use bevy_renet::renet::RenetClient;
+
+    fn lockstep_world(clients: &[u64]) -> World {
+        let mut world = World::new();
+        let mut lobby = ServerLobby::default();
+        for client_id in clients {
+            let entity = world.spawn_empty().id();
+            lobby.players.insert(*client_id, entity);
+        }
+        world.insert_resource(lobby);
+        world.insert_resource(LockstepBuffer::default());
+        world.insert_resource(RapierConfiguration::new(1.));
+        world
+    }
+
+    #[test]
+    fn lockstep_steps_a_tick_with_all_inputs() {
+        let mut world = lockstep_world(&[1, 2]);
+        let input = PlayerInput {
+            forward: true,
+            ..Default::default()
+        };
+        {
+            let mut lockstep = world.resource_mut::<LockstepBuffer>();
+            lockstep.insert(0, 1, input);
+            lockstep.insert(0, 2, PlayerInput::default());
+        }
+
+        world.run_system_once(lockstep_step_system);
+
+        assert_eq!(world.resource::<LockstepBuffer>().tick, 1);
+        assert!(
+            world
+                .resource::<RapierConfiguration>()
+                .physics_pipeline_active
+        );
+        let car = world.resource::<ServerLobby>().players[&1];
+        assert!(
+            world
+                .get::<PlayerInput>(car)
+                .is_some_and(|input| input.forward)
+        );
+    }
+
+    #[test]
+    fn lockstep_waits_for_a_missing_input() {
+        let mut world = lockstep_world(&[1, 2]);
+        world
+            .resource_mut::<LockstepBuffer>()
+            .insert(0, 1, PlayerInput::default());
+
+        world.run_system_once(lockstep_step_system);
+
+        assert_eq!(world.resource::<LockstepBuffer>().tick, 0);
+        assert!(
+            !world
+                .resource::<RapierConfiguration>()
+                .physics_pipeline_active
+        );
+    }
+
+    /// World ready to welcome clients on the built-in track, car scenes stand in empty.
+    fn welcome_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(TrackConfig::default());
+        world.run_system_once(bevy_garage_track::track_polyline_start_system);
+        world.insert_resource(RenetServer::new(connection_config()));
+        world.insert_resource(CarRes {
+            car_scene: Some(Handle::default()),
+            wheel_scene: Some(Handle::default()),
+            show_rays: false,
+        });
+        world.insert_resource(ServerLobby::default());
+        world.insert_resource(NetworkMode::default());
+        world.insert_resource(LockstepBuffer::default());
+        world.insert_resource(PhysicsParams::default());
+        world.insert_resource(Weather::default());
+        world.insert_resource(Leaderboard::default());
+        world.init_resource::<PendingConnects>();
+        world.init_resource::<PendingBaselines>();
+        world
+    }
+
+    fn connect(world: &mut World, client_id: u64) -> RenetClient {
+        let client_id = ClientId::from_raw(client_id);
+        world
+            .resource_mut::<RenetServer>()
+            .add_connection(client_id);
+        world.resource_mut::<PendingConnects>().0.push(client_id);
+        let mut client = RenetClient::new(connection_config());
+        client.set_connected();
+        client
+    }
+
+    /// Server messages sent to `client_id` since the last call.
+    fn server_messages(
+        world: &mut World,
+        client: &mut RenetClient,
+        client_id: u64,
+    ) -> Vec<ServerMessages> {
+        let packets = world
+            .resource_mut::<RenetServer>()
+            .get_packets_to_send(ClientId::from_raw(client_id))
+            .unwrap();
+        for packet in packets {
+            client.process_packet(&packet);
         }
-        if input.brake {
-            car.brake = 1.;
-        } else {
-            car.brake = 0.;
+        let mut messages = vec![];
+        while let Some(message) = client.receive_message(ServerChannel::ServerMessages) {
+            messages.push(bincode::deserialize(&message).unwrap());
         }
-        if input.left {
-            car.steering = -1.;
+        messages
+    }
+
+    /// Player ids of the `PlayerCreate`s the server sent `client_id` so far, sorted.
+    fn player_creates(world: &mut World, client: &mut RenetClient, client_id: u64) -> Vec<u64> {
+        let mut ids: Vec<u64> = server_messages(world, client, client_id)
+            .into_iter()
+            .filter_map(|message| match message {
+                ServerMessages::PlayerCreate { id, .. } => Some(id),
+                _ => None,
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Laps of every `MatchInfo` the server sent `client_id` since the last call.
+    fn match_infos(
+        world: &mut World,
+        client: &mut RenetClient,
+        client_id: u64,
+    ) -> Vec<Option<u32>> {
+        server_messages(world, client, client_id)
+            .into_iter()
+            .filter_map(|message| match message {
+                ServerMessages::MatchInfo { info } => Some(info.laps),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn match_info_goes_out_on_connect_and_change_only() {
+        let mut world = World::new();
+        world.insert_resource(RenetServer::new(connection_config()));
+        world.insert_resource(Weather::default());
+        world.insert_resource(MatchInfo::new("test".into(), None, Weather::default()));
+        world.init_resource::<Events<ServerEvent>>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(match_info_system);
+        schedule.run(&mut world);
+
+        let client_id = ClientId::from_raw(1);
+        world
+            .resource_mut::<RenetServer>()
+            .add_connection(client_id);
+        world.send_event(ServerEvent::ClientConnected { client_id });
+        let mut client = RenetClient::new(connection_config());
+        client.set_connected();
+        for _ in 0..3 {
+            schedule.run(&mut world);
         }
-        if input.right {
-            car.steering = 1.;
+        assert_eq!(match_infos(&mut world, &mut client, 1), vec![None]);
+
+        world.resource_mut::<MatchInfo>().laps = Some(3);
+        for _ in 0..3 {
+            schedule.run(&mut world);
         }
-        if !input.left && !input.right {
-            car.steering = 0.;
+        assert_eq!(match_infos(&mut world, &mut client, 1), vec![Some(3)]);
+    }
+
+    #[test]
+    fn sequential_connects_announce_every_car_once() {
+        let mut world = welcome_world();
+        let mut client_1 = connect(&mut world, 1);
+        world.run_system_once(server_welcome_system);
+        let mut client_2 = connect(&mut world, 2);
+        world.run_system_once(server_welcome_system);
+
+        assert_eq!(player_creates(&mut world, &mut client_1, 1), vec![1, 2]);
+        assert_eq!(player_creates(&mut world, &mut client_2, 2), vec![1, 2]);
+        assert_eq!(world.query::<&Player>().iter(&world).count(), 2);
+    }
+
+    #[test]
+    fn reconnect_gets_the_lobby_without_players_gone_meanwhile() {
+        let mut world = welcome_world();
+        let mut client_1 = connect(&mut world, 1);
+        let _client_2 = connect(&mut world, 2);
+        world.run_system_once(server_welcome_system);
+        server_messages(&mut world, &mut client_1, 1);
+
+        // 1 drops, 2 leaves for good before 1 is back
+        let mut lobby = world.resource_mut::<ServerLobby>();
+        let car_1 = lobby.players.remove(&1).unwrap();
+        lobby
+            .disconnected
+            .insert(1, (car_1, Timer::new(RECONNECT_GRACE, TimerMode::Once)));
+        let car_2 = lobby.players.remove(&2).unwrap();
+        world.despawn(car_2);
+        world
+            .resource_mut::<PendingConnects>()
+            .0
+            .push(ClientId::from_raw(1));
+        world.run_system_once(server_welcome_system);
+
+        let lobbies: Vec<Vec<u64>> = server_messages(&mut world, &mut client_1, 1)
+            .into_iter()
+            .filter_map(|message| match message {
+                ServerMessages::Lobby { ids } => Some(ids),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lobbies, vec![vec![1]]);
+        assert_eq!(world.resource::<ServerLobby>().players[&1], car_1);
+    }
+
+    #[test]
+    fn world_state_follows_every_player_create_on_connect() {
+        let mut world = welcome_world();
+        let mut client_1 = connect(&mut world, 1);
+        world.run_system_once(server_welcome_system);
+        let mut client_2 = connect(&mut world, 2);
+        world.run_system_once(server_welcome_system);
+        let wheels: Vec<Entity> = world
+            .query_filtered::<Entity, With<Wheel>>()
+            .iter(&world)
+            .collect();
+        for wheel in wheels {
+            world.entity_mut(wheel).insert(GlobalTransform::default());
         }
+        world.run_system_once(server_baseline_system);
+
+        for (client, client_id) in [(&mut client_1, 1), (&mut client_2, 2)] {
+            let messages = server_messages(&mut world, client, client_id);
+            let last_create = messages
+                .iter()
+                .rposition(|message| matches!(message, ServerMessages::PlayerCreate { .. }))
+                .unwrap();
+            let Some(ServerMessages::WorldState { snapshot }) = messages.last() else {
+                panic!("no WorldState after the PlayerCreates of client {client_id}");
+            };
+            assert!(last_create < messages.len() - 1);
+            assert_eq!(
+                snapshot.entities.len(),
+                2,
+                "both cars, the client's own included"
+            );
+        }
+        assert!(world.resource::<PendingBaselines>().0.is_empty());
+    }
+
+    #[test]
+    fn connects_in_one_frame_announce_every_car_once() {
+        let mut world = welcome_world();
+        let mut client_1 = connect(&mut world, 1);
+        let mut client_2 = connect(&mut world, 2);
+        world.run_system_once(server_welcome_system);
+
+        assert_eq!(player_creates(&mut world, &mut client_1, 1), vec![1, 2]);
+        assert_eq!(player_creates(&mut world, &mut client_2, 2), vec![1, 2]);
+    }
+
+    /// Player 1 driving at 20 m/s into player 2 standing 4 m ahead, client 2 listening.
+    fn ram_world() -> (World, Entity, Entity, RenetClient) {
+        let mut world = World::new();
+        let mut server = RenetServer::new(connection_config());
+        server.add_connection(ClientId::from_raw(2));
+        world.insert_resource(server);
+        world.insert_resource(RamPenalty {
+            enabled: true,
+            ..Default::default()
+        });
+        world.init_resource::<Events<ContactForceEvent>>();
+        let rammer = world
+            .spawn((
+                Player { id: 1 },
+                Transform::default(),
+                Velocity::linear(Vec3::Z * 20.),
+            ))
+            .id();
+        let victim = world
+            .spawn((
+                Player { id: 2 },
+                Transform::from_xyz(0., 0., 4.),
+                Velocity::zero(),
+            ))
+            .id();
+        let mut client = RenetClient::new(connection_config());
+        client.set_connected();
+        (world, rammer, victim, client)
+    }
+
+    fn contact(collider1: Entity, collider2: Entity, force: f32) -> ContactForceEvent {
+        ContactForceEvent {
+            collider1,
+            collider2,
+            total_force: Vec3::Z * force,
+            total_force_magnitude: force,
+            max_force_direction: Vec3::Z,
+            max_force_magnitude: force,
+        }
+    }
+
+    fn rams(world: &mut World, client: &mut RenetClient) -> Vec<(u64, u64)> {
+        server_messages(world, client, 2)
+            .into_iter()
+            .filter_map(|message| match message {
+                ServerMessages::Rammed { rammer, victim } => Some((rammer, victim)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hard_contact_blames_the_rammer_once() {
+        let (mut world, rammer, victim, mut client) = ram_world();
+        for _ in 0..3 {
+            world.send_event(contact(victim, rammer, 20_000.));
+        }
+        world.run_system_once(ram_detection_system);
+
+        assert!(world.get::<Penalized>(rammer).is_some());
+        assert!(world.get::<Penalized>(victim).is_none());
+        assert_eq!(rams(&mut world, &mut client), vec![(1, 2)]);
+
+        // still touching on the next frame, the running penalty covers it
+        world.send_event(contact(rammer, victim, 20_000.));
+        world.run_system_once(ram_detection_system);
+        assert!(rams(&mut world, &mut client).is_empty());
+    }
+
+    #[test]
+    fn scrape_below_the_force_threshold_is_no_ram() {
+        let (mut world, rammer, victim, mut client) = ram_world();
+        world.send_event(contact(rammer, victim, 500.));
+        world.run_system_once(ram_detection_system);
+
+        assert!(world.get::<Penalized>(rammer).is_none());
+        assert!(rams(&mut world, &mut client).is_empty());
     }
 }
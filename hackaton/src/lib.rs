@@ -1,48 +1,149 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use bevy::{
-    prelude::{Component, Entity, Event, Resource}, utils::HashMap
+    math::{Quat, Vec3},
+    prelude::{Component, Entity, Event, Resource, Timer, Transform},
+    utils::{HashMap, HashSet},
+};
+use bevy_garage_car::{PhysicsParams, Weather};
+use bevy_rapier3d::plugin::TimestepMode;
+use bevy_renet::renet::{
+    ChannelConfig, ClientId, ConnectionConfig, DisconnectReason, SendType,
+    transport::{NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES, generate_random_bytes},
 };
-use bevy_renet::renet::{ChannelConfig, ConnectionConfig, DisconnectReason, SendType};
 use serde::{Deserialize, Serialize};
 
+pub mod memory_transport;
+pub mod metrics;
 pub mod shared_systems;
+pub mod sync;
 
 #[derive(Debug, Component)]
 pub struct Player {
     pub id: u64,
 }
 
+/// Id of this client, inserted next to whichever transport connected it.
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct LocalClientId(pub ClientId);
+
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Component, Resource)]
 pub struct PlayerInput {
     pub forward: bool,
     pub left: bool,
     pub right: bool,
     pub brake: bool,
+    /// Analog steering from -1 left to 1 right, used over `left` and `right` when nonzero.
+    pub steering: f32,
+    /// Analog pedals from -1 full brake to 1 full gas, used over `forward` and `brake` when nonzero.
+    pub throttle: f32,
+}
+
+impl PlayerInput {
+    /// `throttle` when a controller set it, clamped, a non-finite value counts as unset.
+    pub fn analog_throttle(&self) -> Option<f32> {
+        analog_axis(self.throttle)
+    }
+    /// Steering in `-1..1`, analog when set, else from the keys.
+    pub fn steering(&self) -> f32 {
+        analog_axis(self.steering).unwrap_or(self.right as u8 as f32 - self.left as u8 as f32)
+    }
+    /// Gas and brake in `0..1`, analog when set, else from the keys.
+    pub fn pedals(&self) -> (f32, f32) {
+        match self.analog_throttle() {
+            Some(throttle) => (throttle.max(0.), (-throttle).max(0.)),
+            None => (self.forward as u8 as f32, self.brake as u8 as f32),
+        }
+    }
+}
+
+fn analog_axis(value: f32) -> Option<f32> {
+    (value.is_finite() && value != 0.).then(|| value.clamp(-1., 1.))
 }
 
 pub enum ClientChannel {
     Input,
+    Command,
+}
+
+/// `ClientChannel::Input` payload, `sequence` grows by one per message so the
+/// server can drop inputs which arrive after a newer one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SequencedInput {
+    pub sequence: u64,
+    pub input: PlayerInput,
+}
+
+/// Input messages the server decodes per client and tick, older ones of a bigger
+/// burst are dropped unread, so flooding the channel costs no more than playing.
+pub const MAX_INPUTS_PER_TICK: usize = 4;
+
+/// How `ClientChannel::Input` is delivered.
+///
+/// `Reliable` resends every lost input and holds back the newer ones until it arrives,
+/// under loss the car reacts late but no input is missed. `Unreliable` only ever applies
+/// the newest input which made it, a lost one is simply replaced by the next frame's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDelivery {
+    Reliable,
+    Unreliable,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessages {
+    /// Ask for a reliable `ServerMessages::WorldState`.
+    RequestResync,
+    /// Input for one simulation tick in `NetworkMode::Lockstep`.
+    TickInput { tick: u64, input: PlayerInput },
+    /// Admin command, ignored unless `token` matches the server's `APP_ADMIN_TOKEN`.
+    RestartRace { token: String },
+    /// Name shown in lap records, sent once after connecting.
+    SetName { name: String },
+    /// Admin command clearing the lap records of the current track.
+    ResetRecords { token: String },
+    /// Put the sender's car back on the track at the nearest centerline point,
+    /// at most once per `RESET_COOLDOWN`.
+    ResetToTrack,
+}
+
+/// Seconds between two `ClientMessages::ResetToTrack` of one client the server accepts.
+pub const RESET_COOLDOWN: f32 = 3.;
+
 impl From<ClientChannel> for u8 {
     fn from(channel_id: ClientChannel) -> Self {
         match channel_id {
+            ClientChannel::Command => 0,
             ClientChannel::Input => 1,
         }
     }
 }
 
 impl ClientChannel {
-    pub fn channels_config() -> Vec<ChannelConfig> {
-        vec![ChannelConfig {
-            channel_id: Self::Input.into(),
-            max_memory_usage_bytes: 5 * 1024 * 1024,
-            send_type: SendType::ReliableOrdered {
-                resend_time: Duration::ZERO,
+    pub fn channels_config(tuning: &ChannelTuning) -> Vec<ChannelConfig> {
+        vec![
+            ChannelConfig {
+                channel_id: Self::Input.into(),
+                max_memory_usage_bytes: tuning.input_max_memory_usage_bytes,
+                send_type: match tuning.input_delivery {
+                    InputDelivery::Reliable => SendType::ReliableOrdered {
+                        resend_time: tuning.input_resend_time,
+                    },
+                    InputDelivery::Unreliable => SendType::Unreliable,
+                },
+                // the server validates and rate limits what arrives, see `MAX_INPUTS_PER_TICK`
             },
-            // Potential user attack info goes here
-        }]
+            ChannelConfig {
+                channel_id: Self::Command.into(),
+                max_memory_usage_bytes: tuning.command_max_memory_usage_bytes,
+                send_type: SendType::ReliableOrdered {
+                    resend_time: tuning.command_resend_time,
+                },
+            },
+        ]
     }
 }
 
@@ -51,6 +152,7 @@ pub enum ServerChannel {
     NetworkedEntities,
 }
 
+/// bincode tags a variant with its index, add new ones at the end.
 #[derive(Debug, Serialize, Deserialize, Component)]
 pub enum ServerMessages {
     PlayerCreate {
@@ -61,6 +163,62 @@ pub enum ServerMessages {
     PlayerRemove {
         id: u64,
     },
+    /// Complete world baseline sent reliably to late joiners and on request,
+    /// applying it twice leaves the client in the same state.
+    WorldState {
+        snapshot: NetworkedEntities,
+    },
+    /// Server solver settings, sent on connect. Clients don't simulate the cars,
+    /// they keep the settings and warn when they differ from their own.
+    PhysicsConfig {
+        params: PhysicsParams,
+    },
+    /// Every car is despawned, fresh `PlayerCreate`s on the start grid follow.
+    RaceRestart,
+    /// First tick the client has to send `ClientMessages::TickInput` for.
+    LockstepStart {
+        tick: u64,
+    },
+    /// Sensor readings of the receiver's car, `CarSensors::sensor_inputs` order.
+    /// Always part of the enum so the wire layout doesn't depend on the
+    /// `telemetry` feature, only servers built with it send it.
+    Telemetry {
+        id: u64,
+        sensors: Vec<f32>,
+        speed: f32,
+    },
+    /// All-time best lap of the track, on connect and whenever it changes.
+    TrackRecord {
+        record: Option<LapRecord>,
+    },
+    /// `rammer` drove into `victim` and got `RamPenalty` applied.
+    Rammed {
+        rammer: u64,
+        victim: u64,
+    },
+    /// What the race is about, on connect and whenever any of it changes.
+    MatchInfo {
+        info: MatchInfo,
+    },
+    /// `(client id, completed laps, meters from the start line)` of every car,
+    /// leader first, every `STANDINGS_INTERVAL`.
+    Standings {
+        entries: Vec<(u64, u32, f32)>,
+    },
+    /// Sent to a client the server turns away, right before dropping it.
+    Rejected {
+        reason: String,
+    },
+    /// Track condition, sent on connect and whenever the server changes it.
+    Weather {
+        weather: Weather,
+    },
+    /// Client ids of every car on the track, sent on connect after their `PlayerCreate`s.
+    /// A client back within `RECONNECT_GRACE` missed the `PlayerRemove` of players who
+    /// left meanwhile and drops the cars not listed.
+    Lobby {
+        ids: Vec<u64>,
+    },
 }
 
 impl From<ServerChannel> for u8 {
@@ -73,47 +231,598 @@ impl From<ServerChannel> for u8 {
 }
 
 impl ServerChannel {
-    pub fn channels_config() -> Vec<ChannelConfig> {
+    pub fn channels_config(tuning: &ChannelTuning) -> Vec<ChannelConfig> {
         vec![
             ChannelConfig {
                 channel_id: Self::NetworkedEntities.into(),
-                max_memory_usage_bytes: 10 * 1024 * 1024,
+                max_memory_usage_bytes: tuning.networked_entities_max_memory_usage_bytes,
                 send_type: SendType::Unreliable,
             },
             ChannelConfig {
                 channel_id: Self::ServerMessages.into(),
-                max_memory_usage_bytes: 10 * 1024 * 1024,
+                max_memory_usage_bytes: tuning.server_messages_max_memory_usage_bytes,
                 send_type: SendType::ReliableOrdered {
-                    resend_time: Duration::from_millis(200),
+                    resend_time: tuning.server_messages_resend_time,
                 },
             },
         ]
     }
 }
 
+/// Car and wheel poses, all in world space. Wheel poses per car follow its
+/// `CarWheels` order and are as many as the vehicle has wheels.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct NetworkedEntities {
     pub entities: Vec<Entity>,
     pub positions: Vec<[f32; 3]>,
     pub orientations: Vec<[f32; 4]>,
-    pub wheel_positions: Vec<[[f32; 3]; 4]>,
-    pub wheel_orientations: Vec<[[f32; 4]; 4]>,
+    pub wheel_positions: Vec<Vec<[f32; 3]>>,
+    pub wheel_orientations: Vec<Vec<[f32; 4]>>,
+    /// Next lockstep tick the server waits for, 0 in `NetworkMode::Authoritative`.
+    pub tick: u64,
+    /// Counts unreliable snapshots from 1, clients drop ones older than what they
+    /// already have. 0 for reliable baselines, which always apply.
+    pub sequence: u64,
+    /// Per entity, the `SequencedInput::sequence` the server applied last, 0 before any.
+    pub acked_inputs: Vec<u64>,
+}
+
+/// How long a disconnected player's car waits on the track for the same client id.
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+/// How long a client past `max_clients` stays connected for its `ServerMessages::Rejected` to arrive.
+pub const REJECT_GRACE: Duration = Duration::from_secs(1);
+
+/// Players and spectators a server takes, `MAX_CLIENTS` or 12.
+pub fn max_clients() -> usize {
+    std::env::var("MAX_CLIENTS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(12)
 }
 
 #[derive(Debug, Default, Resource)]
 pub struct ServerLobby {
     pub players: HashMap<u64, Entity>,
+    /// Cars of dropped players with the time left to reconnect, see `RECONNECT_GRACE`.
+    pub disconnected: HashMap<u64, (Entity, Timer)>,
+    /// Clients connected as `ConnectMode::Spectator`, they never get a car.
+    pub spectators: HashSet<u64>,
+    /// Clients past `max_clients` waiting to be dropped, see `REJECT_GRACE`.
+    pub rejected: HashMap<u64, Timer>,
 }
 
-pub fn connection_config() -> ConnectionConfig {
-    ConnectionConfig {
-        available_bytes_per_tick: 1024 * 1024,
-        client_channels_config: ClientChannel::channels_config(),
-        server_channels_config: ServerChannel::channels_config(),
+/// Roughly one second of server ticks at 60 fps.
+pub const SNAPSHOT_HISTORY_TICKS: usize = 60;
+
+/// Per tick car transforms kept on the server, so events can be validated
+/// against the world as the client saw it (lag compensation).
+#[derive(Debug, Resource)]
+pub struct SnapshotHistory {
+    pub tick: u64,
+    capacity: usize,
+    snapshots: VecDeque<(u64, HashMap<Entity, Transform>)>,
+}
+
+impl Default for SnapshotHistory {
+    fn default() -> Self {
+        Self::new(SNAPSHOT_HISTORY_TICKS)
+    }
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            tick: 0,
+            capacity: capacity.max(1),
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Stores transforms for the next tick and drops the ones older than the capacity.
+    pub fn push(&mut self, transforms: HashMap<Entity, Transform>) -> u64 {
+        self.tick += 1;
+        self.snapshots.push_back((self.tick, transforms));
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.tick
+    }
+
+    /// Transforms at `tick`, or at the latest stored tick before it.
+    /// `None` when the tick is older than the kept history.
+    pub fn rewind_to(&self, tick: u64) -> Option<&HashMap<Entity, Transform>> {
+        let i = self.snapshots.partition_point(|(t, _)| *t <= tick);
+        if i == 0 {
+            return None;
+        }
+        self.snapshots.get(i - 1).map(|(_, transforms)| transforms)
+    }
+
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.snapshots.front().map(|(tick, _)| *tick)
+    }
+}
+
+/// Channel memory caps and resend times, defaults are the values the game shipped with.
+/// `from_env` lets operators tune them without a rebuild, client and server should agree.
+#[derive(Debug, Clone)]
+pub struct ChannelTuning {
+    pub available_bytes_per_tick: u64,
+    pub input_max_memory_usage_bytes: usize,
+    pub input_resend_time: Duration,
+    pub input_delivery: InputDelivery,
+    pub command_max_memory_usage_bytes: usize,
+    pub command_resend_time: Duration,
+    pub networked_entities_max_memory_usage_bytes: usize,
+    pub server_messages_max_memory_usage_bytes: usize,
+    pub server_messages_resend_time: Duration,
+}
+
+impl Default for ChannelTuning {
+    fn default() -> Self {
+        Self {
+            available_bytes_per_tick: 1024 * 1024,
+            input_max_memory_usage_bytes: 5 * 1024 * 1024,
+            input_resend_time: Duration::ZERO,
+            input_delivery: InputDelivery::Reliable,
+            command_max_memory_usage_bytes: 1024 * 1024,
+            command_resend_time: Duration::from_millis(200),
+            networked_entities_max_memory_usage_bytes: 10 * 1024 * 1024,
+            server_messages_max_memory_usage_bytes: 10 * 1024 * 1024,
+            server_messages_resend_time: Duration::from_millis(200),
+        }
+    }
+}
+
+impl ChannelTuning {
+    /// Reads `APP_BYTES_PER_TICK`, `APP_INPUT_MEMORY`, `APP_INPUT_RESEND_MS`,
+    /// `APP_ENTITIES_MEMORY`, `APP_MESSAGES_MEMORY`, `APP_MESSAGES_RESEND_MS` and
+    /// `APP_INPUT_DELIVERY=unreliable`, unset or unparsable variables keep the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+        let mut tuning = Self::default();
+        if let Some(bytes) = var("APP_BYTES_PER_TICK") {
+            tuning = tuning.with_available_bytes_per_tick(bytes);
+        }
+        if let Some(bytes) = var("APP_INPUT_MEMORY") {
+            tuning = tuning.with_input_memory(bytes);
+        }
+        if let Some(ms) = var("APP_INPUT_RESEND_MS") {
+            tuning = tuning.with_input_resend_time(Duration::from_millis(ms));
+        }
+        match std::env::var("APP_INPUT_DELIVERY").as_deref() {
+            Ok("unreliable") => tuning = tuning.with_input_delivery(InputDelivery::Unreliable),
+            Ok("reliable") => tuning = tuning.with_input_delivery(InputDelivery::Reliable),
+            _ => {}
+        }
+        if let Some(bytes) = var("APP_ENTITIES_MEMORY") {
+            tuning = tuning.with_networked_entities_memory(bytes);
+        }
+        if let Some(bytes) = var("APP_MESSAGES_MEMORY") {
+            tuning = tuning.with_server_messages_memory(bytes);
+        }
+        if let Some(ms) = var("APP_MESSAGES_RESEND_MS") {
+            tuning = tuning.with_server_messages_resend_time(Duration::from_millis(ms));
+        }
+        tuning
+    }
+
+    pub fn with_available_bytes_per_tick(mut self, bytes: u64) -> Self {
+        self.available_bytes_per_tick = bytes;
+        self
+    }
+
+    pub fn with_input_memory(mut self, bytes: usize) -> Self {
+        self.input_max_memory_usage_bytes = bytes;
+        self
+    }
+
+    pub fn with_input_resend_time(mut self, resend_time: Duration) -> Self {
+        self.input_resend_time = resend_time;
+        self
+    }
+
+    pub fn with_input_delivery(mut self, delivery: InputDelivery) -> Self {
+        self.input_delivery = delivery;
+        self
+    }
+
+    pub fn with_networked_entities_memory(mut self, bytes: usize) -> Self {
+        self.networked_entities_max_memory_usage_bytes = bytes;
+        self
+    }
+
+    pub fn with_server_messages_memory(mut self, bytes: usize) -> Self {
+        self.server_messages_max_memory_usage_bytes = bytes;
+        self
+    }
+
+    pub fn with_server_messages_resend_time(mut self, resend_time: Duration) -> Self {
+        self.server_messages_resend_time = resend_time;
+        self
+    }
+
+    pub fn connection_config(&self) -> ConnectionConfig {
+        ConnectionConfig {
+            available_bytes_per_tick: self.available_bytes_per_tick,
+            client_channels_config: ClientChannel::channels_config(self),
+            server_channels_config: ServerChannel::channels_config(self),
+        }
+    }
+}
+
+/// Seconds between `ServerMessages::Telemetry` updates.
+#[cfg(feature = "telemetry")]
+pub const TELEMETRY_INTERVAL: f32 = 0.5;
+
+/// Seconds between `ServerMessages::Standings` updates.
+pub const STANDINGS_INTERVAL: f32 = 1.;
+
+/// Secret shared with admin clients, read from `APP_ADMIN_TOKEN`.
+/// Remote admin commands are disabled when it's unset or empty.
+pub fn admin_token() -> Option<String> {
+    std::env::var("APP_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// `NETCODE_PRIVATE_KEY`, 64 hex digits shared by server and clients. Unset keeps the
+/// transport unsecure for local development, a malformed key panics rather than
+/// silently falling back to it.
+pub fn netcode_private_key() -> Option<[u8; NETCODE_KEY_BYTES]> {
+    let hex = std::env::var("NETCODE_PRIVATE_KEY")
+        .ok()
+        .filter(|hex| !hex.is_empty())?;
+    match key_from_hex(hex.trim()) {
+        Some(key) => Some(key),
+        None => panic!(
+            "NETCODE_PRIVATE_KEY must be {} hex digits",
+            NETCODE_KEY_BYTES * 2
+        ),
+    }
+}
+
+pub fn key_from_hex(hex: &str) -> Option<[u8; NETCODE_KEY_BYTES]> {
+    if hex.len() != NETCODE_KEY_BYTES * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0; NETCODE_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Fresh random key as `NETCODE_PRIVATE_KEY` expects it.
+pub fn generate_netcode_key() -> String {
+    generate_random_bytes::<NETCODE_KEY_BYTES>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// What a client joins as, carried in the first byte of the netcode `user_data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Resource)]
+pub enum ConnectMode {
+    #[default]
+    Player,
+    /// Watches the race, the server spawns no car and applies no input for it.
+    Spectator,
+}
+
+impl ConnectMode {
+    /// `APP_SPECTATE=1` joins as a spectator.
+    pub fn from_env() -> Self {
+        match std::env::var("APP_SPECTATE").as_deref() {
+            Ok("1") | Ok("true") => Self::Spectator,
+            _ => Self::Player,
+        }
+    }
+    pub fn to_user_data(self) -> [u8; NETCODE_USER_DATA_BYTES] {
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        user_data[0] = match self {
+            Self::Player => 0,
+            Self::Spectator => 1,
+        };
+        user_data
+    }
+    /// Missing or unknown user data joins as a player.
+    pub fn from_user_data(user_data: Option<[u8; NETCODE_USER_DATA_BYTES]>) -> Self {
+        match user_data.map(|user_data| user_data[0]) {
+            Some(1) => Self::Spectator,
+            _ => Self::Player,
+        }
+    }
+    pub fn is_spectator(&self) -> bool {
+        *self == Self::Spectator
+    }
+}
+
+pub const LOCKSTEP_INPUT_DELAY: u64 = 3;
+
+/// How the server turns client inputs into simulation steps, client and server should agree.
+///
+/// `Authoritative` applies the latest input of every client and steps physics every frame,
+/// a laggy client only hurts its own car. `Lockstep` steps physics with a fixed dt only once
+/// every client sent its input for the tick, inputs are scheduled `input_delay` ticks ahead
+/// to hide the round trip. The race then replays exactly from the input log, but everyone
+/// drives with the slowest client's latency plus the delay, and a stalled or lost client
+/// freezes the race until it catches up or disconnects. Meant for small LAN races.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub enum NetworkMode {
+    Authoritative,
+    Lockstep { input_delay: u64 },
+}
+
+impl Default for NetworkMode {
+    fn default() -> Self {
+        Self::Authoritative
     }
 }
 
-pub const SERVER_PROTOCOL_ID: u64 = 7;
+impl NetworkMode {
+    /// `APP_NET_MODE=lockstep` with an optional `APP_INPUT_DELAY` in ticks.
+    pub fn from_env() -> Self {
+        match std::env::var("APP_NET_MODE").as_deref() {
+            Ok("lockstep") => Self::Lockstep {
+                input_delay: std::env::var("APP_INPUT_DELAY")
+                    .ok()
+                    .and_then(|delay| delay.parse().ok())
+                    .unwrap_or(LOCKSTEP_INPUT_DELAY),
+            },
+            _ => Self::Authoritative,
+        }
+    }
+    /// Rapier timestep of `params` for this mode, fixed under lockstep so every
+    /// peer steps with the same dt and frame time doesn't leak into the simulation.
+    pub fn timestep_mode(&self, params: &PhysicsParams) -> TimestepMode {
+        match self {
+            Self::Authoritative => params.timestep_mode(1.),
+            Self::Lockstep { .. } => params.fixed_timestep_mode(),
+        }
+    }
+}
+
+/// Server side lockstep inputs by tick and client id.
+#[derive(Debug, Default, Resource)]
+pub struct LockstepBuffer {
+    /// Next tick to simulate.
+    pub tick: u64,
+    inputs: BTreeMap<u64, HashMap<u64, PlayerInput>>,
+}
+
+impl LockstepBuffer {
+    /// Inputs for ticks already simulated are dropped.
+    pub fn insert(&mut self, tick: u64, client_id: u64, input: PlayerInput) {
+        if tick >= self.tick {
+            self.inputs
+                .entry(tick)
+                .or_default()
+                .insert(client_id, input);
+        }
+    }
+    /// Fills the delay window of a new client with idle input, returns its first tick.
+    pub fn join(&mut self, client_id: u64, input_delay: u64) -> u64 {
+        for tick in self.tick..self.tick + input_delay {
+            self.insert(tick, client_id, PlayerInput::default());
+        }
+        self.tick + input_delay
+    }
+    /// Takes the current tick inputs once every client in `clients` sent one.
+    pub fn pop_ready(&mut self, clients: &[u64]) -> Option<HashMap<u64, PlayerInput>> {
+        let inputs = self.inputs.get(&self.tick)?;
+        if !clients
+            .iter()
+            .all(|client_id| inputs.contains_key(client_id))
+        {
+            return None;
+        }
+        let inputs = self.inputs.remove(&self.tick);
+        self.tick += 1;
+        inputs
+    }
+}
+
+/// Server rule slowing down the car which rams another player, off by default.
+/// Enabling it also lets player car bodies collide with each other.
+#[derive(Debug, Clone, Resource)]
+pub struct RamPenalty {
+    pub enabled: bool,
+    /// Contact force in newtons from which a touch between two cars is a ram, not a scrape.
+    pub min_force: f32,
+    /// Closing speed in m/s below which a contact is not attributed to anyone.
+    pub min_closing_speed: f32,
+    /// Gas multiplier while the penalty lasts.
+    pub gas_scale: f32,
+    pub duration: Duration,
+}
+
+impl Default for RamPenalty {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_force: 10_000.,
+            min_closing_speed: 5.,
+            gas_scale: 0.3,
+            duration: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RamPenalty {
+    /// `APP_RAM_PENALTY=1` turns the rule on.
+    pub fn from_env() -> Self {
+        Self {
+            enabled: matches!(
+                std::env::var("APP_RAM_PENALTY").as_deref(),
+                Ok("1" | "true")
+            ),
+            ..Self::default()
+        }
+    }
+}
+
+/// Server driven opponents racing alongside the players, none by default.
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct BotConfig {
+    pub count: usize,
+}
+
+impl BotConfig {
+    /// `BOT_COUNT` bots.
+    pub fn from_env() -> Self {
+        Self {
+            count: std::env::var("BOT_COUNT")
+                .ok()
+                .and_then(|count| count.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Player id of a bot, counting down from `u64::MAX`, far from the client ids
+/// which are taken from the clock.
+pub fn bot_id(index: usize) -> u64 {
+    u64::MAX - index as u64
+}
+
+/// Client side smoothing of networked cars between snapshots.
+#[derive(Debug, Clone, Resource)]
+pub struct InterpolationConfig {
+    /// How long a car keeps moving along its last known velocity when no newer snapshot
+    /// arrived, it freezes after that until the next one. Zero disables extrapolation.
+    pub max_extrapolation: Duration,
+    /// How far in the past other players' cars are shown, interpolated between the
+    /// snapshots around that time. A few snapshot intervals hide lost packets.
+    pub delay: Duration,
+}
+
+impl Default for InterpolationConfig {
+    fn default() -> Self {
+        Self {
+            max_extrapolation: Duration::from_millis(250),
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl InterpolationConfig {
+    /// `APP_MAX_EXTRAPOLATION_MS` and `APP_INTERPOLATION_DELAY_MS`, unset or unparsable
+    /// ones keep the default.
+    pub fn from_env() -> Self {
+        fn ms(name: &str) -> Option<Duration> {
+            std::env::var(name)
+                .ok()?
+                .parse()
+                .ok()
+                .map(Duration::from_millis)
+        }
+        let default = Self::default();
+        Self {
+            max_extrapolation: ms("APP_MAX_EXTRAPOLATION_MS").unwrap_or(default.max_extrapolation),
+            delay: ms("APP_INTERPOLATION_DELAY_MS").unwrap_or(default.delay),
+        }
+    }
+}
+
+/// Snapshots sent so far, `NetworkedEntities::sequence` of the latest one.
+/// Unlike the lockstep tick it grows in every `NetworkMode`.
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct ServerTick(pub u64);
+
+/// `SequencedInput::sequence` the server last applied to a player car.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct AckedInput(pub u64);
+
+/// Client side prediction of the own car, a kinematic stand-in for the server
+/// physics which moves it on input at once. Snapshots correct it by replaying the
+/// inputs the server has not applied yet on top of the server pose.
+///
+/// The stand-in is not the server's car simulation, it ignores suspension, slip
+/// and collisions, so the shown car drifts from the server's between snapshots
+/// and gets pulled back. Off unless asked for until prediction replays the same
+/// rapier car as `move_players_system`.
+#[derive(Debug, Clone, Resource)]
+pub struct PredictionConfig {
+    pub enabled: bool,
+    /// m/s² at full throttle on dry road, scaled by `Weather::grip`.
+    pub acceleration: f32,
+    /// m/s² while braking.
+    pub braking: f32,
+    /// Speed lost per second without throttle, as a share of the speed.
+    pub drag: f32,
+    pub max_speed: f32,
+    /// rad/s at full steering and `max_speed`, less when slower.
+    pub turn_rate: f32,
+    /// Seconds a correction by a snapshot is blended over instead of snapping.
+    pub correction: f32,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            acceleration: 8.,
+            braking: 20.,
+            drag: 0.2,
+            max_speed: 60.,
+            turn_rate: 1.5,
+            correction: 0.2,
+        }
+    }
+}
+
+impl PredictionConfig {
+    /// `APP_PREDICTION=on` predicts the own car, otherwise it's shown where the
+    /// server has it.
+    pub fn from_env() -> Self {
+        Self {
+            enabled: std::env::var("APP_PREDICTION").as_deref() == Ok("on"),
+            ..Self::default()
+        }
+    }
+
+    /// `transform` and forward `speed` after driving `dt` seconds with `input`.
+    pub fn step(
+        &self,
+        transform: &mut Transform,
+        speed: &mut f32,
+        input: &PlayerInput,
+        grip: f32,
+        dt: f32,
+    ) {
+        let (gas, brake) = input.pedals();
+        // keys press full throttle, which only grips as much as the weather allows
+        let gas = match input.analog_throttle() {
+            Some(_) => gas,
+            None => gas * grip,
+        };
+        let throttle = gas * self.acceleration;
+        let braking = (brake * self.braking * dt).min(speed.abs()) * speed.signum();
+        *speed = (*speed + (throttle - self.drag * *speed) * dt - braking)
+            .clamp(-self.max_speed, self.max_speed);
+        let steering = input.steering();
+        // steering right turns away from +x for a car facing +z
+        let yaw = -steering * self.turn_rate * (*speed / self.max_speed).clamp(-1., 1.) * dt;
+        transform.rotation = Quat::from_rotation_y(yaw) * transform.rotation;
+        let forward = transform.rotation * Vec3::Z;
+        transform.translation += forward * *speed * dt;
+    }
+}
+
+/// Remaining ram penalty time on a car.
+#[derive(Debug, Component)]
+pub struct Penalized(pub Timer);
+
+pub fn connection_config() -> ConnectionConfig {
+    ChannelTuning::from_env().connection_config()
+}
+
+/// Bumped whenever a message layout changes, bincode payloads carry no version,
+/// so clients of another layout are refused at connect instead of sending garbage.
+pub const SERVER_PROTOCOL_ID: u64 = 15;
 #[derive(Debug, PartialEq, Eq, Event)]
 pub enum ServerEvent {
     ClientConnected {
@@ -125,4 +834,225 @@ pub enum ServerEvent {
     },
 }
 
+/// How the server picks `Weather` for each race.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub enum WeatherSchedule {
+    Fixed(Weather),
+    /// Dry, wet, icy, dry, ... one step per race restart.
+    Cycle,
+    Random,
+}
+
+impl WeatherSchedule {
+    /// `APP_WEATHER` is `dry`, `wet`, `icy`, `cycle` or `random`, dry when unset.
+    pub fn from_env() -> Self {
+        match std::env::var("APP_WEATHER").as_deref() {
+            Ok("cycle") => Self::Cycle,
+            Ok("random") => Self::Random,
+            Ok(name) => Self::Fixed(Weather::from_name(name).unwrap_or_default()),
+            Err(_) => Self::Fixed(Weather::default()),
+        }
+    }
+    pub fn first(&self) -> Weather {
+        match self {
+            Self::Fixed(weather) => *weather,
+            Self::Cycle => Weather::default(),
+            Self::Random => random_weather(),
+        }
+    }
+    /// Weather for the race after one run in `current`.
+    pub fn next(&self, current: Weather) -> Weather {
+        match self {
+            Self::Fixed(weather) => *weather,
+            Self::Cycle => current.next(),
+            Self::Random => random_weather(),
+        }
+    }
+}
+
+fn random_weather() -> Weather {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or_default();
+    Weather::ALL[nanos as usize % Weather::ALL.len()]
+}
+
+/// Phase of the current race.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RaceState {
+    /// No lap target, cars drive until the race is restarted.
+    Practice,
+    Running,
+    /// `winner` completed the lap target first, the race waits for a restart.
+    Finished {
+        winner: u64,
+    },
+}
+
+/// Server authority on the match a client joins. Values the server doesn't
+/// track are `None`. bincode has no field tags, so options don't make the
+/// layout forward compatible: any field change needs a `SERVER_PROTOCOL_ID` bump.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Resource)]
+pub struct MatchInfo {
+    /// Leaderboard key of the track, also names the direction it's raced in.
+    pub track_name: String,
+    /// Laps to win, `None` for open practice.
+    pub laps: Option<u32>,
+    pub weather: Option<Weather>,
+    pub race_state: Option<RaceState>,
+}
+
+impl MatchInfo {
+    pub fn new(track_name: String, laps: Option<u32>, weather: Weather) -> Self {
+        Self {
+            track_name,
+            laps,
+            weather: Some(weather),
+            race_state: Some(Self::start_state(laps)),
+        }
+    }
+
+    /// `APP_RACE_LAPS`, unset, zero or unparsable means open practice.
+    pub fn laps_from_env() -> Option<u32> {
+        std::env::var("APP_RACE_LAPS")
+            .ok()
+            .and_then(|laps| laps.parse().ok())
+            .filter(|laps| *laps > 0)
+    }
+
+    /// State of a freshly started race.
+    pub fn start_state(laps: Option<u32>) -> RaceState {
+        match laps {
+            Some(_) => RaceState::Running,
+            None => RaceState::Practice,
+        }
+    }
+}
+
+/// Player name for records, `APP_PLAYER_NAME` or empty to let the server pick one.
+pub fn player_name() -> String {
+    std::env::var("APP_PLAYER_NAME").unwrap_or_default()
+}
+
+/// Longest accepted player name in characters.
+pub const PLAYER_NAME_MAX: usize = 16;
+/// Records kept per track.
+pub const LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LapRecord {
+    pub name: String,
+    /// Seconds.
+    pub time: f32,
+}
+
+/// Best laps per track, fastest first and one per name, stored as json at `path`.
+#[derive(Debug, Default, Resource, Serialize, Deserialize)]
+pub struct Leaderboard {
+    #[serde(skip)]
+    path: PathBuf,
+    tracks: BTreeMap<String, Vec<LapRecord>>,
+}
+
+impl Leaderboard {
+    /// `APP_LEADERBOARD` or `leaderboard.json` in the working directory.
+    pub fn path_from_env() -> PathBuf {
+        std::env::var("APP_LEADERBOARD")
+            .unwrap_or_else(|_| "leaderboard.json".to_string())
+            .into()
+    }
+
+    /// Starts empty when the file is missing or unreadable, it's rewritten on the next record.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let tracks = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                println!("Ignoring unreadable leaderboard {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self { path, tracks }
+    }
+
+    /// Writes a temporary file next to the target and renames it over, so a crash
+    /// or a second server writing at the same time never leaves half a file.
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.tracks)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+
+    pub fn records(&self, track: &str) -> &[LapRecord] {
+        self.tracks
+            .get(track)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn best(&self, track: &str) -> Option<&LapRecord> {
+        self.records(track).first()
+    }
+
+    /// Returns whether the records changed, slower laps than the name's best are dropped.
+    pub fn submit(&mut self, track: &str, record: LapRecord) -> bool {
+        if !record.time.is_finite() || record.time <= 0. {
+            return false;
+        }
+        let records = self.tracks.entry(track.to_string()).or_default();
+        if let Some(i) = records.iter().position(|r| r.name == record.name) {
+            if records[i].time <= record.time {
+                return false;
+            }
+            records.remove(i);
+        }
+        let i = records.partition_point(|r| r.time <= record.time);
+        if i >= LEADERBOARD_SIZE {
+            return false;
+        }
+        records.insert(i, record);
+        records.truncate(LEADERBOARD_SIZE);
+        true
+    }
+
+    pub fn reset(&mut self, track: &str) {
+        self.tracks.remove(track);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networked_entities_round_trip_with_four_wheels() {
+        let wheel_orientations: Vec<[f32; 4]> = (0..4)
+            .map(|i| Quat::from_rotation_y(0.1 * i as f32).into())
+            .collect();
+        let snapshot = NetworkedEntities {
+            entities: vec![Entity::from_raw(7)],
+            positions: vec![[1., 0.5, -2.]],
+            orientations: vec![Quat::from_rotation_y(0.3).into()],
+            wheel_positions: vec![(0..4).map(|i| [i as f32, 0.3, -(i as f32)]).collect()],
+            wheel_orientations: vec![wheel_orientations.clone()],
+            tick: 42,
+            sequence: 9,
+            acked_inputs: vec![5],
+        };
 
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        let decoded: NetworkedEntities = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded.entities, snapshot.entities);
+        assert_eq!(decoded.positions, snapshot.positions);
+        assert_eq!(decoded.orientations, snapshot.orientations);
+        assert_eq!(decoded.wheel_positions, snapshot.wheel_positions);
+        assert_eq!(decoded.wheel_orientations, vec![wheel_orientations]);
+        assert_eq!(decoded.tick, 42);
+        assert_eq!(decoded.sequence, 9);
+        assert_eq!(decoded.acked_inputs, vec![5]);
+    }
+}
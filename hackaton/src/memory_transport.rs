@@ -0,0 +1,132 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    app::{App, Plugin, PostUpdate, PreUpdate},
+    prelude::{IntoSystemConfigs, Res, ResMut, Resource},
+};
+use bevy_renet::{
+    RenetClientPlugin, RenetServerPlugin,
+    renet::{ClientId, RenetClient, RenetServer},
+};
+
+type Queue = Arc<Mutex<VecDeque<Vec<u8>>>>;
+
+/// Packet queues between one server and one client app in the same process,
+/// a stand-in for UDP in tests which step the apps by hand.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLink {
+    to_server: Queue,
+    to_client: Queue,
+}
+
+fn drain(queue: &Queue) -> Vec<Vec<u8>> {
+    queue.lock().unwrap().drain(..).collect()
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct MemoryServerTransport {
+    links: Vec<(ClientId, MemoryLink)>,
+}
+
+impl MemoryServerTransport {
+    /// Adds the connection to `server`, hand the returned link to the client app.
+    pub fn connect(&mut self, server: &mut RenetServer, client_id: ClientId) -> MemoryLink {
+        server.add_connection(client_id);
+        let link = MemoryLink::default();
+        self.links.push((client_id, link.clone()));
+        link
+    }
+
+    pub fn disconnect(&mut self, server: &mut RenetServer, client_id: ClientId) {
+        server.remove_connection(client_id);
+        self.links.retain(|(id, _)| *id != client_id);
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct MemoryClientTransport {
+    pub client_id: ClientId,
+    link: MemoryLink,
+}
+
+impl MemoryClientTransport {
+    /// Marks `client` connected, there is no handshake in memory.
+    pub fn new(client: &mut RenetClient, client_id: ClientId, link: MemoryLink) -> Self {
+        client.set_connected();
+        Self { client_id, link }
+    }
+}
+
+fn memory_server_receive_system(
+    mut server: ResMut<RenetServer>,
+    transport: Res<MemoryServerTransport>,
+) {
+    for (client_id, link) in transport.links.iter() {
+        for packet in drain(&link.to_server) {
+            if let Err(e) = server.process_packet_from(&packet, *client_id) {
+                println!("Memory transport dropped packet from {}: {}", client_id, e);
+            }
+        }
+    }
+}
+
+fn memory_server_send_system(
+    mut server: ResMut<RenetServer>,
+    transport: Res<MemoryServerTransport>,
+) {
+    for (client_id, link) in transport.links.iter() {
+        if let Ok(packets) = server.get_packets_to_send(*client_id) {
+            link.to_client.lock().unwrap().extend(packets);
+        }
+    }
+}
+
+fn memory_client_receive_system(
+    mut client: ResMut<RenetClient>,
+    transport: Res<MemoryClientTransport>,
+) {
+    for packet in drain(&transport.link.to_client) {
+        client.process_packet(&packet);
+    }
+}
+
+fn memory_client_send_system(
+    mut client: ResMut<RenetClient>,
+    transport: Res<MemoryClientTransport>,
+) {
+    transport
+        .link
+        .to_server
+        .lock()
+        .unwrap()
+        .extend(client.get_packets_to_send());
+}
+
+/// Replaces `NetcodeServerPlugin`, needs `RenetServerPlugin` and a `MemoryServerTransport`.
+pub struct MemoryServerTransportPlugin;
+
+impl Plugin for MemoryServerTransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            memory_server_receive_system.after(RenetServerPlugin::update_system),
+        )
+        .add_systems(PostUpdate, memory_server_send_system);
+    }
+}
+
+/// Replaces `NetcodeClientPlugin`, needs `RenetClientPlugin` and a `MemoryClientTransport`.
+pub struct MemoryClientTransportPlugin;
+
+impl Plugin for MemoryClientTransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            memory_client_receive_system.after(RenetClientPlugin::update_system),
+        )
+        .add_systems(PostUpdate, memory_client_send_system);
+    }
+}
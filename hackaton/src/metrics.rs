@@ -0,0 +1,209 @@
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::{Res, ResMut, Resource};
+
+/// Upper bounds in seconds of the tick duration buckets.
+pub const TICK_BUCKETS: [f64; 9] = [0.001, 0.002, 0.004, 0.008, 0.016, 0.033, 0.05, 0.1, 0.25];
+/// Seconds between overrun log lines and metrics endpoint refreshes.
+pub const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Cumulative histogram in the Prometheus text format.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// One more than `bounds`, the last one counts values above all of them.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let i = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[i] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+/// Plain text metrics served over http on a background thread, every request
+/// gets the last published body whatever its path.
+#[derive(Debug, Clone, Resource)]
+pub struct MetricsEndpoint {
+    body: Arc<Mutex<String>>,
+}
+
+impl MetricsEndpoint {
+    pub fn start(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let body = Arc::new(Mutex::new(String::new()));
+        let shared = body.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let body = shared.lock().map(|body| body.clone()).unwrap_or_default();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        Ok(Self { body })
+    }
+
+    /// `APP_METRICS_ADDR`, like `0.0.0.0:9100`, the endpoint is off when unset.
+    pub fn from_env() -> Option<Self> {
+        let addr = std::env::var("APP_METRICS_ADDR").ok()?;
+        match Self::start(&addr) {
+            Ok(endpoint) => {
+                println!("Metrics on http://{addr}/metrics");
+                Some(endpoint)
+            }
+            Err(e) => {
+                println!("Could not serve metrics on {addr}: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn publish(&self, body: String) {
+        if let Ok(mut shared) = self.body.lock() {
+            *shared = body;
+        }
+    }
+}
+
+/// Wall time of each main schedule run on the server, from `First` to `Last`.
+#[derive(Debug, Resource)]
+pub struct TickMetrics {
+    /// Ticks longer than this count as overruns.
+    pub budget: Duration,
+    pub histogram: Histogram,
+    pub overruns: u64,
+    /// Overruns since the last log line.
+    recent_overruns: u64,
+    longest_recent: Duration,
+    started: Option<Instant>,
+    reported: Instant,
+}
+
+impl TickMetrics {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            histogram: Histogram::new(&TICK_BUCKETS),
+            overruns: 0,
+            recent_overruns: 0,
+            longest_recent: Duration::ZERO,
+            started: None,
+            reported: Instant::now(),
+        }
+    }
+
+    /// `APP_TICK_BUDGET_MS` or `default_budget`.
+    pub fn from_env(default_budget: Duration) -> Self {
+        let budget = std::env::var("APP_TICK_BUDGET_MS")
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .map_or(default_budget, Duration::from_millis);
+        Self::new(budget)
+    }
+
+    pub fn record(&mut self, tick: Duration) {
+        self.histogram.observe(tick.as_secs_f64());
+        if tick > self.budget {
+            self.overruns += 1;
+            self.recent_overruns += 1;
+            self.longest_recent = self.longest_recent.max(tick);
+        }
+    }
+
+    pub fn render(&self, out: &mut String) {
+        self.histogram
+            .render("server_tick_seconds", "Wall time of one server tick.", out);
+        let _ = writeln!(
+            out,
+            "# HELP server_tick_overruns_total Ticks longer than the budget."
+        );
+        let _ = writeln!(out, "# TYPE server_tick_overruns_total counter");
+        let _ = writeln!(out, "server_tick_overruns_total {}", self.overruns);
+        let _ = writeln!(out, "# HELP server_tick_budget_seconds Tick budget.");
+        let _ = writeln!(out, "# TYPE server_tick_budget_seconds gauge");
+        let _ = writeln!(
+            out,
+            "server_tick_budget_seconds {}",
+            self.budget.as_secs_f64()
+        );
+    }
+}
+
+pub fn tick_start_system(mut metrics: ResMut<TickMetrics>) {
+    metrics.started = Some(Instant::now());
+}
+
+/// Records the tick, logs overruns at most once per `METRICS_INTERVAL`
+/// and refreshes the metrics endpoint when there is one.
+pub fn tick_end_system(mut metrics: ResMut<TickMetrics>, endpoint: Option<Res<MetricsEndpoint>>) {
+    let Some(started) = metrics.started.take() else {
+        return;
+    };
+    metrics.record(started.elapsed());
+    if metrics.reported.elapsed() < METRICS_INTERVAL {
+        return;
+    }
+    metrics.reported = Instant::now();
+    if metrics.recent_overruns > 0 {
+        println!(
+            "{} ticks over the {:.1}ms budget, longest {:.1}ms.",
+            metrics.recent_overruns,
+            metrics.budget.as_secs_f64() * 1000.,
+            metrics.longest_recent.as_secs_f64() * 1000.
+        );
+        metrics.recent_overruns = 0;
+        metrics.longest_recent = Duration::ZERO;
+    }
+    if let Some(endpoint) = endpoint {
+        let mut body = String::new();
+        metrics.render(&mut body);
+        endpoint.publish(body);
+    }
+}
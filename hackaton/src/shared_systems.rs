@@ -3,39 +3,58 @@ use std::f32::consts::PI;
 use bevy::{
     asset::Assets,
     color::Color,
-    math::{Quat, Vec3},
+    math::{Quat, Vec2, Vec3},
     pbr::{DirectionalLight, DirectionalLightBundle, PbrBundle, StandardMaterial},
-    prelude::{Commands, Cuboid, Mesh, ResMut, Transform, TransformBundle},
-};
-use bevy_garage_car::STATIC_GROUP;
-use bevy_rapier3d::prelude::{
-    Collider, ColliderScale, CollisionGroups, Friction, Group, Restitution, RigidBody,
+    prelude::{Commands, Cuboid, Mesh, Res, ResMut, Resource, Transform},
 };
+use bevy_garage_track::{GroundConfig, GroundShape, spawn_ground};
+
+/// Ground under the hackaton track. Server and client both build their ground
+/// from it, a mismatch would make predicted cars drive on a different world.
+#[derive(Debug, Clone, PartialEq, Resource)]
+pub struct LevelConfig {
+    /// Extent along x and z.
+    pub ground_size: Vec2,
+    /// Center of the ground surface.
+    pub ground_translation: Vec3,
+    pub ground_color: Color,
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        Self {
+            ground_size: Vec2::splat(1000.),
+            ground_translation: Vec3::new(0., -0.5, 0.),
+            ground_color: Color::srgb(0.3, 0.5, 0.3),
+        }
+    }
+}
+
+impl LevelConfig {
+    pub fn ground(&self) -> GroundConfig {
+        GroundConfig {
+            shape: GroundShape::Flat,
+            size: self.ground_size,
+            translation: self.ground_translation,
+            ..Default::default()
+        }
+    }
+}
 
 pub fn setup_level(
     mut cmd: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    level: Res<LevelConfig>,
 ) {
-    let size = 1000.;
-    let cuboid = Collider::cuboid(size / 2., 0.5, size / 2.);
-    let transform = Transform::from_xyz(0.0, -1.0, 0.0);
-
-    let mut cuboid_cmd = cmd.spawn((
-        cuboid,
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(3.),
-        Restitution::coefficient(0.),
-    ));
-    cuboid_cmd.insert(PbrBundle {
-        mesh: meshes.add(Mesh::from(Cuboid::new(size, 1., size))),
-        material: materials.add(Color::srgb(0.3, 0.5, 0.3)),
-        transform,
+    let ground = level.ground();
+    spawn_ground(&mut cmd, &ground);
+    cmd.spawn(PbrBundle {
+        mesh: meshes.add(Mesh::from(Cuboid::new(ground.size.x, 1., ground.size.y))),
+        material: materials.add(level.ground_color),
+        transform: Transform::from_translation(ground.translation - Vec3::Y * 0.5),
         ..Default::default()
     });
-    cuboid_cmd.insert(TransformBundle::from_transform(transform));
 
     cmd.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
@@ -50,3 +69,63 @@ pub fn setup_level(
         ..Default::default()
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{ecs::system::RunSystemOnce, prelude::World};
+    use bevy_rapier3d::prelude::{Collider, Friction, Restitution};
+
+    /// Local bounds, pose, friction and restitution of every collider `setup_level` spawns.
+    fn ground_colliders(level: &LevelConfig) -> Vec<(Vec3, Vec3, Transform, f32, f32)> {
+        let mut world = World::new();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<StandardMaterial>>();
+        world.insert_resource(level.clone());
+        world.run_system_once(setup_level);
+        world
+            .query::<(&Collider, &Transform, &Friction, &Restitution)>()
+            .iter(&world)
+            .map(|(collider, transform, friction, restitution)| {
+                let aabb = collider.raw.compute_local_aabb();
+                (
+                    aabb.mins.into(),
+                    aabb.maxs.into(),
+                    *transform,
+                    friction.coefficient,
+                    restitution.coefficient,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn server_and_client_build_the_same_ground() {
+        let level = LevelConfig {
+            ground_size: Vec2::new(400., 250.),
+            ground_translation: Vec3::new(3., -0.2, -7.),
+            ..Default::default()
+        };
+        let server = ground_colliders(&level);
+        let client = ground_colliders(&level);
+        assert_eq!(server.len(), 1);
+        assert_eq!(server, client);
+
+        let (mins, maxs, transform, ..) = server[0];
+        assert_eq!(maxs - mins, Vec3::new(400., 1., 250.));
+        // the collider top is the ground surface
+        assert_eq!(transform.translation.y + maxs.y, level.ground_translation.y);
+    }
+
+    #[test]
+    fn another_level_builds_another_ground() {
+        let other = LevelConfig {
+            ground_size: Vec2::splat(500.),
+            ..Default::default()
+        };
+        assert_ne!(
+            ground_colliders(&LevelConfig::default()),
+            ground_colliders(&other)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::prelude::{Entity, GlobalTransform, Query, Transform, With};
+use bevy_garage_car::{CarWheels, Wheel};
+
+use crate::{NetworkedEntities, Player};
+
+/// Poses of every player car and its wheels, cars with a missing wheel are left out.
+/// Free of `App` state so benchmarks can drive it from a bare `World`.
+pub fn networked_entities(
+    cars: &Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: &Query<&GlobalTransform, With<Wheel>>,
+) -> NetworkedEntities {
+    let mut networked_entities = NetworkedEntities::default();
+    for (entity, transform, car_wheels) in cars.iter() {
+        let wheel_poses: Result<(Vec<[f32; 3]>, Vec<[f32; 4]>), _> = match car_wheels.four() {
+            // the common car, a single lookup without collecting the transforms first
+            Some(four) => wheels
+                .get_many(four)
+                .map(|tfs| tfs.map(wheel_pose).into_iter().unzip()),
+            None => car_wheels
+                .entities
+                .iter()
+                .map(|wheel| wheels.get(*wheel).map(wheel_pose))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|poses| poses.into_iter().unzip()),
+        };
+        let Ok((wheel_positions, wheel_orientations)) = wheel_poses else {
+            continue;
+        };
+        networked_entities.entities.push(entity);
+        networked_entities
+            .positions
+            .push(transform.translation.into());
+        networked_entities
+            .orientations
+            .push(transform.rotation.into());
+        networked_entities.wheel_positions.push(wheel_positions);
+        networked_entities
+            .wheel_orientations
+            .push(wheel_orientations);
+    }
+    networked_entities
+}
+
+fn wheel_pose(transform: &GlobalTransform) -> ([f32; 3], [f32; 4]) {
+    let transform = transform.compute_transform();
+    (transform.translation.into(), transform.rotation.into())
+}
+
+/// Unreliable snapshot payload for `ServerChannel::NetworkedEntities`,
+/// `acked_input` gives the last applied input sequence of a car.
+pub fn sync_message(
+    cars: &Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: &Query<&GlobalTransform, With<Wheel>>,
+    tick: u64,
+    sequence: u64,
+    acked_input: impl Fn(Entity) -> u64,
+) -> Vec<u8> {
+    let mut networked_entities = networked_entities(cars, wheels);
+    networked_entities.tick = tick;
+    networked_entities.sequence = sequence;
+    networked_entities.acked_inputs = networked_entities
+        .entities
+        .iter()
+        .map(|entity| acked_input(*entity))
+        .collect();
+    bincode::serialize(&networked_entities).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::system::SystemState,
+        prelude::{Vec3, World},
+    };
+
+    /// Player car at the origin with a wheel at each of `offsets`.
+    fn spawn_car(world: &mut World, id: u64, offsets: &[Vec3]) -> Entity {
+        let wheels = offsets
+            .iter()
+            .enumerate()
+            .map(|(w, offset)| {
+                world
+                    .spawn((
+                        Wheel {
+                            radius: 0.35,
+                            width: 0.25,
+                            front: w == 0,
+                            left: offset.x < 0.,
+                            border_radius: 0.1,
+                        },
+                        GlobalTransform::from_translation(*offset),
+                    ))
+                    .id()
+            })
+            .collect();
+        world
+            .spawn((Player { id }, Transform::default(), CarWheels::new(wheels)))
+            .id()
+    }
+
+    type SyncState = SystemState<(
+        Query<'static, 'static, (Entity, &'static Transform, &'static CarWheels), With<Player>>,
+        Query<'static, 'static, &'static GlobalTransform, With<Wheel>>,
+    )>;
+
+    fn snapshot(world: &mut World) -> NetworkedEntities {
+        let mut state = SyncState::new(world);
+        let (cars, wheels) = state.get(world);
+        networked_entities(&cars, &wheels)
+    }
+
+    #[test]
+    fn four_and_three_wheelers_send_their_wheels_in_order() {
+        let mut world = World::new();
+        let four = [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z];
+        let three = [Vec3::Z, Vec3::new(1., 0., -1.), Vec3::new(-1., 0., -1.)];
+        let car_4 = spawn_car(&mut world, 1, &four);
+        let car_3 = spawn_car(&mut world, 2, &three);
+
+        let snapshot = snapshot(&mut world);
+        for (car, offsets) in [(car_4, &four[..]), (car_3, &three[..])] {
+            let i = snapshot.entities.iter().position(|e| *e == car).unwrap();
+            let expected: Vec<[f32; 3]> = offsets.iter().map(|o| (*o).into()).collect();
+            assert_eq!(snapshot.wheel_positions[i], expected);
+            assert_eq!(snapshot.wheel_orientations[i].len(), offsets.len());
+        }
+    }
+
+    #[test]
+    fn car_with_a_missing_wheel_is_left_out() {
+        let mut world = World::new();
+        let car = spawn_car(&mut world, 1, &[Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z]);
+        let wheel = world.get::<CarWheels>(car).unwrap().entities[2];
+        world.despawn(wheel);
+
+        assert!(snapshot(&mut world).entities.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! A server and a client app in one process over `memory_transport`, stepped by
+//! hand: the client connects and ends up with the server's car poses every frame.
+//!
+//! `cargo test -p hackaton --test memory_sync`
+
+use bevy::{
+    MinimalPlugins,
+    app::{App, Update},
+    prelude::{
+        Entity, GlobalTransform, IntoSystemConfigs, Mut, Quat, Query, ResMut, Resource, Transform,
+        Vec3, With,
+    },
+};
+use bevy_garage_car::{CarWheels, Wheel};
+use bevy_renet::{
+    RenetClientPlugin, RenetServerPlugin,
+    renet::{ClientId, RenetClient, RenetServer},
+};
+use hackaton::{
+    NetworkedEntities, Player, ServerChannel, ServerTick, connection_config,
+    memory_transport::{
+        MemoryClientTransport, MemoryClientTransportPlugin, MemoryServerTransport,
+        MemoryServerTransportPlugin,
+    },
+    sync::sync_message,
+};
+
+const CLIENT_ID: u64 = 7;
+const FRAMES: u64 = 30;
+
+fn drive_system(mut cars: Query<&mut Transform, With<Player>>) {
+    for mut transform in cars.iter_mut() {
+        transform.translation.z += 0.5;
+        transform.rotate_y(0.01);
+    }
+}
+
+fn sync_system(
+    mut server: ResMut<RenetServer>,
+    mut tick: ResMut<ServerTick>,
+    cars: Query<(Entity, &Transform, &CarWheels), With<Player>>,
+    wheels: Query<&GlobalTransform, With<Wheel>>,
+) {
+    tick.0 += 1;
+    let message = sync_message(&cars, &wheels, 0, tick.0, |_| 0);
+    server.broadcast_message(ServerChannel::NetworkedEntities, message);
+}
+
+#[derive(Debug, Default, Resource)]
+struct Received(Vec<NetworkedEntities>);
+
+fn receive_system(mut client: ResMut<RenetClient>, mut received: ResMut<Received>) {
+    while let Some(message) = client.receive_message(ServerChannel::NetworkedEntities) {
+        received.0.push(bincode::deserialize(&message).unwrap());
+    }
+}
+
+fn server_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        RenetServerPlugin,
+        MemoryServerTransportPlugin,
+    ));
+    app.insert_resource(RenetServer::new(connection_config()));
+    app.init_resource::<MemoryServerTransport>();
+    app.init_resource::<ServerTick>();
+    app.add_systems(Update, (drive_system, sync_system.after(drive_system)));
+    let world = app.world_mut();
+    let wheels = (0..4)
+        .map(|w| {
+            let offset = Vec3::new((w % 2) as f32 - 0.5, 0., (w / 2) as f32 - 0.5);
+            world
+                .spawn((
+                    Wheel {
+                        radius: 0.35,
+                        width: 0.25,
+                        front: w < 2,
+                        left: w % 2 == 0,
+                        border_radius: 0.1,
+                    },
+                    GlobalTransform::from(Transform::from_translation(offset)),
+                ))
+                .id()
+        })
+        .collect();
+    world.spawn((
+        Player { id: CLIENT_ID },
+        Transform::from_xyz(3., 0.5, -20.).with_rotation(Quat::from_rotation_y(0.4)),
+        CarWheels::new(wheels),
+    ));
+    app
+}
+
+fn client_app(server: &mut App) -> App {
+    let client_id = ClientId::from_raw(CLIENT_ID);
+    let link =
+        server
+            .world_mut()
+            .resource_scope(|world, mut transport: Mut<MemoryServerTransport>| {
+                transport.connect(&mut world.resource_mut::<RenetServer>(), client_id)
+            });
+    let mut client = RenetClient::new(connection_config());
+    let transport = MemoryClientTransport::new(&mut client, client_id, link);
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        RenetClientPlugin,
+        MemoryClientTransportPlugin,
+    ));
+    app.insert_resource(client).insert_resource(transport);
+    app.init_resource::<Received>();
+    app.add_systems(Update, receive_system);
+    app
+}
+
+fn car_pose(app: &mut App) -> Transform {
+    *app.world_mut()
+        .query_filtered::<&Transform, With<Player>>()
+        .single(app.world())
+}
+
+#[test]
+fn client_gets_every_server_snapshot_in_order() {
+    let mut server = server_app();
+    let mut client = client_app(&mut server);
+
+    for frame in 1..=FRAMES {
+        server.update();
+        client.update();
+
+        assert_eq!(
+            server.world().resource::<RenetServer>().connected_clients(),
+            1
+        );
+        assert!(client.world().resource::<RenetClient>().is_connected());
+        let pose = car_pose(&mut server);
+        let received = &client.world().resource::<Received>().0;
+        assert_eq!(
+            received.len() as u64,
+            frame,
+            "one snapshot per server frame"
+        );
+        let snapshot = received.last().unwrap();
+        assert_eq!(snapshot.sequence, frame);
+        assert_eq!(snapshot.positions, vec![<[f32; 3]>::from(pose.translation)]);
+        assert_eq!(snapshot.orientations, vec![<[f32; 4]>::from(pose.rotation)]);
+        assert_eq!(snapshot.wheel_positions[0].len(), 4);
+    }
+}
+
+#[test]
+fn two_runs_receive_the_same_bytes() {
+    let run = || {
+        let mut server = server_app();
+        let mut client = client_app(&mut server);
+        for _ in 0..FRAMES {
+            server.update();
+            client.update();
+        }
+        client
+            .world()
+            .resource::<Received>()
+            .0
+            .iter()
+            .map(|snapshot| bincode::serialize(snapshot).unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(run(), run());
+}
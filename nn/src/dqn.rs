@@ -1,13 +1,14 @@
-use crate::{dqn_bevy::*, gradient::get_sgd, params::*, util::*};
+use crate::{dqn_bevy::*, gradient::get_sgd, params::*, replay::*, reward::*, util::*};
 use bevy::prelude::*;
 use bevy_garage_car::{
     sensor::CarSensors,
-    CarWheels, {Car, Player},
+    CarWheels, PhysicsParams, ROAD_GROUP, {Car, Player},
 };
 use bevy_garage_track::{CarTrack, SpawnCarOnTrackEvent};
 use bevy_rapier3d::prelude::*;
 use dfdx::prelude::*;
 use rand::Rng;
+use std::collections::HashMap;
 
 #[cfg(target_arch = "wasm32")]
 pub type QNetwork = (
@@ -24,6 +25,64 @@ pub type QNetwork = (
 );
 pub type QNetworkBuilt = <QNetwork as BuildOnDevice<AutoDevice, f32>>::Built;
 pub type Observation = [f32; STATE_SIZE];
+pub type Frame = [f32; FRAME_SIZE];
+
+/// Value of each batch next state, the target network maximum or with `double`
+/// the target network value of the action the online network picks.
+pub fn next_state_values(
+    qn: &QNetworkBuilt,
+    tqn: &QNetworkBuilt,
+    sn: &BatchTensor2D<STATE_SIZE>,
+    double: bool,
+    device: &AutoDevice,
+) -> BatchTensor1D {
+    let next_q_values: BatchTensor2D<ACTIONS> = tqn.forward(sn.clone());
+    if !double {
+        return next_q_values.max();
+    }
+    let online_q_values: BatchTensor2D<ACTIONS> = qn.forward(sn.clone());
+    let next_actions: Vec<usize> = online_q_values
+        .as_vec()
+        .chunks_exact(ACTIONS)
+        .map(|q| {
+            q.iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(0, |(i, _)| i)
+        })
+        .collect();
+    let batch_size = next_actions.len();
+    let next_actions: BatchTensor1DUsize = device.tensor_from_vec(next_actions, (batch_size,));
+    next_q_values.select(next_actions)
+}
+
+/// Seconds the physics simulates in a frame of `delta` seconds under `mode`.
+pub fn simulated_seconds(mode: TimestepMode, delta: f32) -> f32 {
+    match mode {
+        TimestepMode::Fixed { dt, .. } => dt,
+        TimestepMode::Variable {
+            max_dt, time_scale, ..
+        } => (delta * time_scale).min(max_dt),
+        TimestepMode::Interpolated { time_scale, .. } => delta * time_scale,
+    }
+}
+
+/// Counts `PhysicsParams::max_dt` steps of simulated time for `DqnStepMode::Ticks`,
+/// so a long frame or fast-forward counts more ticks and a paused one none.
+pub fn dqn_ticks_system(
+    time: Res<Time>,
+    rapier_config: Res<RapierConfiguration>,
+    physics_params: Res<PhysicsParams>,
+    mut dqn: ResMut<DqnResource>,
+) {
+    if !rapier_config.physics_pipeline_active {
+        return;
+    }
+    dqn.physics_seconds +=
+        simulated_seconds(rapier_config.timestep_mode, time.delta_seconds()) as f64;
+    // a thousandth of a tick absorbs the rounding of f32 frame times
+    dqn.ticks = (dqn.physics_seconds / physics_params.max_dt as f64 + 1e-3) as usize;
+}
 
 pub fn dqn_system(
     time: Res<Time>,
@@ -41,42 +100,77 @@ pub fn dqn_system(
         &mut CarDqn,
         &mut CarWheels,
     )>,
-    q_colliding_entities: Query<&CollidingEntities, With<CollidingEntities>>,
+    // grouped to stay within the system parameter limit
+    (mut contact_forces, colliders): (
+        EventReader<ContactForceEvent>,
+        Query<(Option<&Parent>, Has<Car>, Option<&CollisionGroups>)>,
+    ),
     mut cmd: Commands,
     mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>,
+    mut metrics: ResMut<DqnMetrics>,
+    config: Res<DqnConfig>,
+    reward_fn: Res<DqnReward>,
+    mut step_log: Option<ResMut<MetricsLogger>>,
     #[cfg(feature = "api")] api: Res<crate::api_client::ApiClient>,
 ) {
     let seconds = time.elapsed_seconds_f64();
-    if dqn.respawn_in > 0. && seconds > dqn.respawn_in {
+    // crashed cars come back on the frame after, the others keep driving
+    dqn.respawns.retain(|respawn| {
+        if seconds <= respawn.at {
+            return true;
+        }
         car_spawn_events.send(SpawnCarOnTrackEvent {
-            player: dqn.respawn_player,
-            index: dqn.respawn_index,
+            player: respawn.player,
+            index: respawn.index,
             position: None,
         });
-        dqn.respawn_in = 0.;
-        dqn.respawn_player = false;
-        dqn.respawn_index = 0;
-        dqn.use_nn = true;
-        return;
+        false
+    });
+    let should_act: bool = match dqn.step_mode {
+        DqnStepMode::Seconds => seconds > dqn.seconds,
+        DqnStepMode::Ticks(_) => dqn.ticks >= dqn.next_tick,
     };
-    let should_act: bool = seconds > dqn.seconds;
     if should_act && dqn.use_nn {
-        dqn.seconds = seconds + STEP_DURATION;
+        match dqn.step_mode {
+            DqnStepMode::Seconds => dqn.seconds = seconds + STEP_DURATION,
+            DqnStepMode::Ticks(step_ticks) => dqn.next_tick = dqn.ticks + step_ticks.max(1),
+        }
         dqn.step += 1;
     }
 
+    // colliders are on the car body or its children
+    let is_car = |e: Entity| colliders.get(e).is_ok_and(|(_, is_car, _)| is_car);
+    let car_of = |e: Entity| match colliders.get(e) {
+        Ok((_, true, _)) => Some(e),
+        Ok((Some(parent), false, _)) => Some(parent.get()).filter(|&parent| is_car(parent)),
+        _ => None,
+    };
+    let is_road = |e: Entity| {
+        colliders
+            .get(e)
+            .is_ok_and(|(_, _, groups)| groups.is_some_and(|g| g.memberships.contains(ROAD_GROUP)))
+    };
+    let mut impacts: HashMap<Entity, f32> = HashMap::new();
+    for event in contact_forces.read() {
+        for (car, other) in [
+            (event.collider1, event.collider2),
+            (event.collider2, event.collider1),
+        ] {
+            let Some(car) = car_of(car).filter(|_| !is_road(other)) else {
+                continue;
+            };
+            let impact = impacts.entry(car).or_default();
+            *impact = impact.max(event.total_force_magnitude);
+        }
+    }
+
     for (mut car, car_track, car_sensors, v, tr, e, hid, mut car_dqn, mut wheels) in
         q_car.iter_mut()
     {
         let player = hid.is_some();
-        let mut crash: bool = false;
-
-        let colliding_entities = q_colliding_entities.get(e);
-        if let Ok(colliding_entities) = colliding_entities {
-            for _ in colliding_entities.iter() {
-                crash = true;
-            }
-        }
+        let impact = impacts.get(&e).copied().unwrap_or_default();
+        // lying on its roof it only touches the road, which is no crash by force
+        let crash: bool = impact >= CRASH_FORCE || tr.up().y < 0.;
 
         let mut vel_angle = car_track.line_dir.angle_between(v.linvel);
         if vel_angle.is_nan() {
@@ -89,48 +183,46 @@ pub fn dqn_system(
         }
         let vel_cos = vel_angle.cos();
         let pos_cos = pos_angle.cos();
+        if dqn.reset_eps_on_flip && dqn.use_nn && should_act && pos_cos < 0. {
+            // facing against the track, explore again
+            dqn.eps = dqn.max_eps;
+        }
         let mut d_from_center = car_track.line_pos - tr.translation;
         d_from_center.y = 0.;
         let d = d_from_center.length();
         let d_norm = d / 4.;
 
         let velocity = v.linvel.length();
-        let mut velocity_reward = velocity / car_dqn.max_speed;
-        if velocity_reward > 1. {
-            // reduce reward when it's over desired speed
-            velocity_reward = 1. - (velocity_reward - 1.) / velocity_reward;
-        }
-        let shape_reward = || -> f32 {
-            if crash {
-                return -1.;
-            }
-            // https://team.inria.fr/rits/files/2018/02/ICRA18_EndToEndDriving_CameraReady.pdf
-            // In [13] the reward is computed as a function of the difference of angle α between the road and car’s heading and the speed v.
-            // R = v(cos α − d)
-            let mut reward = velocity_reward * (vel_cos - d_norm);
-            if vel_cos.is_sign_positive() && pos_cos.is_sign_negative() && reward.is_sign_positive()
-            {
-                // going backward
-                reward = -reward;
-            }
-            if reward.is_nan() {
-                return 0.;
-            }
-            return reward;
-        };
-        let reward = shape_reward();
-        let mut obs: Observation = [0.; STATE_SIZE];
-        for i in 0..STATE_SIZE {
-            obs[i] = match i {
+        let reward = reward_fn.reward(&RewardContext {
+            progress: car_track.total_meters,
+            prev_progress: car_dqn.prev_meters,
+            mps: velocity,
+            max_speed: car_dqn.max_speed,
+            crashed: crash,
+            impact,
+            angle: vel_angle,
+            heading_cos: pos_cos,
+            center_distance: d_norm,
+            steering: car.steering,
+            prev_steering: car.prev_steering,
+        });
+        let mut frame: Frame = [0.; FRAME_SIZE];
+        for i in 0..FRAME_SIZE {
+            frame[i] = match i {
                 0 => velocity,
                 1 => v.angvel.y,
                 2 => d_norm,
                 3 => vel_cos,
                 4 => pos_cos,
-                STATE_SIZE_BASE..=STATE_SIZE => car_sensors.sensor_inputs[i - STATE_SIZE_BASE],
+                STATE_SIZE_BASE..=FRAME_SIZE => car_sensors.sensor_inputs[i - STATE_SIZE_BASE],
                 _ => panic!("unknown observation record"),
             };
         }
+        let raw_obs = car_dqn.observe(&frame);
+        if dqn.use_nn && should_act && !crash {
+            dqn.normalizer.update(&raw_obs);
+        }
+        let obs = dqn.normalizer.normalize(&raw_obs);
 
         let (prev_action, prev_obs) = (car_dqn.prev_action, car_dqn.prev_obs);
         if dqn.use_nn && (should_act || crash) && !prev_obs.iter().all(|&x| x == 0.) {
@@ -141,127 +233,232 @@ pub fn dqn_system(
             }
         }
 
-        let (action, _) = cars_dqn.act(obs, dqn.eps);
+        let (action, random) = cars_dqn.act(obs, dqn.eps, &mut car_dqn.rng);
+        if let (Some(logger), true) = (step_log.as_mut(), should_act && dqn.use_nn) {
+            let row = StepLogRow {
+                step: dqn.step,
+                car: car_track.index,
+                epsilon: dqn.eps,
+                reward,
+                meters: car_track.total_meters,
+                random,
+            };
+            logger.log(&row, &metrics, &dqn);
+        }
         if should_act && !crash {
+            car_dqn.push_frame(frame);
             car_dqn.prev_obs = obs;
             car_dqn.prev_action = action;
             car_dqn.prev_reward = reward;
+            car_dqn.prev_meters = car_track.total_meters;
+            if dqn.use_nn {
+                car_dqn.episode_reward += reward;
+                car_dqn.episode.push((obs, action, reward));
+            }
         }
         if !dqn.use_nn {
-            return;
+            continue;
         }
         if crash {
+            metrics.push_episode_reward(car_dqn.episode_reward);
+            if car_dqn.episode_reward > dqn.best_reward {
+                dqn.best_reward = car_dqn.episode_reward;
+                dqn.best_meters = car_track.total_meters;
+                cars_dqn.best_qn = Some(cars_dqn.qn.clone());
+                #[cfg(not(target_arch = "wasm32"))]
+                save_episode(&best_episode_path(), &car_dqn.episode);
+            }
             dqn.crashes += 1;
-            dqn.respawn_in = seconds;
-            dqn.respawn_player = player;
-            dqn.respawn_index = car_track.index;
+            dqn.respawns.push(DqnRespawn {
+                at: seconds,
+                player,
+                index: car_track.index,
+            });
             cmd.entity(e).despawn_recursive();
             wheels.despawn(&mut cmd);
-            dqn.use_nn = false;
+            continue;
+        }
+        if !should_act {
+            continue;
         }
-        if !dqn.use_nn || !should_act || crash {
-            return;
+        if player && dqn.rb.len() < config.batch_size {
+            log_action_reward(car_dqn.prev_action, reward);
         }
 
-        if let Some(_hid) = hid {
-            let rb_len = dqn.rb.len();
-            if rb_len < BATCH_SIZE {
-                log_action_reward(car_dqn.prev_action, reward);
-            } else if !cars_dqn.processing {
-                cars_dqn.processing = true;
-                let mut rng = rand::thread_rng();
-                let batch_indexes = [(); BATCH_SIZE].map(|_| rng.gen_range(0..dqn.rb.len()));
-                let (s, a, r, sn, done) = dqn
-                    .rb
-                    .get_batch_tensors(batch_indexes, cars_dqn.device.clone());
+        let (gas, brake, left, right) = map_action_to_car(action);
+        car.set_controls(gas, brake, -left + right);
+    }
+
+    // every car fills the shared replay buffer, the network learns once per step
+    let batch_size = config.batch_size.max(1);
+    if should_act && dqn.use_nn && dqn.rb.len() >= batch_size && !cars_dqn.processing {
+        train_step(&mut dqn, &mut cars_dqn, &dqn_tx, &config);
+    }
+}
 
-                let tqn = cars_dqn.tqn.clone();
-                let mut qn = cars_dqn.qn.clone();
-                let gradients = cars_dqn.gradients.clone();
-                let dqn_tx = dqn_tx.clone();
+/// Samples a batch and trains a copy of the online network on it, off the main
+/// thread on native, the result comes back as a `DqnEvent`.
+fn train_step(
+    dqn: &mut DqnResource,
+    cars_dqn: &mut CarsDqnResource,
+    dqn_tx: &DqnTx,
+    config: &DqnConfig,
+) {
+    let batch_size = config.batch_size.max(1);
+    cars_dqn.processing = true;
+    let rb_len = dqn.rb.len();
+    let batch_indexes: Vec<usize> = (0..batch_size)
+        .map(|_| dqn.batch_rng.gen_range(0..rb_len))
+        .collect();
+    let (s, a, r, sn, done) = dqn
+        .rb
+        .get_batch_tensors(&batch_indexes, cars_dqn.device.clone());
+    let DqnConfig {
+        gamma,
+        learning_rate,
+        epochs,
+        ..
+    } = *config;
 
-                #[cfg(target_arch = "wasm32")]
-                {
-                    let mut loss_string: String = String::from("");
-                    let mut sgd = get_sgd(&qn);
-                    for _i_epoch in 0..EPOCHS {
-                        let next_q_values: Tensor2D<BATCH_SIZE, ACTIONS> = tqn.forward(sn.clone());
-                        let max_next_q: Tensor1D<BATCH_SIZE> = next_q_values.max();
-                        let target_q = (max_next_q * (-done.clone() + 1.0)) * 0.99 + r.clone();
+    let tqn = cars_dqn.tqn.clone();
+    let device = cars_dqn.device.clone();
+    let double = dqn.use_double_dqn;
+    let mut qn = cars_dqn.qn.clone();
+    let gradients = cars_dqn.gradients.clone();
+    let dqn_tx = dqn_tx.clone();
 
-                        // forward through model, computing gradients
-                        let q_values = qn.forward(s.trace(gradients.clone()));
-                        let action_qs = q_values.select(a.clone());
+    #[cfg(target_arch = "wasm32")]
+    {
+        let mut loss_string: String = String::from("");
+        let mut loss_sum = 0.;
+        let mut sgd = get_sgd(&qn, learning_rate);
+        for _i_epoch in 0..epochs {
+            let next_q = next_state_values(&qn, &tqn, &sn, double, &device);
+            let target_q = (next_q * (-done.clone() + 1.0)) * gamma + r.clone();
 
-                        let loss = huber_loss(action_qs, target_q, 1.);
-                        let loss_v = loss.array();
-                        // run backprop
-                        let gradients = loss.backward();
-                        sgd.update(&mut qn, &gradients).expect("Unused params");
-                        if _i_epoch % 10 == 0 {
-                            loss_string.push_str(format!("{:.2} ", loss_v).as_str());
-                        }
-                    }
-                    dqn_tx
-                        .send(DqnX {
-                            loss_string,
-                            qn,
-                            duration_string: "-".to_string(),
-                        })
-                        .unwrap();
-                }
+            // forward through model, computing gradients
+            let q_values = qn.forward(s.trace(gradients.clone()));
+            let action_qs = q_values.select(a.clone());
 
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    std::thread::spawn(move || {
-                        let start = std::time::Instant::now();
-                        let mut loss_string: String = String::from("");
-                        let mut sgd = get_sgd(&qn);
-                        for _i_epoch in 0..EPOCHS {
-                            let next_q_values: Tensor2D<BATCH_SIZE, ACTIONS> =
-                                tqn.forward(sn.clone());
-                            let max_next_q: Tensor1D<BATCH_SIZE> = next_q_values.max();
-                            let target_q = (max_next_q * (-done.clone() + 1.0)) * 0.99 + r.clone();
+            let loss = huber_loss(action_qs, target_q, 1.);
+            let loss_v = loss.array();
+            loss_sum += loss_v;
+            // run backprop
+            let gradients = loss.backward();
+            sgd.update(&mut qn, &gradients).expect("Unused params");
+            if _i_epoch % 10 == 0 {
+                loss_string.push_str(format!("{:.2} ", loss_v).as_str());
+            }
+        }
+        dqn_tx
+            .send(DqnX {
+                loss_string,
+                // 0 epochs give NaN, which the metrics skip
+                loss: loss_sum / epochs as f32,
+                qn,
+                duration_string: "-".to_string(),
+            })
+            .unwrap();
+    }
 
-                            // forward through model, computing gradients
-                            let q_values = qn.forward(s.trace(gradients.clone()));
-                            let action_qs = q_values.select(a.clone());
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::thread::spawn(move || {
+            let start = std::time::Instant::now();
+            let mut loss_string: String = String::from("");
+            let mut loss_sum = 0.;
+            let mut sgd = get_sgd(&qn, learning_rate);
+            for _i_epoch in 0..epochs {
+                let next_q = next_state_values(&qn, &tqn, &sn, double, &device);
+                let target_q = (next_q * (-done.clone() + 1.0)) * gamma + r.clone();
 
-                            let loss = huber_loss(action_qs, target_q, 1.);
-                            let loss_v = loss.array();
-                            // run backprop
-                            let gradients = loss.backward();
-                            sgd.update(&mut qn, &gradients).expect("Unused params");
-                            if _i_epoch % 10 == 0 {
-                                loss_string.push_str(format!("{:.2} ", loss_v).as_str());
-                            }
-                        }
-                        let duration_string = start.elapsed().as_millis().to_string() + "ms";
-                        dqn_tx
-                            .send(DqnX {
-                                loss_string,
-                                qn,
-                                duration_string,
-                            })
-                            .unwrap();
-                    });
-                }
+                // forward through model, computing gradients
+                let q_values = qn.forward(s.trace(gradients.clone()));
+                let action_qs = q_values.select(a.clone());
 
-                if dqn.step % SYNC_INTERVAL_STEPS == 0 && dqn.rb.len() > BATCH_SIZE * 2 {
-                    dbg!("networks sync");
-                    cars_dqn.tqn = cars_dqn.qn.clone();
+                let loss = huber_loss(action_qs, target_q, 1.);
+                let loss_v = loss.array();
+                loss_sum += loss_v;
+                // run backprop
+                let gradients = loss.backward();
+                sgd.update(&mut qn, &gradients).expect("Unused params");
+                if _i_epoch % 10 == 0 {
+                    loss_string.push_str(format!("{:.2} ", loss_v).as_str());
                 }
-                dqn.eps = if dqn.eps <= dqn.min_eps {
-                    dqn.min_eps
-                } else {
-                    dqn.eps - DECAY
-                };
             }
+            let duration_string = start.elapsed().as_millis().to_string() + "ms";
+            dqn_tx
+                .send(DqnX {
+                    loss_string,
+                    // 0 epochs give NaN, which the metrics skip
+                    loss: loss_sum / epochs as f32,
+                    qn,
+                    duration_string,
+                })
+                .unwrap();
+        });
+    }
+
+    if dqn.rb.len() > batch_size * 2 && dqn.target_sync.sync(cars_dqn, dqn.step) {
+        dqn.syncs += 1;
+        if let DqnTargetSync::Hard(_) = dqn.target_sync {
+            dbg!("networks sync");
+        }
+    }
+    dqn.eps = dqn
+        .eps_schedule
+        .next(dqn.eps, dqn.min_eps, dqn.max_eps, dqn.step);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    fn ticks_after(mode: TimestepMode, frames: &[f32]) -> usize {
+        let mut world = World::new();
+        let mut rapier_config = RapierConfiguration::new(1.);
+        rapier_config.timestep_mode = mode;
+        world.insert_resource(rapier_config);
+        world.insert_resource(PhysicsParams::default());
+        world.insert_resource(DqnResource::default());
+        world.insert_resource(Time::<()>::default());
+        for frame in frames {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(*frame));
+            world.run_system_once(dqn_ticks_system);
         }
+        world.resource::<DqnResource>().ticks
+    }
 
-        let (gas, brake, left, right) = map_action_to_car(action);
-        car.gas = gas;
-        car.brake = brake;
-        car.steering = -left + right;
+    #[test]
+    fn ticks_count_simulated_steps_not_frames() {
+        let params = PhysicsParams::default();
+        let dt = params.max_dt;
+        // 120 fps, every frame simulates half a step
+        assert_eq!(ticks_after(params.timestep_mode(1.), &[dt / 2.; 8]), 4);
+        // a catch-up frame simulates several steps at once
+        let catch_up = params.catch_up_timestep_mode(1., dt * 3.);
+        assert_eq!(ticks_after(catch_up, &[dt * 3.; 2]), 6);
+        // a fixed step is one step per frame however long the frame
+        assert_eq!(ticks_after(params.fixed_timestep_mode(), &[dt * 3.; 2]), 2);
+    }
+
+    #[test]
+    fn paused_physics_counts_no_ticks() {
+        let mut world = World::new();
+        let mut rapier_config = RapierConfiguration::new(1.);
+        rapier_config.physics_pipeline_active = false;
+        world.insert_resource(rapier_config);
+        world.insert_resource(PhysicsParams::default());
+        world.insert_resource(DqnResource::default());
+        world.insert_resource(Time::<()>::default());
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        world.run_system_once(dqn_ticks_system);
+        assert_eq!(world.resource::<DqnResource>().ticks, 0);
     }
 }
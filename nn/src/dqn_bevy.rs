@@ -2,13 +2,18 @@ use crate::{
     dash::{TrainerEpsilonText, TrainerGenerationText, TrainerRewardsText},
     dqn::*,
     gradient::get_sgd,
+    normalize::RunningNormalizer,
     params::*,
     replay::ReplayBuffer,
 };
 use bevy::prelude::*;
+use bevy_garage_track::ResetCarOnTrackEvent;
 use crossbeam_channel::{bounded, Receiver, Sender};
 use dfdx::{optim::Sgd, prelude::*};
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
 
 #[derive(Component, Debug)]
 pub struct CarDqn {
@@ -16,114 +21,392 @@ pub struct CarDqn {
     pub prev_obs: Observation,
     pub prev_action: usize,
     pub prev_reward: f32,
+    /// Track meters at the last step, `RewardContext::prev_progress`.
+    pub prev_meters: f32,
+    pub episode_reward: f32,
+    pub episode: Vec<EpisodeStep>,
+    /// Frames of the last `FRAME_STACK - 1` steps, oldest first.
+    pub frames: VecDeque<Frame>,
+    /// Seed of `rng`, see `DqnResource::car_seed`.
+    pub seed: u64,
+    /// Exploration randomness of this car only.
+    pub rng: StdRng,
 }
 
 impl CarDqn {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
             max_speed: 100. * 1000. / 3600.,
             prev_obs: [0.; STATE_SIZE],
             prev_action: 0,
             prev_reward: 0.,
+            prev_meters: 0.,
+            episode_reward: 0.,
+            episode: Vec::new(),
+            frames: VecDeque::with_capacity(FRAME_STACK),
         }
     }
+
+    /// Observation of the current `frame` stacked after the remembered frames, oldest first.
+    /// Until enough steps are taken the oldest known frame fills the missing history.
+    pub fn observe(&self, frame: &Frame) -> Observation {
+        let mut obs: Observation = [0.; STATE_SIZE];
+        let history = FRAME_STACK - 1;
+        for (i, chunk) in obs.chunks_exact_mut(FRAME_SIZE).enumerate() {
+            let missing = history - self.frames.len();
+            let source = match i.checked_sub(missing) {
+                _ if i == history => frame,
+                Some(k) => &self.frames[k],
+                None => self.frames.front().unwrap_or(frame),
+            };
+            chunk.copy_from_slice(source);
+        }
+        obs
+    }
+
+    /// Remembers the frame of a taken step, forgetting frames older than the stack.
+    pub fn push_frame(&mut self, frame: Frame) {
+        if FRAME_STACK == 1 {
+            return;
+        }
+        if self.frames.len() == FRAME_STACK - 1 {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// Forgets the stacked frames and the last observation after a teleport,
+    /// so no frame or transition spans the jump.
+    pub fn clear_history(&mut self) {
+        self.frames.clear();
+        self.prev_obs = [0.; STATE_SIZE];
+    }
 }
 
+pub type EpisodeStep = (Observation, usize, f32);
+
 pub struct CarsDqnResource {
     pub processing: bool,
     pub qn: QNetworkBuilt,
     pub tqn: QNetworkBuilt,
+    /// Network snapshot taken at the end of the best episode so far.
+    pub best_qn: Option<QNetworkBuilt>,
     pub device: AutoDevice,
     pub gradients: Gradients<f32, Cpu>,
+    /// Go back to `best_qn` when the network starts answering NaN or infinite Q-values.
+    pub restore_on_divergence: bool,
 }
+
+/// Index of the highest Q-value, `None` when any of them is NaN or infinite.
+pub fn greedy_action(q_values: &[f32; ACTIONS]) -> Option<usize> {
+    if q_values.iter().any(|q| !q.is_finite()) {
+        return None;
+    }
+    q_values
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
+
 impl CarsDqnResource {
-    pub fn act(&self, obs: Observation, epsilon: f32) -> (usize, bool) {
+    pub fn act(&mut self, obs: Observation, epsilon: f32, rng: &mut impl Rng) -> (usize, bool) {
         let obs_state_tensor = self
             .device
             .tensor_from_vec(obs.to_vec(), (Const::<STATE_SIZE>,));
-        let mut rng = rand::thread_rng();
         let random_number = rng.gen_range(0.0..1.0);
         let exploration = random_number < epsilon;
 
+        // random actions include coasting
         let action: usize = if exploration {
-            rng.gen_range(0..ACTIONS - 1)
+            rng.gen_range(0..ACTIONS)
         } else {
-            let q_values = self.qn.forward(obs_state_tensor.clone());
-            let max_q_value = q_values.clone().max::<Rank0, _>();
-            let some_action = q_values
-                .clone()
-                .array()
-                .iter()
-                .position(|q| *q >= max_q_value.array());
-            if None == some_action {
-                dbg!(q_values);
-                panic!();
-            } else {
-                some_action.unwrap()
+            let q_values = self.qn.forward(obs_state_tensor.clone()).array();
+            match greedy_action(&q_values) {
+                Some(action) => action,
+                None => {
+                    warn!("dqn diverged, q values {q_values:?}, acting randomly");
+                    if self.restore_on_divergence && self.best_qn.is_some() {
+                        warn!("dqn restoring best network");
+                        self.restore_best();
+                    }
+                    rng.gen_range(0..ACTIONS)
+                }
             }
         };
         (action, exploration)
     }
+    pub fn restore_best(&mut self) {
+        if let Some(best_qn) = &self.best_qn {
+            self.qn = best_qn.clone();
+            self.tqn = best_qn.clone();
+        }
+    }
+    /// Writes the online network weights as `.npz`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_model(&self, path: &Path) -> Result<(), String> {
+        self.qn.save(path).map_err(|e| format!("{e:?}"))?;
+        std::fs::write(model_shape_path(path), model_shape_csv()).map_err(|e| e.to_string())
+    }
+    /// Reads `.npz` weights into both networks, they are left untouched on error.
+    /// A model saved with another input size is refused before dfdx reads it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_model(&mut self, path: &Path) -> Result<(), String> {
+        let shape_path = model_shape_path(path);
+        let shape = std::fs::read_to_string(&shape_path)
+            .map_err(|e| format!("{} {e}", shape_path.display()))?;
+        check_model_shape(&shape)?;
+        let mut qn = self.qn.clone();
+        qn.load(path).map_err(|e| format!("{e:?}"))?;
+        self.tqn = qn.clone();
+        self.qn = qn;
+        Ok(())
+    }
     pub fn new(qn: &QNetworkBuilt, device: AutoDevice) -> Self {
         let gradients = qn.alloc_grads();
         Self {
             processing: false,
             qn: qn.clone(),
             tqn: qn.clone(),
+            best_qn: None,
             device,
             gradients,
+            restore_on_divergence: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DqnStepMode {
+    /// Act every `STEP_DURATION` wall-clock seconds, control frequency follows the frame rate.
+    Seconds,
+    /// Act every n physics ticks of `PhysicsParams::max_dt` simulated seconds, each
+    /// decision covers the same amount of simulated time whatever the frame rate.
+    Ticks(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DqnTargetSync {
+    /// Copy the online network into the target network every n steps.
+    Hard(usize),
+    /// Blend `tqn = tau * qn + (1 - tau) * tqn` every step, tau 1 is a copy.
+    Soft(f32),
+}
+
+impl DqnTargetSync {
+    /// Soft sync blending in `SOFT_SYNC_TAU` per step.
+    pub fn soft() -> Self {
+        Self::Soft(SOFT_SYNC_TAU)
+    }
+    /// `APP_DQN_TARGET_SYNC=soft` blends every step with `APP_DQN_TAU`, by default
+    /// `SOFT_SYNC_TAU`, otherwise the target is copied every `SYNC_INTERVAL_STEPS`.
+    pub fn from_env() -> Self {
+        if std::env::var("APP_DQN_TARGET_SYNC").as_deref() != Ok("soft") {
+            return Self::Hard(SYNC_INTERVAL_STEPS);
+        }
+        match std::env::var("APP_DQN_TAU")
+            .ok()
+            .and_then(|tau| tau.parse().ok())
+        {
+            Some(tau) => Self::Soft(tau),
+            None => Self::soft(),
+        }
+    }
+    pub fn sync(&self, cars_dqn: &mut CarsDqnResource, step: usize) -> bool {
+        match *self {
+            DqnTargetSync::Hard(interval) => {
+                if step % interval != 0 {
+                    return false;
+                }
+                cars_dqn.tqn = cars_dqn.qn.clone();
+            }
+            DqnTargetSync::Soft(tau) => {
+                let tau = tau.clamp(0., 1.);
+                // ema keeps `decay` of self and takes the rest from the other network
+                cars_dqn.tqn.ema(&cars_dqn.qn, 1. - tau);
+            }
+        }
+        true
+    }
+}
+
+/// Training hyperparameters read on every training batch, except `buffer_size`
+/// which sizes the replay buffer on startup. The network shape, `STATE_SIZE`,
+/// `ACTIONS` and `HIDDEN_SIZE`, is a dfdx const generic and stays in `params`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct DqnConfig {
+    pub gamma: f32,
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    pub buffer_size: usize,
+    /// Gradient steps per sampled batch.
+    pub epochs: usize,
+    /// Cars driven by the network besides the players, all sharing the replay buffer.
+    pub agents: usize,
+}
+
+impl Default for DqnConfig {
+    fn default() -> Self {
+        Self {
+            gamma: GAMMA,
+            learning_rate: LEARNING_RATE,
+            batch_size: BATCH_SIZE,
+            buffer_size: BUFFER_SIZE,
+            epochs: EPOCHS,
+            agents: 0,
         }
     }
 }
 
+impl DqnConfig {
+    /// Defaults overridden by `APP_DQN_GAMMA`, `APP_DQN_LEARNING_RATE`,
+    /// `APP_DQN_BATCH_SIZE`, `APP_DQN_BUFFER_SIZE`, `APP_DQN_EPOCHS` and `APP_DQN_AGENTS`.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+        let default = Self::default();
+        Self {
+            gamma: var("APP_DQN_GAMMA").unwrap_or(default.gamma),
+            learning_rate: var("APP_DQN_LEARNING_RATE").unwrap_or(default.learning_rate),
+            batch_size: var("APP_DQN_BATCH_SIZE")
+                .unwrap_or(default.batch_size)
+                .max(1),
+            buffer_size: var("APP_DQN_BUFFER_SIZE")
+                .unwrap_or(default.buffer_size)
+                .max(1),
+            epochs: var("APP_DQN_EPOCHS").unwrap_or(default.epochs),
+            agents: var("APP_DQN_AGENTS").unwrap_or(default.agents),
+        }
+    }
+}
+
+/// How exploration decays from `max_eps` to `min_eps`, applied after every training batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EpsilonSchedule {
+    /// Minus `DECAY` per batch.
+    Linear,
+    /// Minus `rate` of the distance to `min_eps` per batch.
+    Exponential { rate: f32 },
+    /// Half cosine from `max_eps` down to `min_eps` over `period_steps` steps, then again.
+    Cosine { period_steps: usize },
+}
+
+impl EpsilonSchedule {
+    pub fn next(&self, eps: f32, min_eps: f32, max_eps: f32, step: usize) -> f32 {
+        let eps = match *self {
+            EpsilonSchedule::Linear => eps - DECAY,
+            EpsilonSchedule::Exponential { rate } => eps - (eps - min_eps) * rate.clamp(0., 1.),
+            EpsilonSchedule::Cosine { period_steps } => {
+                let period = period_steps.max(1);
+                let phase = (step % period) as f32 / period as f32;
+                min_eps + (max_eps - min_eps) * 0.5 * (1. + (std::f32::consts::PI * phase).cos())
+            }
+        };
+        eps.clamp(min_eps, max_eps)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DqnRespawn {
+    /// Elapsed seconds at the crash.
+    pub at: f64,
+    pub player: bool,
+    pub index: usize,
+}
+
 #[derive(Resource)]
 pub struct DqnResource {
     pub use_nn: bool,
+    pub step_mode: DqnStepMode,
+    pub target_sync: DqnTargetSync,
+    /// Double DQN targets, the online network picks the next action and the target network rates it.
+    pub use_double_dqn: bool,
+    pub syncs: usize,
     pub seconds: f64,
+    /// Simulated seconds so far, counted by `dqn_ticks_system`.
+    pub physics_seconds: f64,
+    /// Whole `PhysicsParams::max_dt` steps in `physics_seconds`.
+    pub ticks: usize,
+    pub next_tick: usize,
     pub step: usize,
     pub crashes: usize,
+    pub best_reward: f32,
+    pub best_meters: f32,
     pub rb: ReplayBuffer,
+    /// Scales observations before the network and the replay buffer see them.
+    pub normalizer: RunningNormalizer,
     pub eps: f32,
     pub max_eps: f32,
     pub min_eps: f32,
+    pub eps_schedule: EpsilonSchedule,
+    /// Back to `max_eps` whenever a car faces against the track.
+    pub reset_eps_on_flip: bool,
     pub done: f32,
 
-    pub respawn_in: f64,
-    pub respawn_player: bool,
-    pub respawn_index: usize,
+    /// Crashed cars waiting to come back, any number of cars train at once.
+    pub respawns: Vec<DqnRespawn>,
+
+    /// Base of every training random number, same seed and car setup give the same run.
+    pub seed: u64,
+    /// Replay buffer batch sampling.
+    pub batch_rng: StdRng,
 }
 impl DqnResource {
     pub fn default() -> Self {
+        Self::with_seed(0)
+    }
+    pub fn with_seed(seed: u64) -> Self {
         Self {
+            seed,
+            batch_rng: StdRng::seed_from_u64(seed),
             use_nn: false,
+            step_mode: DqnStepMode::Ticks(STEP_TICKS),
+            target_sync: DqnTargetSync::from_env(),
+            use_double_dqn: true,
+            syncs: 0,
             seconds: 0.,
+            physics_seconds: 0.,
+            ticks: 0,
+            next_tick: 0,
             step: 0,
             crashes: 0,
+            best_reward: f32::MIN,
+            best_meters: 0.,
             rb: ReplayBuffer::new(),
+            normalizer: RunningNormalizer::default(),
             eps: 1.,
             max_eps: 1.,
             min_eps: 0.01,
+            eps_schedule: EpsilonSchedule::Linear,
+            reset_eps_on_flip: false,
             done: 0.,
 
-            respawn_in: 0.,
-            respawn_player: false,
-            respawn_index: 0,
+            respawns: Vec::new(),
         }
     }
+    /// Exploration seed of the car with track `index`, never equal to the batch seed.
+    pub fn car_seed(&self, index: usize) -> u64 {
+        self.seed.wrapping_add(1 + index as u64)
+    }
 }
 
 pub struct SgdResource {
     pub sgd: Sgd<QNetworkBuilt, f32, AutoDevice>,
 }
 impl SgdResource {
-    pub fn new(qn: &QNetworkBuilt) -> Self {
-        let sgd = get_sgd(qn);
+    pub fn new(qn: &QNetworkBuilt, learning_rate: f64) -> Self {
+        let sgd = get_sgd(qn, learning_rate);
         Self { sgd }
     }
 }
 
 pub struct DqnX {
     pub loss_string: String,
+    /// Mean loss of the batch over its training epochs.
+    pub loss: f32,
     pub duration_string: String,
     pub qn: QNetworkBuilt,
 }
@@ -139,9 +422,133 @@ pub fn dqn_start_system(world: &mut World) {
     let device = AutoDevice::default();
     let mut qn: QNetworkBuilt = device.build_module::<QNetwork, f32>();
     qn.reset_params();
-    world.insert_non_send_resource(SgdResource::new(&qn));
+    let config = world
+        .get_resource::<DqnConfig>()
+        .copied()
+        .unwrap_or_default();
+    if let Some(mut dqn) = world.get_resource_mut::<DqnResource>() {
+        dqn.rb.capacity = config.buffer_size;
+    }
+    if let Some(logger) = MetricsLogger::from_env() {
+        world.insert_resource(logger);
+    }
+    world.insert_non_send_resource(SgdResource::new(&qn, config.learning_rate));
     world.insert_non_send_resource(CarsDqnResource::new(&qn, device));
 }
+/// `DQN_MODEL_PATH` or `MODEL_PATH`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn model_path() -> PathBuf {
+    std::env::var("DQN_MODEL_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(MODEL_PATH))
+}
+/// `APP_DQN_BEST_EPISODE_PATH` or `BEST_EPISODE_PATH`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn best_episode_path() -> String {
+    std::env::var("APP_DQN_BEST_EPISODE_PATH").unwrap_or_else(|_| BEST_EPISODE_PATH.to_string())
+}
+/// Observation normalizer saved next to the model, `dqn_model.norm.csv` for `dqn_model.npz`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn normalizer_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("norm.csv")
+}
+/// Network input and output sizes the model at `model_path` was saved with.
+pub fn model_shape_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("shape.csv")
+}
+pub fn model_shape_csv() -> String {
+    format!("{STATE_SIZE},{ACTIONS}\n")
+}
+/// Errs unless `csv` of `model_shape_path` matches the compiled `STATE_SIZE` and `ACTIONS`.
+pub fn check_model_shape(csv: &str) -> Result<(), String> {
+    let bad = || format!("bad model shape {csv:?}");
+    let (state_size, actions) = csv.trim().split_once(',').ok_or_else(bad)?;
+    let state_size: usize = state_size.trim().parse().map_err(|_| bad())?;
+    let actions: usize = actions.trim().parse().map_err(|_| bad())?;
+    if state_size != STATE_SIZE {
+        return Err(format!(
+            "model input size {state_size} is not STATE_SIZE {STATE_SIZE} \
+             (FRAME_SIZE {FRAME_SIZE} x FRAME_STACK {FRAME_STACK})"
+        ));
+    }
+    if actions != ACTIONS {
+        return Err(format!(
+            "model output size {actions} is not ACTIONS {ACTIONS}, retrain it"
+        ));
+    }
+    Ok(())
+}
+/// Continues from the network at `DQN_MODEL_PATH` when the file exists,
+/// exploiting it with `min_eps` instead of exploring from scratch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dqn_model_load_system(
+    mut dqn: ResMut<DqnResource>,
+    mut cars_dqn: NonSendMut<CarsDqnResource>,
+) {
+    let Ok(path) = std::env::var("DQN_MODEL_PATH").map(PathBuf::from) else {
+        return;
+    };
+    if !path.exists() {
+        println!(
+            "dqn model {} not found, training from scratch",
+            path.display()
+        );
+        return;
+    }
+    match cars_dqn.load_model(&path) {
+        Ok(_) => {
+            dqn.eps = dqn.min_eps;
+            println!("dqn model loaded {}", path.display());
+        }
+        Err(e) => {
+            println!("dqn model loading error: {}", e);
+            return;
+        }
+    }
+    let norm_path = normalizer_path(&path);
+    let normalizer = std::fs::read_to_string(&norm_path)
+        .map_err(|e| e.to_string())
+        .and_then(|csv| RunningNormalizer::from_csv(&csv));
+    match normalizer {
+        Ok(normalizer) => dqn.normalizer = normalizer,
+        Err(e) => println!(
+            "dqn normalizer {} loading error: {}",
+            norm_path.display(),
+            e
+        ),
+    }
+}
+/// Saves the online network to `model_path()` every `MODEL_AUTOSAVE_STEPS` steps.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dqn_model_autosave_system(
+    dqn: Res<DqnResource>,
+    cars_dqn: NonSend<CarsDqnResource>,
+    mut saved_step: Local<usize>,
+) {
+    if dqn.step == *saved_step || dqn.step % MODEL_AUTOSAVE_STEPS != 0 {
+        return;
+    }
+    *saved_step = dqn.step;
+    let path = model_path();
+    match cars_dqn.save_model(&path) {
+        Ok(_) => println!("dqn model saved {} step:{}", path.display(), dqn.step),
+        Err(e) => println!("dqn model saving error: {}", e),
+    }
+    if let Err(e) = std::fs::write(normalizer_path(&path), dqn.normalizer.to_csv()) {
+        println!("dqn normalizer saving error: {}", e);
+    }
+}
+/// A car put back on the track keeps its `CarDqn` but not the frames from before.
+pub fn dqn_reset_history_system(
+    mut events: EventReader<ResetCarOnTrackEvent>,
+    mut cars: Query<&mut CarDqn>,
+) {
+    for event in events.read() {
+        if let Ok(mut car_dqn) = cars.get_mut(event.entity) {
+            car_dqn.clear_history();
+        }
+    }
+}
 pub fn dqn_x_start_system(mut cmd: Commands) {
     let (tx, rx) = bounded::<DqnX>(10);
     cmd.insert_resource(DqnRx(rx));
@@ -155,8 +562,10 @@ pub fn dqn_rx_to_bevy_event_system(receiver: Res<DqnRx>, mut events: EventWriter
 pub fn dqn_event_reader_system(
     mut reader: EventReader<DqnEvent>,
     mut cars_dqn: NonSendMut<CarsDqnResource>,
+    mut metrics: ResMut<DqnMetrics>,
 ) {
     for event in reader.read() {
+        metrics.push_loss(event.0.loss);
         // dbg!((&event.0.duration_string, &event.0.loss_string));
         println!(
             "dqn_event:{}:{}",
@@ -167,6 +576,145 @@ pub fn dqn_event_reader_system(
     }
 }
 
+/// Recent training losses and episode rewards, averaged into the `MetricsLogger` rows.
+#[derive(Resource, Debug, Default)]
+pub struct DqnMetrics {
+    pub losses: VecDeque<f32>,
+    pub episode_rewards: VecDeque<f32>,
+}
+
+impl DqnMetrics {
+    pub fn push_loss(&mut self, loss: f32) {
+        Self::push(&mut self.losses, loss);
+    }
+    pub fn push_episode_reward(&mut self, reward: f32) {
+        Self::push(&mut self.episode_rewards, reward);
+    }
+    fn push(window: &mut VecDeque<f32>, value: f32) {
+        if !value.is_finite() {
+            return;
+        }
+        if window.len() == METRICS_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(value);
+    }
+    fn mean(window: &VecDeque<f32>) -> Option<f32> {
+        match window.len() {
+            0 => None,
+            len => Some(window.iter().sum::<f32>() / len as f32),
+        }
+    }
+    pub fn mean_loss(&self) -> Option<f32> {
+        Self::mean(&self.losses)
+    }
+    pub fn mean_episode_reward(&self) -> Option<f32> {
+        Self::mean(&self.episode_rewards)
+    }
+}
+
+/// Per car part of a `MetricsLogger` row.
+#[derive(Debug, Clone, Copy)]
+pub struct StepLogRow {
+    pub step: usize,
+    pub car: usize,
+    pub epsilon: f32,
+    pub reward: f32,
+    pub meters: f32,
+    pub random: bool,
+}
+
+/// Training progress csv for learning curves and unattended runs, one row per
+/// car step, written to `DQN_METRICS_PATH` or `METRICS_PATH`.
+#[derive(Resource)]
+pub struct MetricsLogger {
+    writer: std::io::BufWriter<std::fs::File>,
+    unflushed: usize,
+}
+
+impl MetricsLogger {
+    /// `batch_loss` is the mean loss of the latest training batch, `mean_loss` and
+    /// `mean_episode_reward` average the last `METRICS_WINDOW` batches and episodes.
+    pub const CSV_HEADER: &'static str = "step,car,epsilon,batch_loss,mean_loss,reward,meters,random,buffer,syncs,crashes,mean_episode_reward,best_reward,best_meters\n";
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writer.write_all(Self::CSV_HEADER.as_bytes())?;
+        Ok(Self {
+            writer,
+            unflushed: 0,
+        })
+    }
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("DQN_METRICS_PATH").unwrap_or_else(|_| METRICS_PATH.to_string());
+        match Self::open(&path) {
+            Ok(logger) => {
+                println!("dqn metrics {path}");
+                Some(logger)
+            }
+            Err(e) => {
+                println!("dqn metrics error: {}", e);
+                None
+            }
+        }
+    }
+    /// One `CSV_HEADER` row, losses, averages and the best reward are empty until there are some.
+    pub fn csv_row(row: &StepLogRow, metrics: &DqnMetrics, dqn: &DqnResource) -> String {
+        let optional = |value: Option<f32>| value.map_or(String::new(), |v| v.to_string());
+        let StepLogRow {
+            step,
+            car,
+            epsilon,
+            reward,
+            meters,
+            random,
+        } = *row;
+        format!(
+            "{step},{car},{epsilon},{},{},{reward},{meters},{random},{},{},{},{},{},{}\n",
+            optional(metrics.losses.back().copied()),
+            optional(metrics.mean_loss()),
+            dqn.rb.len(),
+            dqn.syncs,
+            dqn.crashes,
+            optional(metrics.mean_episode_reward()),
+            optional(Some(dqn.best_reward).filter(|best| *best > f32::MIN)),
+            dqn.best_meters,
+        )
+    }
+    pub fn log(&mut self, row: &StepLogRow, metrics: &DqnMetrics, dqn: &DqnResource) {
+        use std::io::Write;
+        let row = Self::csv_row(row, metrics, dqn);
+        if let Err(e) = self.writer.write_all(row.as_bytes()) {
+            println!("dqn metrics error: {}", e);
+            return;
+        }
+        self.unflushed += 1;
+        if self.unflushed >= STEP_LOG_FLUSH_ROWS {
+            self.flush();
+        }
+    }
+    pub fn flush(&mut self) {
+        use std::io::Write;
+        self.unflushed = 0;
+        if let Err(e) = self.writer.flush() {
+            println!("dqn metrics error: {}", e);
+        }
+    }
+}
+
+/// Writes out the buffered metrics rows before the app exits.
+pub fn metrics_logger_exit_system(
+    mut exit: EventReader<AppExit>,
+    logger: Option<ResMut<MetricsLogger>>,
+) {
+    if exit.read().last().is_none() {
+        return;
+    }
+    if let Some(mut logger) = logger {
+        logger.flush();
+    }
+}
+
 pub fn dqn_dash_update_system(
     mut dash_set: ParamSet<(
         Query<&mut Text, With<TrainerEpsilonText>>,
@@ -181,13 +729,20 @@ pub fn dqn_dash_update_system(
     generation_text.sections[0].value = format!(
         "rb {:?}, sync {:?}, crashes {:?}",
         dqn.rb.len(),
-        (dqn.step / SYNC_INTERVAL_STEPS),
+        dqn.syncs,
         dqn.crashes
     );
 
     let mut q_timing_text = dash_set.p0();
     let mut timing_text = q_timing_text.single_mut();
-    timing_text.sections[0].value = format!("epsilon {:.4}", dqn.eps);
+    timing_text.sections[0].value = if dqn.best_reward > f32::MIN {
+        format!(
+            "epsilon {:.4}, best {:.2} {:.0}m",
+            dqn.eps, dqn.best_reward, dqn.best_meters
+        )
+    } else {
+        format!("epsilon {:.4}", dqn.eps)
+    };
 
     let mut rewards: String = String::from("");
     for car_dqn in cars.iter() {
@@ -202,3 +757,146 @@ pub fn dqn_dash_update_system(
     let mut rewards_text = q_rewards_text.single_mut();
     rewards_text.sections[0].value = format!("reward {rewards}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn q_values(qn: &QNetworkBuilt, device: &AutoDevice) -> [f32; ACTIONS] {
+        let state = device.tensor_from_vec(
+            (0..STATE_SIZE).map(|i| (i as f32 * 0.37).sin()).collect(),
+            (Const::<STATE_SIZE>,),
+        );
+        qn.forward(state).array()
+    }
+
+    #[test]
+    fn soft_sync_with_tau_1_is_a_hard_copy() {
+        let device = AutoDevice::default();
+        let target: QNetworkBuilt = device.build_module::<QNetwork, f32>();
+        let online: QNetworkBuilt = device.build_module::<QNetwork, f32>();
+        let mut soft = CarsDqnResource::new(&target, device.clone());
+        soft.qn = online.clone();
+        let mut hard = CarsDqnResource::new(&target, device.clone());
+        hard.qn = online.clone();
+        assert_ne!(q_values(&soft.tqn, &device), q_values(&online, &device));
+
+        assert!(DqnTargetSync::Soft(1.).sync(&mut soft, 1));
+        assert!(DqnTargetSync::Hard(1).sync(&mut hard, 1));
+
+        assert_eq!(q_values(&soft.tqn, &device), q_values(&hard.tqn, &device));
+        assert_eq!(q_values(&soft.tqn, &device), q_values(&online, &device));
+    }
+
+    /// `network` with every weight NaN, as after a diverged training step.
+    fn diverged(network: &QNetworkBuilt) -> QNetworkBuilt {
+        let mut diverged = network.clone();
+        diverged.ema(network, f32::NAN);
+        diverged
+    }
+
+    #[test]
+    fn greedy_action_refuses_non_finite_q_values() {
+        let mut q_values = [0.; ACTIONS];
+        q_values[1] = 1.;
+        assert_eq!(greedy_action(&q_values), Some(1));
+        q_values[0] = f32::NAN;
+        assert_eq!(greedy_action(&q_values), None);
+        q_values[0] = f32::INFINITY;
+        assert_eq!(greedy_action(&q_values), None);
+    }
+
+    #[test]
+    fn nan_q_values_fall_back_to_the_best_network() {
+        let device = AutoDevice::default();
+        let best: QNetworkBuilt = device.build_module::<QNetwork, f32>();
+        let mut cars_dqn = CarsDqnResource::new(&best, device.clone());
+        cars_dqn.best_qn = Some(best.clone());
+        cars_dqn.qn = diverged(&best);
+        assert!(greedy_action(&q_values(&cars_dqn.qn, &device)).is_none());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let (action, exploration) = cars_dqn.act([0.1; STATE_SIZE], 0., &mut rng);
+
+        assert!(action < ACTIONS);
+        assert!(!exploration);
+        assert_eq!(q_values(&cars_dqn.qn, &device), q_values(&best, &device));
+        assert_eq!(q_values(&cars_dqn.tqn, &device), q_values(&best, &device));
+    }
+
+    #[test]
+    fn nan_q_values_without_a_checkpoint_act_randomly() {
+        let device = AutoDevice::default();
+        let network: QNetworkBuilt = device.build_module::<QNetwork, f32>();
+        let mut cars_dqn = CarsDqnResource::new(&network, device.clone());
+        cars_dqn.qn = diverged(&network);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10 {
+            let (action, exploration) = cars_dqn.act([0.1; STATE_SIZE], 0., &mut rng);
+            assert!(action < ACTIONS);
+            assert!(!exploration);
+        }
+    }
+
+    #[test]
+    fn model_shape_must_match_state_size_and_actions() {
+        assert_eq!(check_model_shape(&model_shape_csv()), Ok(()));
+        let stale = format!("{},{ACTIONS}\n", FRAME_SIZE * (FRAME_STACK + 1));
+        assert!(check_model_shape(&stale)
+            .unwrap_err()
+            .contains("FRAME_STACK"));
+        // saved before the coast action
+        let no_coast = format!("{STATE_SIZE},{}\n", ACTIONS - 1);
+        assert!(check_model_shape(&no_coast)
+            .unwrap_err()
+            .contains("ACTIONS"));
+        assert!(check_model_shape("").is_err());
+        assert!(check_model_shape(&format!("{STATE_SIZE}\n")).is_err());
+    }
+
+    #[test]
+    fn reset_clears_the_frame_history() {
+        let mut car_dqn = CarDqn::new(1);
+        car_dqn.prev_obs = [1.; STATE_SIZE];
+        car_dqn.frames.push_back([1.; FRAME_SIZE]);
+        let mut world = World::new();
+        world.init_resource::<Events<ResetCarOnTrackEvent>>();
+        let entity = world.spawn(car_dqn).id();
+        world.send_event(ResetCarOnTrackEvent { entity });
+
+        world.run_system_once(dqn_reset_history_system);
+
+        let car_dqn = world.get::<CarDqn>(entity).unwrap();
+        assert!(car_dqn.frames.is_empty());
+        assert_eq!(car_dqn.prev_obs, [0.; STATE_SIZE]);
+        let frame = [2.; FRAME_SIZE];
+        assert_eq!(car_dqn.observe(&frame), [2.; STATE_SIZE]);
+    }
+
+    #[test]
+    fn metrics_row_has_a_value_for_every_header_column() {
+        let dqn = DqnResource::default();
+        let mut metrics = DqnMetrics::default();
+        let row = StepLogRow {
+            step: 3,
+            car: 1,
+            epsilon: 0.5,
+            reward: -1.,
+            meters: 12.,
+            random: true,
+        };
+        let columns = MetricsLogger::CSV_HEADER.split(',').count();
+
+        let before_training = MetricsLogger::csv_row(&row, &metrics, &dqn);
+        assert_eq!(before_training, "3,1,0.5,,,-1,12,true,0,0,0,,,0\n");
+
+        metrics.push_loss(2.);
+        metrics.push_loss(4.);
+        let row = MetricsLogger::csv_row(&row, &metrics, &dqn);
+        let values: Vec<&str> = row.trim_end().split(',').collect();
+        assert_eq!(values.len(), columns);
+        assert_eq!(values[3..5], ["4", "3"]);
+    }
+}
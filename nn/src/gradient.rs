@@ -1,14 +1,13 @@
-use super::params::LEARNING_RATE;
 use dfdx::{
     optim::{Momentum, Sgd, SgdConfig},
     tensor::AutoDevice,
 };
 
-pub fn get_sgd<M>(m: &M) -> Sgd<M, f32, AutoDevice> {
+pub fn get_sgd<M>(m: &M, lr: f64) -> Sgd<M, f32, AutoDevice> {
     let sgd: Sgd<M, f32, AutoDevice> = Sgd::new(
         m,
         SgdConfig {
-            lr: LEARNING_RATE,
+            lr,
             momentum: Some(Momentum::Nesterov(0.9)),
             weight_decay: None,
         },
@@ -1,5 +1,3 @@
-#![feature(slice_flatten)]
-
 #[cfg(feature = "api")]
 pub mod api_client;
 
@@ -7,36 +5,85 @@ pub mod dash;
 pub mod dqn;
 pub mod dqn_bevy;
 pub mod gradient;
+pub mod normalize;
 pub mod params;
 pub mod replay;
+pub mod reward;
 pub mod spawn;
+pub mod training;
 pub mod util;
 
-use crate::{dqn::dqn_system, dqn_bevy::*, spawn::*};
-use bevy::prelude::{App, IntoSystemConfigs, Plugin, Startup, Update};
-use bevy_garage_car::CarSet;
+use crate::{
+    dqn::{dqn_system, dqn_ticks_system},
+    dqn_bevy::*,
+    reward::DqnReward,
+    spawn::*,
+    training::*,
+};
+use bevy::prelude::{
+    run_once, App, Condition, IntoSystemConfigs, Last, Plugin, PostUpdate, Startup, Update,
+};
+use bevy_garage_car::{
+    car_assets_ready, not_paused,
+    sensor::{SensorConfig, SENSOR_COUNT},
+    CarSet,
+};
 pub use dqn_bevy::DqnResource;
+pub use training::TrainingMode;
 
 pub struct NeuralNetworkPlugin;
 
 impl Plugin for NeuralNetworkPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(DqnResource::default())
+        if let Some(sensors) = app.world().get_resource::<SensorConfig>() {
+            assert_eq!(
+                sensors.count, SENSOR_COUNT,
+                "the network takes SENSOR_COUNT sensor inputs, change it with SensorConfig::count"
+            );
+        }
+        if !app.world().contains_resource::<DqnReward>() {
+            app.init_resource::<DqnReward>();
+        }
+        if !app.world().contains_resource::<TrainingMode>() {
+            app.insert_resource(TrainingMode::from_env());
+        }
+        app.insert_resource(DqnResource::with_seed(bevy_garage_car::seed_from_env()))
+            .insert_resource(DqnConfig::from_env())
+            .init_resource::<DqnMetrics>()
             .add_event::<DqnEvent>()
-            .add_systems(Startup, (dqn_start_system, dqn_x_start_system))
+            .add_systems(
+                Startup,
+                (
+                    dqn_start_system,
+                    dqn_x_start_system,
+                    training_mode_start_system,
+                ),
+            )
+            .add_systems(Last, headless_training_system)
+            .add_systems(
+                PostUpdate,
+                dqn_ticks_system.after(bevy_rapier3d::plugin::PhysicsSet::StepSimulation),
+            )
             .add_systems(
                 Update,
                 (
                     add_dqn_on_spawned_car_system,
+                    spawn_dqn_agents_system.run_if(car_assets_ready.and_then(run_once())),
                     dqn_rx_to_bevy_event_system,
+                    dqn_reset_history_system.before(CarSet::NeuralNetwork),
                     dqn_event_reader_system,
                     bevy_garage_car::sensor::sensor_system.in_set(CarSet::Input),
                     dqn_system
                         .in_set(CarSet::NeuralNetwork)
-                        .after(CarSet::Input),
+                        .after(CarSet::Input)
+                        .run_if(not_paused),
                     dqn_dash_update_system,
+                    metrics_logger_exit_system,
                 ),
             );
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, dqn_model_load_system.after(dqn_start_system))
+            .add_systems(Update, dqn_model_autosave_system);
 
         #[cfg(feature = "api")]
         {
@@ -0,0 +1,86 @@
+use crate::{dqn::Observation, params::STATE_SIZE};
+
+/// Normalized values are clamped to this many standard deviations.
+pub const NORMALIZED_CLAMP: f32 = 5.;
+
+/// Per observation value running mean and variance, Welford's algorithm,
+/// so speeds, angles and sensor distances reach the network on one scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunningNormalizer {
+    pub count: u64,
+    pub mean: [f64; STATE_SIZE],
+    /// Sum of squared differences from the mean.
+    pub m2: [f64; STATE_SIZE],
+}
+
+impl Default for RunningNormalizer {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: [0.; STATE_SIZE],
+            m2: [0.; STATE_SIZE],
+        }
+    }
+}
+
+impl RunningNormalizer {
+    pub fn update(&mut self, obs: &Observation) {
+        self.count += 1;
+        let count = self.count as f64;
+        for (i, &x) in obs.iter().enumerate() {
+            let x = x as f64;
+            let delta = x - self.mean[i];
+            self.mean[i] += delta / count;
+            self.m2[i] += delta * (x - self.mean[i]);
+        }
+    }
+
+    pub fn variance(&self, i: usize) -> f64 {
+        match self.count {
+            0 | 1 => 1.,
+            count => self.m2[i] / (count - 1) as f64,
+        }
+    }
+
+    /// `(x - mean) / std`, values pass unchanged until two observations were seen.
+    pub fn normalize(&self, obs: &Observation) -> Observation {
+        if self.count < 2 {
+            return *obs;
+        }
+        let mut normalized: Observation = [0.; STATE_SIZE];
+        for (i, &x) in obs.iter().enumerate() {
+            let std = self.variance(i).sqrt().max(1e-8);
+            normalized[i] = (((x as f64 - self.mean[i]) / std) as f32)
+                .clamp(-NORMALIZED_CLAMP, NORMALIZED_CLAMP);
+        }
+        normalized
+    }
+
+    /// `count` on the first line, then one `mean,m2` line per observation value.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", self.count);
+        for (mean, m2) in self.mean.iter().zip(&self.m2) {
+            csv.push_str(format!("{mean},{m2}\n").as_str());
+        }
+        csv
+    }
+
+    pub fn from_csv(csv: &str) -> Result<Self, String> {
+        let mut lines = csv.lines();
+        let count = lines
+            .next()
+            .and_then(|count| count.trim().parse().ok())
+            .ok_or("missing count")?;
+        let mut normalizer = Self {
+            count,
+            ..Self::default()
+        };
+        for i in 0..STATE_SIZE {
+            let line = lines.next().ok_or(format!("missing value {i}"))?;
+            let (mean, m2) = line.split_once(',').ok_or(format!("bad value {i}"))?;
+            normalizer.mean[i] = mean.trim().parse().map_err(|_| format!("bad mean {i}"))?;
+            normalizer.m2[i] = m2.trim().parse().map_err(|_| format!("bad m2 {i}"))?;
+        }
+        Ok(normalizer)
+    }
+}
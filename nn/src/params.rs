@@ -20,15 +20,63 @@ pub const STEP_DURATION: f64 = 1. / 10.;
 #[cfg(not(target_arch = "wasm32"))]
 pub const STEP_DURATION: f64 = 1. / 30.;
 
+// ticks of simulated PhysicsParams::max_dt, 60 per second
+#[cfg(target_arch = "wasm32")]
+pub const STEP_TICKS: usize = 6;
+#[cfg(not(target_arch = "wasm32"))]
+pub const STEP_TICKS: usize = 2;
+
+// STATE_SIZE, ACTIONS and HIDDEN_SIZE shape QNetwork and stay compile-time,
+// the training hyperparameters are runtime defaults of DqnConfig
 #[cfg(target_arch = "wasm32")]
 pub const HIDDEN_SIZE: usize = 32;
 #[cfg(not(target_arch = "wasm32"))]
 pub const HIDDEN_SIZE: usize = 256;
 
+/// Discount of the next state Q-value.
+pub const GAMMA: f32 = 0.99;
 pub const DECAY: f32 = 0.001;
 pub const SYNC_INTERVAL_STEPS: usize = 300;
+/// Share of the online network blended into the target network per step with soft sync.
+pub const SOFT_SYNC_TAU: f32 = 0.005;
 pub const BUFFER_SIZE: usize = 10_000_000;
+/// Contact force in newtons against anything but the road which ends an episode,
+/// about the weight of the car, weaker scrapes only lower the reward.
+pub const CRASH_FORCE: f32 = 10_000.;
+/// Highest crash penalty, in multiples of the penalty at `CRASH_FORCE`.
+pub const CRASH_PENALTY_MAX: f32 = 5.;
+/// Track index of the first agent car, clear of the local player indexes.
+pub const AGENT_INDEX_OFFSET: usize = 100;
 
 pub const STATE_SIZE_BASE: usize = 5;
-pub const STATE_SIZE: usize = STATE_SIZE_BASE + SENSOR_COUNT;
-pub const ACTIONS: usize = 9; //
+/// Values observed in a single step, the car state followed by the sensors.
+pub const FRAME_SIZE: usize = STATE_SIZE_BASE + SENSOR_COUNT;
+/// Number of most recent frames stacked into the network input, 1 feeds only the current frame.
+pub const FRAME_STACK: usize = 1;
+pub const STATE_SIZE: usize = FRAME_SIZE * FRAME_STACK;
+const _: () = assert!(
+    FRAME_STACK > 0,
+    "FRAME_STACK needs at least the current frame"
+);
+/// Network outputs, one per `map_action_to_car` action. Changing it changes the
+/// network shape, trained networks of another size can't be reused and are refused
+/// on load by `check_model_shape`, retrain from scratch.
+pub const ACTIONS: usize = 9;
+/// No gas, no brake, straight wheels, lets the car roll through corners.
+pub const COAST_ACTION: usize = 8;
+const _: () = assert!(
+    COAST_ACTION < ACTIONS,
+    "ACTIONS must include the coast action"
+);
+
+/// Steps of the best rewarded episode, `APP_DQN_BEST_EPISODE_PATH` overrides it.
+pub const BEST_EPISODE_PATH: &str = "dqn_best_episode.csv";
+/// Network weights autosave, `DQN_MODEL_PATH` overrides it and is loaded on start.
+pub const MODEL_PATH: &str = "dqn_model.npz";
+pub const MODEL_AUTOSAVE_STEPS: usize = SYNC_INTERVAL_STEPS * 10;
+/// Training progress csv, `DQN_METRICS_PATH` overrides it.
+pub const METRICS_PATH: &str = "dqn_metrics.csv";
+/// Metrics rows buffered before `MetricsLogger` flushes them.
+pub const STEP_LOG_FLUSH_ROWS: usize = 1000;
+/// Latest losses and episode rewards averaged in `DqnMetrics`.
+pub const METRICS_WINDOW: usize = 100;
@@ -2,14 +2,18 @@ use super::{dqn::*, params::*};
 use dfdx::prelude::*;
 
 pub type Tensor1DUsize<const M: usize, Tape = NoneTape> = Tensor<Rank1<M>, usize, Cpu, Tape>;
+/// Batch tensors, the batch size is `DqnConfig::batch_size` known only at runtime.
+pub type BatchTensor2D<const N: usize, Tape = NoneTape> = Tensor<(usize, Const<N>), f32, Cpu, Tape>;
+pub type BatchTensor1D<Tape = NoneTape> = Tensor<(usize,), f32, Cpu, Tape>;
+pub type BatchTensor1DUsize = Tensor<(usize,), usize, Cpu>;
 
 type StateTuple = (Observation, usize, f32, Observation, f32);
 type StateTensorsTuple = (
-    Tensor2D<BATCH_SIZE, STATE_SIZE>, // s
-    Tensor1DUsize<BATCH_SIZE>,        // a
-    Tensor1D<BATCH_SIZE>,             // r
-    Tensor2D<BATCH_SIZE, STATE_SIZE>, // sn
-    Tensor1D<BATCH_SIZE>,             // done
+    BatchTensor2D<STATE_SIZE>, // s
+    BatchTensor1DUsize,        // a
+    BatchTensor1D,             // r
+    BatchTensor2D<STATE_SIZE>, // sn
+    BatchTensor1D,             // done
 );
 
 pub struct ReplayBuffer {
@@ -19,10 +23,15 @@ pub struct ReplayBuffer {
     pub next_state: Vec<Observation>,
     pub done: Vec<f32>,
     pub i: usize,
+    /// Oldest transitions are overwritten past it.
+    pub capacity: usize,
 }
 
 impl ReplayBuffer {
     pub fn new() -> Self {
+        Self::with_capacity(BUFFER_SIZE)
+    }
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             state: Vec::new(),
             action: Vec::new(),
@@ -30,51 +39,52 @@ impl ReplayBuffer {
             next_state: Vec::new(),
             done: Vec::new(),
             i: 0,
+            capacity: capacity.max(1),
         }
     }
     pub fn len(&self) -> usize {
         self.state.len()
     }
-    pub fn get_batch(&self, sample_indexes: [usize; BATCH_SIZE]) -> [StateTuple; BATCH_SIZE] {
-        sample_indexes.map(|i| {
-            (
-                self.state[i],
-                self.action[i],
-                self.reward[i],
-                self.next_state[i],
-                self.done[i],
-            )
-        })
+    pub fn get_batch(&self, sample_indexes: &[usize]) -> Vec<StateTuple> {
+        sample_indexes
+            .iter()
+            .map(|&i| {
+                (
+                    self.state[i],
+                    self.action[i],
+                    self.reward[i],
+                    self.next_state[i],
+                    self.done[i],
+                )
+            })
+            .collect()
     }
     pub fn get_batch_tensors(
         &self,
-        sample_indexes: [usize; BATCH_SIZE],
+        sample_indexes: &[usize],
         device: AutoDevice,
     ) -> StateTensorsTuple {
-        let batch: [StateTuple; BATCH_SIZE] = self.get_batch(sample_indexes);
-        let mut states: [[f32; STATE_SIZE]; BATCH_SIZE] = [[0.; STATE_SIZE]; BATCH_SIZE];
-        let mut actions: [usize; BATCH_SIZE] = [0; BATCH_SIZE];
-        let mut rewards: [f32; BATCH_SIZE] = [0.; BATCH_SIZE];
-        let mut next_states: [[f32; STATE_SIZE]; BATCH_SIZE] = [[0.; STATE_SIZE]; BATCH_SIZE];
-        let mut done: [f32; BATCH_SIZE] = [0.; BATCH_SIZE];
-        for (i, (s, a, r, s_n, d)) in batch.iter().enumerate() {
-            states[i] = *s;
-            actions[i] = *a;
-            rewards[i] = *r;
-            next_states[i] = *s_n;
-            done[i] = *d;
+        let batch_size = sample_indexes.len();
+        let batch: Vec<StateTuple> = self.get_batch(sample_indexes);
+        let mut states: Vec<f32> = Vec::with_capacity(batch_size * STATE_SIZE);
+        let mut actions: Vec<usize> = Vec::with_capacity(batch_size);
+        let mut rewards: Vec<f32> = Vec::with_capacity(batch_size);
+        let mut next_states: Vec<f32> = Vec::with_capacity(batch_size * STATE_SIZE);
+        let mut done: Vec<f32> = Vec::with_capacity(batch_size);
+        for (s, a, r, s_n, d) in batch.iter() {
+            states.extend_from_slice(s);
+            actions.push(*a);
+            rewards.push(*r);
+            next_states.extend_from_slice(s_n);
+            done.push(*d);
         }
-        let states_tensor: Tensor2D<BATCH_SIZE, STATE_SIZE> = device.tensor_from_vec(
-            states.flatten().to_vec(),
-            (Const::<BATCH_SIZE>, Const::<STATE_SIZE>),
-        );
-        let next_states_tensor: Tensor2D<BATCH_SIZE, STATE_SIZE> = device.tensor_from_vec(
-            next_states.flatten().to_vec(),
-            (Const::<BATCH_SIZE>, Const::<STATE_SIZE>),
-        );
-        let actions_tensor = device.tensor_from_vec(actions.to_vec(), (Const::<BATCH_SIZE>,));
-        let rewards_tensor = device.tensor_from_vec(rewards.to_vec(), (Const::<BATCH_SIZE>,));
-        let done_tensor = device.tensor_from_vec(done.to_vec(), (Const::<BATCH_SIZE>,));
+        let states_tensor: BatchTensor2D<STATE_SIZE> =
+            device.tensor_from_vec(states, (batch_size, Const::<STATE_SIZE>));
+        let next_states_tensor: BatchTensor2D<STATE_SIZE> =
+            device.tensor_from_vec(next_states, (batch_size, Const::<STATE_SIZE>));
+        let actions_tensor = device.tensor_from_vec(actions, (batch_size,));
+        let rewards_tensor = device.tensor_from_vec(rewards, (batch_size,));
+        let done_tensor = device.tensor_from_vec(done, (batch_size,));
         (
             states_tensor,
             actions_tensor,
@@ -85,8 +95,8 @@ impl ReplayBuffer {
     }
     pub fn store(&mut self, s: Observation, a: usize, r: f32, sn: Observation, done: bool) {
         let done_float = if done { 1. } else { 0. };
-        let i = self.i % BUFFER_SIZE;
-        if self.len() < BUFFER_SIZE {
+        let i = self.i % self.capacity;
+        if self.len() < self.capacity {
             self.state.push(s);
             self.action.push(a);
             self.reward.push(r);
@@ -0,0 +1,86 @@
+use crate::params::{CRASH_FORCE, CRASH_PENALTY_MAX};
+use bevy::prelude::Resource;
+
+/// What a car did since its last step, all a `RewardFn` gets to shape a reward.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RewardContext {
+    /// Meters driven along the track line.
+    pub progress: f32,
+    /// `progress` at the previous step.
+    pub prev_progress: f32,
+    /// Speed in meters per second.
+    pub mps: f32,
+    /// Speed the default reward is highest at.
+    pub max_speed: f32,
+    /// `impact` reached `CRASH_FORCE`.
+    pub crashed: bool,
+    /// Strongest contact force in newtons against anything but the road since the last frame.
+    pub impact: f32,
+    /// Radians between the velocity and the track line.
+    pub angle: f32,
+    /// Cosine between the car heading and the track line, negative going backward.
+    pub heading_cos: f32,
+    /// Distance from the track line, 1 is 4 meters.
+    pub center_distance: f32,
+    pub steering: f32,
+    pub prev_steering: f32,
+}
+
+pub trait RewardFn: Send + Sync + 'static {
+    fn reward(&self, ctx: &RewardContext) -> f32;
+}
+
+/// Speed along the track minus the distance from its line, `R = v(cos α − d)`,
+/// minus the share of `CRASH_FORCE` a scrape reached,
+/// <https://team.inria.fr/rits/files/2018/02/ICRA18_EndToEndDriving_CameraReady.pdf>
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultReward;
+
+impl RewardFn for DefaultReward {
+    fn reward(&self, ctx: &RewardContext) -> f32 {
+        if ctx.crashed {
+            // a wall hit at speed costs more than brushing it
+            return -(ctx.impact / CRASH_FORCE).clamp(1., CRASH_PENALTY_MAX);
+        }
+        let mut velocity_reward = ctx.mps / ctx.max_speed;
+        if velocity_reward > 1. {
+            // reduce reward when it's over desired speed
+            velocity_reward = 1. - (velocity_reward - 1.) / velocity_reward;
+        }
+        let vel_cos = ctx.angle.cos();
+        let mut reward = velocity_reward * (vel_cos - ctx.center_distance);
+        if vel_cos.is_sign_positive()
+            && ctx.heading_cos.is_sign_negative()
+            && reward.is_sign_positive()
+        {
+            // going backward
+            reward = -reward;
+        }
+        // scrapes below the crash force cost their share of it
+        reward -= ctx.impact / CRASH_FORCE;
+        if reward.is_nan() {
+            return 0.;
+        }
+        reward
+    }
+}
+
+/// Reward shaping used by `dqn_system`, insert another one before
+/// `NeuralNetworkPlugin` is added to train with it.
+#[derive(Resource)]
+pub struct DqnReward(pub Box<dyn RewardFn>);
+
+impl Default for DqnReward {
+    fn default() -> Self {
+        Self(Box::new(DefaultReward))
+    }
+}
+
+impl DqnReward {
+    pub fn new(reward: impl RewardFn) -> Self {
+        Self(Box::new(reward))
+    }
+    pub fn reward(&self, ctx: &RewardContext) -> f32 {
+        self.0.reward(ctx)
+    }
+}
@@ -1,14 +1,40 @@
-use crate::dqn_bevy::CarDqn;
+use crate::{
+    dqn_bevy::{CarDqn, DqnConfig, DqnResource},
+    params::AGENT_INDEX_OFFSET,
+};
 use bevy::prelude::*;
-use bevy_garage_car::{sensor::CarSensors, Car, CarSpec};
+use bevy_garage_car::{
+    sensor::{CarSensors, SensorConfig},
+    Car, CarSpec,
+};
+use bevy_garage_track::{CarTrack, SpawnCarOnTrackEvent};
+
+/// Spawns `DqnConfig::agents` network driven cars at random track positions.
+pub fn spawn_dqn_agents_system(
+    config: Res<DqnConfig>,
+    mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>,
+) {
+    for i in 0..config.agents {
+        car_spawn_events.send(SpawnCarOnTrackEvent {
+            player: false,
+            index: AGENT_INDEX_OFFSET + i,
+            position: None,
+        });
+    }
+}
 
 pub fn add_dqn_on_spawned_car_system(
-    query: Query<(Entity, &CarSpec), Added<Car>>,
+    query: Query<(Entity, &CarSpec, Option<&CarTrack>), Added<Car>>,
+    dqn: Res<DqnResource>,
+    sensor_config: Option<Res<SensorConfig>>,
     mut cmd: Commands,
 ) {
-    for (car_entity, spec) in &query {
+    let sensor_config = sensor_config.map(|c| c.clone()).unwrap_or_default();
+    for (car_entity, spec, car_track) in &query {
+        // respawns keep the track index and so the seed
+        let index = car_track.map(|t| t.index).unwrap_or_default();
         cmd.entity(car_entity)
-            .insert(CarDqn::new())
-            .insert(CarSensors::new(&spec.size));
+            .insert(CarDqn::new(dqn.car_seed(index)))
+            .insert(CarSensors::with_config(&spec.size, &sensor_config));
     }
 }
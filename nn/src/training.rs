@@ -0,0 +1,127 @@
+use crate::dqn_bevy::{DqnResource, DqnStepMode};
+use crate::params::STEP_TICKS;
+use bevy::prelude::*;
+use bevy_garage_car::{Paused, PhysicsParams};
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+/// How simulation and learning are paced against frames.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TrainingMode {
+    /// One simulation and learning step per frame.
+    #[default]
+    Realtime,
+    /// `steps_per_frame` simulation and learning steps per frame, each a fixed
+    /// physics step. The window is only hidden, not removed, so rendering costs
+    /// once per `steps_per_frame` steps and throughput grows about that many
+    /// times until physics and training take the whole frame.
+    Headless { steps_per_frame: u32 },
+}
+
+impl TrainingMode {
+    /// `Headless` with `APP_HEADLESS_STEPS` steps per frame when set.
+    pub fn from_env() -> Self {
+        match std::env::var("APP_HEADLESS_STEPS")
+            .ok()
+            .and_then(|steps| steps.parse().ok())
+        {
+            Some(steps_per_frame) => Self::Headless { steps_per_frame },
+            None => Self::Realtime,
+        }
+    }
+    pub fn steps_per_frame(&self) -> u32 {
+        match *self {
+            Self::Realtime => 1,
+            Self::Headless { steps_per_frame } => steps_per_frame.max(1),
+        }
+    }
+    pub fn is_headless(&self) -> bool {
+        matches!(self, Self::Headless { .. })
+    }
+}
+
+/// Headless steps count physics ticks instead of wall-clock seconds,
+/// which stand still for the extra steps of a frame.
+pub fn training_mode_start_system(
+    mode: Res<TrainingMode>,
+    physics_params: Res<PhysicsParams>,
+    mut dqn: ResMut<DqnResource>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if !mode.is_headless() {
+        return;
+    }
+    dqn.step_mode = DqnStepMode::Ticks(STEP_TICKS);
+    rapier_config.timestep_mode = physics_params.fixed_timestep_mode();
+    println!(
+        "headless training, {} steps per frame",
+        mode.steps_per_frame()
+    );
+}
+
+/// Runs `Update` and the physics in `PostUpdate` again for the remaining steps
+/// of a headless frame. Every step stores its transitions in the replay buffer
+/// like a frame would, a crashed car respawns on the next frame. Nothing runs while `Paused`.
+/// Presses and releases were handled by the frame's own `Update`, the extra steps
+/// only see held buttons.
+pub fn headless_training_system(world: &mut World) {
+    if world
+        .get_resource::<Paused>()
+        .is_some_and(|paused| paused.0)
+    {
+        return;
+    }
+    let steps = world
+        .get_resource::<TrainingMode>()
+        .map_or(1, TrainingMode::steps_per_frame);
+    if steps > 1 {
+        clear_just_pressed::<KeyCode>(world);
+        clear_just_pressed::<MouseButton>(world);
+        clear_just_pressed::<GamepadButton>(world);
+    }
+    for _ in 1..steps {
+        world.run_schedule(Update);
+        world.run_schedule(PostUpdate);
+    }
+}
+
+fn clear_just_pressed<T: Copy + Eq + std::hash::Hash + Send + Sync + 'static>(world: &mut World) {
+    if let Some(mut input) = world.get_resource_mut::<ButtonInput<T>>() {
+        input.bypass_change_detection().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Presses(u32);
+
+    fn count_presses_system(input: Res<ButtonInput<KeyCode>>, mut presses: ResMut<Presses>) {
+        if input.just_pressed(KeyCode::KeyN) {
+            presses.0 += 1;
+        }
+    }
+
+    #[test]
+    fn extra_headless_steps_skip_presses() {
+        let mut world = World::new();
+        let mut update = Schedule::new(Update);
+        update.add_systems(count_presses_system);
+        world.add_schedule(update);
+        world.add_schedule(Schedule::new(PostUpdate));
+        world.insert_resource(TrainingMode::Headless { steps_per_frame: 4 });
+        world.init_resource::<Presses>();
+        let mut input = ButtonInput::<KeyCode>::default();
+        input.press(KeyCode::KeyN);
+        world.insert_resource(input);
+
+        world.run_schedule(Update);
+        headless_training_system(&mut world);
+
+        assert_eq!(world.resource::<Presses>().0, 1);
+        assert!(world
+            .resource::<ButtonInput<KeyCode>>()
+            .pressed(KeyCode::KeyN));
+    }
+}
@@ -31,7 +31,7 @@ const ZERO: f32 = 0.;
 // 5 - forward-right
 // 6 - backward-left
 // 7 - backward-right
-// 8 - nothing
+// 8 - coast, `COAST_ACTION`
 pub fn map_action_to_car(a: usize) -> (f32, f32, f32, f32) {
     let gas = match a {
         0 | 4 | 5 => ONE,
@@ -51,3 +51,17 @@ pub fn map_action_to_car(a: usize) -> (f32, f32, f32, f32) {
     };
     (gas, brake, left, right)
 }
+
+/// Writes one `step,action,reward,observation...` row per episode step.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_episode(path: &str, episode: &[crate::dqn_bevy::EpisodeStep]) {
+    let mut csv = String::from("step,action,reward,observation\n");
+    for (i, (obs, action, reward)) in episode.iter().enumerate() {
+        let obs: Vec<String> = obs.iter().map(|x| x.to_string()).collect();
+        csv.push_str(format!("{i},{action},{reward},{}\n", obs.join(",")).as_str());
+    }
+    match std::fs::write(path, csv) {
+        Ok(_) => println!("best episode saved {path} steps:{}", episode.len()),
+        Err(e) => println!("best episode saving error: {}", e),
+    }
+}
@@ -1,11 +1,433 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PresentMode};
+use bevy_egui::{egui, EguiContexts};
+use bevy_garage_camera::CameraConfig;
+use bevy_garage_car::{AeroConfig, PhysicsParams, SuspensionConfig, Weather};
+
+#[derive(Debug, Clone)]
+pub struct PlayerBindings {
+    pub gas: KeyCode,
+    pub brake: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub respawn: KeyCode,
+    /// Puts the car back on the track upright at the nearest centerline point.
+    pub reset: KeyCode,
+    /// Held to lock the rear wheels.
+    pub handbrake: KeyCode,
+    /// Ramps keyboard steering like an analog stick, `None` steers at full deflection.
+    pub steering_ramp: Option<SteeringRamp>,
+}
+
+impl PlayerBindings {
+    pub fn arrows() -> Self {
+        Self {
+            gas: KeyCode::ArrowUp,
+            brake: KeyCode::ArrowDown,
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            respawn: KeyCode::Space,
+            reset: KeyCode::Enter,
+            handbrake: KeyCode::ControlRight,
+            steering_ramp: None,
+        }
+    }
+    pub fn wasd() -> Self {
+        Self {
+            gas: KeyCode::KeyW,
+            brake: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            respawn: KeyCode::Tab,
+            reset: KeyCode::KeyF,
+            handbrake: KeyCode::ControlLeft,
+            steering_ramp: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SteeringRamp {
+    /// Seconds from center to full deflection while a key is held.
+    pub attack: f32,
+    /// Seconds from full deflection back to center after release.
+    pub release: f32,
+}
+
+impl Default for SteeringRamp {
+    fn default() -> Self {
+        Self {
+            attack: 0.25,
+            release: 0.15,
+        }
+    }
+}
+
+impl SteeringRamp {
+    pub fn step(&self, steering: f32, target: f32, dt: f32) -> f32 {
+        let seconds = match target == 0. {
+            true => self.release,
+            false => self.attack,
+        };
+        let max_step = dt / seconds.max(0.001);
+        steering + (target - steering).clamp(-max_step, max_step)
+    }
+}
+
+/// Deadzone and response curve for an analog axis in `-1..1`.
+#[derive(Debug, Clone, Copy)]
+pub struct AnalogResponse {
+    /// Axis magnitude below which the input reads as zero.
+    pub deadzone: f32,
+    /// 1 is linear, 2 quadratic, higher values soften the center further.
+    pub exponent: f32,
+}
+
+impl Default for AnalogResponse {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+impl AnalogResponse {
+    pub fn linear() -> Self {
+        Self {
+            deadzone: 0.1,
+            exponent: 1.,
+        }
+    }
+    pub fn quadratic() -> Self {
+        Self {
+            deadzone: 0.1,
+            exponent: 2.,
+        }
+    }
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        if magnitude <= self.deadzone || !value.is_finite() {
+            return 0.;
+        }
+        let deadzone = self.deadzone.clamp(0., 0.99);
+        let scaled = ((magnitude - deadzone) / (1. - deadzone)).min(1.);
+        scaled.powf(self.exponent.max(0.1)).copysign(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeWarningStyle {
+    /// Red glow on the screen side the road edge is on.
+    Tint,
+    /// Arrow pointing back to the centerline.
+    Arrow,
+}
+
+/// Assist warning the first local player before the car leaves the road.
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeWarning {
+    pub enabled: bool,
+    /// Meters from the road edge where the warning fades in, it's full at the edge.
+    pub margin: f32,
+    /// Seconds of sideways motion added to the offset, warns earlier when sliding out.
+    pub lookahead: f32,
+    pub style: EdgeWarningStyle,
+}
+
+impl Default for EdgeWarning {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            margin: 1.5,
+            lookahead: 0.5,
+            style: EdgeWarningStyle::Tint,
+        }
+    }
+}
+
+impl EdgeWarning {
+    /// Warning strength in `0..1` for a car `lateral` meters off the centerline of
+    /// a road `half_width` meters wide each side, moving sideways at `lateral_speed`
+    /// m/s, lateral values positive to the same side.
+    pub fn strength(&self, half_width: f32, lateral: f32, lateral_speed: f32) -> f32 {
+        let outward = (lateral_speed * lateral.signum()).max(0.);
+        let offset = lateral.abs() + outward * self.lookahead;
+        let edge_distance = half_width - offset;
+        (1. - edge_distance / self.margin.max(0.01)).clamp(0., 1.)
+    }
+}
 
 #[derive(Resource)]
 pub struct Config {
     // pub cars_count: usize,
+    /// One entry per local player, index is the player's `LocalPlayer::index`.
+    pub local_players: Vec<PlayerBindings>,
+    /// Meters between local player cars on the start grid.
+    pub local_players_gap: f32,
+    /// Upper bound for the fast-forward time scale, the solver gets unstable beyond it.
+    pub max_time_scale: f32,
+    pub steering_response: AnalogResponse,
+    pub throttle_response: AnalogResponse,
+    /// Name of a registered engine sound profile, picked at startup.
+    pub engine_profile: String,
+    pub edge_warning: EdgeWarning,
 }
 impl Default for Config {
     fn default() -> Self {
-        Self {}
+        Self {
+            local_players: vec![PlayerBindings::arrows()],
+            local_players_gap: 10.,
+            max_time_scale: 8.,
+            steering_response: AnalogResponse::quadratic(),
+            throttle_response: AnalogResponse::linear(),
+            engine_profile: "inline-4".to_string(),
+            edge_warning: EdgeWarning::default(),
+        }
+    }
+}
+impl Config {
+    pub fn two_local_players() -> Self {
+        Self {
+            local_players: vec![PlayerBindings::arrows(), PlayerBindings::wasd()],
+            ..default()
+        }
+    }
+}
+
+#[cfg(feature = "dsp")]
+pub fn engine_profile_start_system(
+    config: Res<Config>,
+    mut profiles: ResMut<bevy_garage_dsp::EngineProfiles>,
+) {
+    if !profiles.select(&config.engine_profile) {
+        println!("unknown engine profile {}", config.engine_profile);
+    }
+}
+
+pub fn camera_players_start_system(config: Res<Config>, mut camera_config: ResMut<CameraConfig>) {
+    camera_config.players = config.local_players.len();
+}
+
+/// The trainer view is for watching agents learn, keep it steady.
+#[cfg(feature = "nn")]
+pub fn camera_shake_off_start_system(mut camera_config: ResMut<CameraConfig>) {
+    camera_config.shake.enabled = false;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsaaLevel {
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl MsaaLevel {
+    pub const ALL: [MsaaLevel; 4] = [MsaaLevel::Off, MsaaLevel::X2, MsaaLevel::X4, MsaaLevel::X8];
+    pub fn msaa(&self) -> Msaa {
+        match self {
+            MsaaLevel::Off => Msaa::Off,
+            MsaaLevel::X2 => Msaa::Sample2,
+            MsaaLevel::X4 => Msaa::Sample4,
+            MsaaLevel::X8 => Msaa::Sample8,
+        }
     }
+    pub fn label(&self) -> &'static str {
+        match self {
+            MsaaLevel::Off => "off",
+            MsaaLevel::X2 => "2x",
+            MsaaLevel::X4 => "4x",
+            MsaaLevel::X8 => "8x",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct GraphicsConfig {
+    pub msaa: MsaaLevel,
+    pub vsync: bool,
+}
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        Self {
+            msaa: MsaaLevel::X4,
+            vsync: true,
+        }
+    }
+}
+
+pub fn graphics_config_system(
+    config: Res<GraphicsConfig>,
+    mut msaa: ResMut<Msaa>,
+    mut windows: Query<&mut Window>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let level = config.msaa.msaa();
+    if *msaa != level {
+        *msaa = level;
+    }
+    let present_mode = match config.vsync {
+        true => PresentMode::AutoVsync,
+        false => PresentMode::AutoNoVsync,
+    };
+    for mut window in windows.iter_mut() {
+        if window.present_mode != present_mode {
+            window.present_mode = present_mode;
+        }
+    }
+}
+
+pub fn graphics_settings_ui_system(
+    mut egui_contexts: EguiContexts,
+    mut config: ResMut<GraphicsConfig>,
+    mut camera_config: ResMut<CameraConfig>,
+) {
+    egui::Window::new("Graphics")
+        .default_open(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let mut msaa = config.msaa;
+            ui.horizontal(|ui| {
+                ui.label("msaa");
+                for level in MsaaLevel::ALL {
+                    ui.radio_value(&mut msaa, level, level.label());
+                }
+            });
+            let mut vsync = config.vsync;
+            ui.checkbox(&mut vsync, "vsync");
+            let mut shake = camera_config.shake.enabled;
+            ui.checkbox(&mut shake, "camera shake");
+            if shake != camera_config.shake.enabled {
+                camera_config.shake.enabled = shake;
+            }
+            // only touch the resource on edits, the apply system runs on change
+            if msaa != config.msaa || vsync != config.vsync {
+                config.msaa = msaa;
+                config.vsync = vsync;
+            }
+        });
+}
+
+pub fn suspension_settings_ui_system(
+    mut egui_contexts: EguiContexts,
+    mut config: ResMut<SuspensionConfig>,
+    mut weather: ResMut<Weather>,
+) {
+    egui::Window::new("Suspension")
+        .default_open(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let mut edited_weather = *weather;
+            ui.horizontal(|ui| {
+                ui.label("weather");
+                for option in Weather::ALL {
+                    ui.radio_value(&mut edited_weather, option, option.name());
+                }
+            });
+            weather.set_if_neq(edited_weather);
+            let mut edited = config.clone();
+            ui.add(
+                egui::Slider::new(&mut edited.stiffness, 1e4..=1e7)
+                    .logarithmic(true)
+                    .text("stiffness"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.damping, 1e1..=1e5)
+                    .logarithmic(true)
+                    .text("damping"),
+            );
+            ui.add(egui::Slider::new(&mut edited.ride_height, 0.0..=0.3).text("ride height"));
+            let mut limited = edited.max_travel.is_some();
+            ui.checkbox(&mut limited, "limit travel");
+            edited.max_travel = match (limited, edited.max_travel) {
+                (true, Some(mut travel)) => {
+                    ui.add(egui::Slider::new(&mut travel, 0.01..=0.5).text("travel"));
+                    Some(travel)
+                }
+                (true, None) => Some(0.2),
+                (false, _) => None,
+            };
+            // only touch the resource on edits, joints are rebuilt on change
+            if edited != *config {
+                *config = edited;
+            }
+        });
+}
+
+pub fn aero_settings_ui_system(mut egui_contexts: EguiContexts, mut config: ResMut<AeroConfig>) {
+    egui::Window::new("Aero")
+        .default_open(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let mut edited = *config;
+            ui.horizontal(|ui| {
+                if ui.button("default").clicked() {
+                    edited = AeroConfig::default();
+                }
+                if ui.button("high downforce").clicked() {
+                    edited = AeroConfig::high_downforce();
+                }
+            });
+            ui.add(egui::Slider::new(&mut edited.drag_area, 0.0..=1.5).text("drag area"));
+            ui.add(egui::Slider::new(&mut edited.downforce, 0.0..=10.0).text("downforce"));
+            config.set_if_neq(edited);
+        });
+}
+
+/// Solver settings, slider edits reach the solver on "apply" and
+/// `physics_params_system` hands them to the rapier context.
+pub fn physics_settings_ui_system(
+    mut egui_contexts: EguiContexts,
+    mut params: ResMut<PhysicsParams>,
+    mut edited: Local<Option<PhysicsParams>>,
+) {
+    egui::Window::new("Physics")
+        .default_open(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let edited = edited.get_or_insert_with(|| params.clone());
+            ui.horizontal(|ui| {
+                if ui.button("standard").clicked() {
+                    *edited = PhysicsParams::standard();
+                }
+                if ui.button("stable").clicked() {
+                    *edited = PhysicsParams::stable();
+                }
+                if ui.button("fast").clicked() {
+                    *edited = PhysicsParams::fast();
+                }
+            });
+            ui.add(
+                egui::Slider::new(&mut edited.max_dt, 1. / 240.0..=1. / 30.)
+                    .logarithmic(true)
+                    .text("max dt"),
+            );
+            ui.add(egui::Slider::new(&mut edited.max_catch_up, 1..=8).text("max catch up"));
+            ui.add(egui::Slider::new(&mut edited.substeps, 1..=20).text("substeps"));
+            ui.add(egui::Slider::new(&mut edited.solver_iterations, 1..=16).text("iterations"));
+            ui.add(
+                egui::Slider::new(&mut edited.warmstart_coefficient, 0.0..=1.0).text("warmstart"),
+            );
+            // rapier derives erp from these two
+            ui.add(
+                egui::Slider::new(&mut edited.contact_natural_frequency, 1.0..=100.0)
+                    .logarithmic(true)
+                    .text("contact frequency"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.contact_damping_ratio, 0.1..=100.0)
+                    .logarithmic(true)
+                    .text("contact damping"),
+            );
+            ui.horizontal(|ui| {
+                let changed = *edited != *params;
+                if ui
+                    .add_enabled(changed, egui::Button::new("apply"))
+                    .clicked()
+                {
+                    *params = edited.clone();
+                }
+                if ui
+                    .add_enabled(changed, egui::Button::new("revert"))
+                    .clicked()
+                {
+                    *edited = params.clone();
+                }
+            });
+        });
 }
@@ -3,9 +3,10 @@ use bevy::{
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     prelude::*,
 };
-use bevy_garage_car::Player;
-use bevy_garage_track::CarTrack;
+use bevy_garage_car::{LocalPlayer, Player};
+use bevy_garage_track::{CarTrack, LapState, RacePosition};
 use bevy_rapier3d::prelude::*;
+use std::time::Duration;
 
 #[derive(Component)]
 pub struct FpsText;
@@ -19,6 +20,14 @@ pub struct KmphText;
 #[derive(Component)]
 pub struct LapText;
 
+/// Current, last and best lap time, left of the dash.
+#[derive(Component)]
+pub struct LapTimeText;
+
+/// Race position under the lap count.
+#[derive(Component)]
+pub struct PositionText;
+
 #[derive(Component)]
 pub struct TrackPositionText;
 
@@ -113,6 +122,55 @@ pub fn dash_start_system(mut cmd: Commands, asset_server: Res<AssetServer>) {
                         ..default()
                     })
                     .insert(LapText);
+                parent
+                    .spawn(TextBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            top: Val::Px(38.),
+                            left: Val::Px(4.),
+                            ..default()
+                        },
+                        text: Text {
+                            sections: vec![TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: medium.clone(),
+                                    font_size: 18.0,
+                                    color: css::SALMON.into(),
+                                },
+                            }],
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(PositionText);
+                parent
+                    .spawn(TextBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            margin: UiRect {
+                                right: Val::Px(4.),
+                                ..default()
+                            },
+                            top: Val::Px(4.),
+                            right: Val::Percent(100.),
+                            ..default()
+                        },
+                        text: Text {
+                            justify: JustifyText::Right,
+                            sections: vec![TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: medium.clone(),
+                                    font_size: 14.0,
+                                    color: css::SALMON.into(),
+                                },
+                            }],
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(LapTimeText);
                 parent
                     .spawn(TextBundle {
                         style: Style {
@@ -284,11 +342,13 @@ pub fn dash_speed_update_system(
         Query<&mut Text, With<KmphText>>,
         Query<&mut Text, With<TrackPositionText>>,
         Query<&mut Text, With<RideDistanceText>>,
-        Query<&mut Text, With<LapText>>,
     )>,
-    mut cars: Query<(&Velocity, &CarTrack), With<Player>>,
+    mut cars: Query<(&Velocity, &CarTrack, Option<&LocalPlayer>), With<Player>>,
 ) {
-    for (velocity, car_track) in cars.iter_mut() {
+    for (velocity, car_track, local_player) in cars.iter_mut() {
+        if local_player.is_some_and(|local_player| local_player.index != 0) {
+            continue;
+        }
         let mps = velocity.linvel.length();
         let kmph = mps * 3.6;
         texts.p0().single_mut().sections[0].value = format!("{:.1}m/s", mps);
@@ -303,7 +363,54 @@ pub fn dash_speed_update_system(
         };
         texts.p3().single_mut().sections[0].value =
             format!("{sign}{:.1}m", car_track.ride_distance.abs());
+    }
+}
 
-        texts.p4().single_mut().sections[0].value = format!("lap {}", car_track.lap);
+/// Lap count, race position and lap times of the first local player.
+pub fn dash_lap_update_system(
+    mut texts: ParamSet<(
+        Query<&mut Text, With<LapText>>,
+        Query<&mut Text, With<PositionText>>,
+        Query<&mut Text, With<LapTimeText>>,
+    )>,
+    cars: Query<
+        (
+            Option<&LocalPlayer>,
+            Option<&LapState>,
+            Option<&RacePosition>,
+        ),
+        With<Player>,
+    >,
+) {
+    for (local_player, lap_state, race_position) in cars.iter() {
+        if local_player.is_some_and(|local_player| local_player.index != 0) {
+            continue;
+        }
+        let lap = lap_state.map_or(0, |state| state.lap);
+        texts.p0().single_mut().sections[0].value = format!("lap {}", lap);
+        texts.p1().single_mut().sections[0].value = match race_position {
+            Some(position) => format!("P{}/{}", position.place, position.of),
+            None => "".to_string(),
+        };
+
+        let last = lap_state.and_then(|state| state.last_lap);
+        let best = lap_state.and_then(|state| state.best_lap);
+        texts.p2().single_mut().sections[0].value = format!(
+            "now  {}\nlast {}\nbest {}",
+            lap_time_text(lap_state.map(|state| state.lap_time)),
+            lap_time_text(last),
+            lap_time_text(best),
+        );
+    }
+}
+
+/// `M:SS.mmm`, dashes before the first timed lap.
+fn lap_time_text(time: Option<Duration>) -> String {
+    match time {
+        Some(time) => {
+            let seconds = time.as_secs_f32();
+            format!("{}:{:06.3}", (seconds / 60.) as u32, seconds % 60.)
+        }
+        None => "-:--.---".to_string(),
     }
 }
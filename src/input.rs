@@ -1,7 +1,10 @@
+use crate::config::{Config, EdgeWarningStyle, SteeringRamp};
 use bevy::{prelude::*, render::camera::camera_system};
+use bevy_egui::{egui, EguiContexts};
 use bevy_garage_camera::CameraConfig;
-use bevy_garage_car::{Car, CarRes, CarWheels, Player};
-use bevy_garage_track::SpawnCarOnTrackEvent;
+use bevy_garage_car::{Car, CarRes, CarWheels, LocalPlayer, PhysicsParams, Player};
+use bevy_garage_track::{CarTrack, ResetCarOnTrackEvent, SpawnCarOnTrackEvent, TrackConfig};
+use bevy_rapier3d::prelude::{RapierConfiguration, Velocity};
 
 pub fn input_system(
     input: Res<ButtonInput<KeyCode>>,
@@ -9,11 +12,15 @@ pub fn input_system(
     axes: Res<Axis<GamepadAxis>>,
     gamepads: Res<Gamepads>,
     mut camera_config: ResMut<CameraConfig>,
-    mut cars: Query<(&mut Car, &mut CarWheels, Entity, &Transform), With<Player>>,
+    config: Res<Config>,
+    mut cars: Query<(&mut Car, &mut CarWheels, Entity, &LocalPlayer), With<Player>>,
     mut cmd: Commands,
     mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>,
+    mut car_reset_events: EventWriter<ResetCarOnTrackEvent>,
     mut debug_ctx: ResMut<bevy_rapier3d::render::DebugRenderContext>,
     mut car_res: ResMut<CarRes>,
+    time: Res<Time>,
+    mut keyboard_steering: Local<Vec<bool>>,
     #[cfg(feature = "nn")] mut dqn: ResMut<bevy_garage_nn::DqnResource>,
 ) {
     #[cfg(feature = "nn")]
@@ -24,35 +31,41 @@ pub fn input_system(
         debug_ctx.enabled = !debug_ctx.enabled;
         car_res.show_rays = debug_ctx.enabled;
     }
-    for (mut car, mut wheels, e, _transform) in cars.iter_mut() {
-        for gamepad in gamepads.iter() {
-            let left_stick_x = axes
-                .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
-                .unwrap();
+    for (mut car, mut wheels, e, local_player) in cars.iter_mut() {
+        let Some(bindings) = config.local_players.get(local_player.index) else {
+            continue;
+        };
+        // gamepads are assigned to local players in connection order
+        for gamepad in gamepads.iter().skip(local_player.index).take(1) {
+            let left_stick_x = config.steering_response.apply(
+                axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+                    .unwrap(),
+            );
             // dbg!(left_stick_x);
-            car.steering = left_stick_x;
+            car.set_steering(left_stick_x);
 
-            let right_stick_y = axes
-                .get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY))
-                .unwrap();
+            let right_stick_y = config.throttle_response.apply(
+                axes.get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY))
+                    .unwrap(),
+            );
             // dbg!(right_stick_y);
             if right_stick_y < 0. {
-                car.brake = -right_stick_y / 0.75;
-                car.gas = 0.;
+                car.set_brake(-right_stick_y / 0.75);
+                car.set_gas(0.);
             } else {
-                car.gas = right_stick_y / 0.75;
-                car.brake = 0.;
+                car.set_gas(right_stick_y / 0.75);
+                car.set_brake(0.);
             }
 
             if buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::North)) {
-                car.gas = 1.;
+                car.set_gas(1.);
             } else if buttons.just_released(GamepadButton::new(gamepad, GamepadButtonType::North)) {
-                car.gas = 0.;
+                car.set_gas(0.);
             }
             if buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::South)) {
-                car.brake = 1.;
+                car.set_brake(1.);
             } else if buttons.just_released(GamepadButton::new(gamepad, GamepadButtonType::South)) {
-                car.brake = 0.;
+                car.set_brake(0.);
             }
             if buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger)) {
                 debug_ctx.enabled = !debug_ctx.enabled;
@@ -63,41 +76,61 @@ pub fn input_system(
             }
         }
 
-        if input.just_pressed(KeyCode::Space) && input.pressed(KeyCode::ShiftLeft) {
+        if input.just_pressed(bindings.respawn) && input.pressed(KeyCode::ShiftLeft) {
             cmd.entity(e).despawn_recursive();
             wheels.despawn(&mut cmd);
 
             car_spawn_events.send(SpawnCarOnTrackEvent {
                 player: true,
-                index: 0,
+                index: local_player.index,
                 position: None,
             });
         }
-        if input.pressed(KeyCode::ArrowUp) {
-            car.gas = 1.;
+        if input.just_pressed(bindings.reset) {
+            car_reset_events.send(ResetCarOnTrackEvent { entity: e });
         }
-        if input.just_released(KeyCode::ArrowUp) {
-            car.gas = 0.;
+        if input.pressed(bindings.gas) {
+            car.set_gas(1.);
         }
-
-        if input.pressed(KeyCode::ArrowDown) {
-            car.brake = 1.;
-        }
-        if input.just_released(KeyCode::ArrowDown) {
-            car.brake = 0.;
+        if input.just_released(bindings.gas) {
+            car.set_gas(0.);
         }
 
-        if input.pressed(KeyCode::ArrowLeft) {
-            car.steering = -1.;
-        }
-        if input.pressed(KeyCode::ArrowRight) {
-            car.steering = 1.;
+        if input.pressed(bindings.brake) {
+            car.set_brake(1.);
         }
-        if input.just_released(KeyCode::ArrowLeft) {
-            car.steering = 0.;
+        if input.just_released(bindings.brake) {
+            car.set_brake(0.);
         }
-        if input.just_released(KeyCode::ArrowRight) {
-            car.steering = 0.;
+        car.set_handbrake(input.pressed(bindings.handbrake));
+
+        if let Some(ramp) = bindings.steering_ramp {
+            if keyboard_steering.len() <= local_player.index {
+                keyboard_steering.resize(local_player.index + 1, false);
+            }
+            let target = input.pressed(bindings.right) as i32 as f32
+                - input.pressed(bindings.left) as i32 as f32;
+            // only ramp back to center what the keyboard deflected, not the gamepad
+            let steering_by_keyboard = &mut keyboard_steering[local_player.index];
+            *steering_by_keyboard |= target != 0.;
+            if *steering_by_keyboard {
+                let steering = ramp.step(car.steering, target, time.delta_seconds());
+                car.set_steering(steering);
+                *steering_by_keyboard = target != 0. || car.steering != 0.;
+            }
+        } else {
+            if input.pressed(bindings.left) {
+                car.set_steering(-1.);
+            }
+            if input.pressed(bindings.right) {
+                car.set_steering(1.);
+            }
+            if input.just_released(bindings.left) {
+                car.set_steering(0.);
+            }
+            if input.just_released(bindings.right) {
+                car.set_steering(0.);
+            }
         }
         // if input.just_released(KeyCode::Space) {
         //     car.gas = 0.;
@@ -106,9 +139,197 @@ pub fn input_system(
         // if input.pressed(KeyCode::Space) {
         //     car.gas = 0.;
         //     car.brake = 1.;
-        if (input.just_released(KeyCode::KeyN)) {
-            camera_config.next_view();
-        }
         // }
     }
+    if input.just_released(KeyCode::KeyN) {
+        camera_config.next_view();
+    }
+}
+
+/// Fast-forward with `]` and slow back down with `[`.
+/// The virtual clock is scaled instead of rapier `time_scale`, so physics, esp smoothing
+/// and dqn timers all see the same time. Max dt and substeps grow with the scale
+/// to keep the substep dt, and the solver stability, unchanged.
+pub fn time_scale_system(
+    input: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+    physics_params: Res<PhysicsParams>,
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let speed = time.relative_speed();
+    let speed = if input.just_pressed(KeyCode::BracketRight) {
+        speed * 2.
+    } else if input.just_pressed(KeyCode::BracketLeft) {
+        speed / 2.
+    } else {
+        return;
+    }
+    .clamp(1., config.max_time_scale);
+    time.set_relative_speed(speed);
+    rapier_config.timestep_mode = physics_params.timestep_mode(speed);
+    info!("time scale {speed}x");
+}
+
+pub fn input_settings_ui_system(mut egui_contexts: EguiContexts, mut config: ResMut<Config>) {
+    egui::Window::new("Input")
+        .default_open(false)
+        .show(egui_contexts.ctx_mut(), |ui| {
+            let Config {
+                steering_response,
+                throttle_response,
+                local_players,
+                edge_warning,
+                ..
+            } = &mut *config;
+            ui.checkbox(&mut edge_warning.enabled, "track edge warning");
+            if edge_warning.enabled {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut edge_warning.style, EdgeWarningStyle::Tint, "tint");
+                    ui.radio_value(&mut edge_warning.style, EdgeWarningStyle::Arrow, "arrow");
+                });
+                ui.add(egui::Slider::new(&mut edge_warning.margin, 0.5..=4.0).text("margin m"));
+            }
+            for (name, response) in [
+                ("steering", steering_response),
+                ("throttle", throttle_response),
+            ] {
+                ui.label(name);
+                ui.add(egui::Slider::new(&mut response.deadzone, 0.0..=0.5).text("deadzone"));
+                ui.add(egui::Slider::new(&mut response.exponent, 0.5..=4.0).text("exponent"));
+            }
+            for (i, bindings) in local_players.iter_mut().enumerate() {
+                let mut ramp = bindings.steering_ramp.is_some();
+                ui.checkbox(
+                    &mut ramp,
+                    format!("player {} keyboard steering ramp", i + 1),
+                );
+                match (ramp, &mut bindings.steering_ramp) {
+                    (true, Some(ramp)) => {
+                        ui.add(egui::Slider::new(&mut ramp.attack, 0.0..=1.0).text("attack s"));
+                        ui.add(egui::Slider::new(&mut ramp.release, 0.0..=1.0).text("release s"));
+                    }
+                    (true, steering_ramp) => *steering_ramp = Some(SteeringRamp::default()),
+                    (false, steering_ramp) => *steering_ramp = None,
+                }
+            }
+        });
+}
+
+/// F8 toggles a small overlay with the controls of every local car as esp sees them.
+pub fn input_display_system(
+    mut egui_contexts: EguiContexts,
+    input: Res<ButtonInput<KeyCode>>,
+    cars: Query<(&Car, &LocalPlayer)>,
+    mut show: Local<bool>,
+) {
+    if input.just_pressed(KeyCode::F8) {
+        *show = !*show;
+    }
+    if !*show {
+        return;
+    }
+    let mut cars: Vec<(&Car, &LocalPlayer)> = cars.iter().collect();
+    cars.sort_by_key(|(_, local)| local.index);
+    egui::Area::new(egui::Id::new("input_display"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10., -10.))
+        .show(egui_contexts.ctx_mut(), |ui| {
+            for (car, local) in cars {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_width(160.);
+                    ui.label(format!("player {}", local.index + 1));
+                    ui.add(egui::ProgressBar::new(car.gas.clamp(0., 1.)).text("gas"));
+                    ui.add(egui::ProgressBar::new(car.brake.clamp(0., 1.)).text("brake"));
+                    steering_bar(ui, car.steering);
+                });
+            }
+        });
+}
+
+/// Shift+F9 writes the track to `APP_TRACK_EXPORT_PATH`, `<track>-export.csv` by default,
+/// load it back with `TRACK_FILE`.
+pub fn track_export_system(input: Res<ButtonInput<KeyCode>>, track_config: Res<TrackConfig>) {
+    if !(input.just_pressed(KeyCode::F9) && input.pressed(KeyCode::ShiftLeft)) {
+        return;
+    }
+    let path = std::env::var("APP_TRACK_EXPORT_PATH")
+        .unwrap_or_else(|_| format!("{}-export.csv", track_config.source.name));
+    match std::fs::write(&path, track_config.to_csv()) {
+        Ok(()) => println!("track exported to {path}"),
+        Err(err) => println!("track export to {path} failed: {err}"),
+    }
+}
+
+/// Warns the first local player when the car gets close to a road edge or slides
+/// towards it, using the centerline projection of `progress_system`.
+pub fn edge_warning_system(
+    mut egui_contexts: EguiContexts,
+    config: Res<Config>,
+    track_config: Res<TrackConfig>,
+    cars: Query<(&Transform, &Velocity, &CarTrack, &LocalPlayer)>,
+) {
+    let warning = config.edge_warning;
+    if !warning.enabled {
+        return;
+    }
+    let Some((transform, velocity, car_track, _)) =
+        cars.iter().find(|(_, _, _, local)| local.index == 0)
+    else {
+        return;
+    };
+    // forward is +Z and left is +X, for the track and for the car
+    let track_left = Vec3::Y.cross(car_track.line_dir).normalize_or_zero();
+    let offset = transform.translation - car_track.line_pos;
+    let lateral = offset.dot(track_left);
+    let strength = warning.strength(
+        track_config.source.half_width,
+        lateral,
+        velocity.linvel.dot(track_left),
+    );
+    if strength <= 0. {
+        return;
+    }
+    // screen side of the edge as seen from the car
+    let edge_on_left = (track_left * lateral).dot(transform.rotation * Vec3::X) > 0.;
+    let ctx = egui_contexts.ctx_mut();
+    let screen = ctx.screen_rect();
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let color = egui::Color32::from_rgba_unmultiplied(220, 40, 30, (strength * 120.) as u8);
+    match warning.style {
+        EdgeWarningStyle::Tint => {
+            let width = screen.width() * 0.08;
+            let rect = match edge_on_left {
+                true => egui::Rect::from_min_max(
+                    screen.left_top(),
+                    egui::pos2(screen.left() + width, screen.bottom()),
+                ),
+                false => egui::Rect::from_min_max(
+                    egui::pos2(screen.right() - width, screen.top()),
+                    screen.right_bottom(),
+                ),
+            };
+            painter.rect_filled(rect, 0., color);
+        }
+        EdgeWarningStyle::Arrow => {
+            let center = egui::pos2(screen.center().x, screen.top() + screen.height() * 0.2);
+            let dir = if edge_on_left { 1. } else { -1. };
+            let vec = egui::vec2(dir * 40., 0.);
+            painter.arrow(center - vec / 2., vec, egui::Stroke::new(6., color));
+        }
+    }
+}
+
+/// Bar filled from the center, to the left for negative `steering`.
+fn steering_bar(ui: &mut egui::Ui, steering: f32) {
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 12.), egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 2., ui.visuals().extreme_bg_color);
+    let x = rect.center().x + steering.clamp(-1., 1.) * rect.width() / 2.;
+    let fill = egui::Rect::from_x_y_ranges(
+        rect.center().x.min(x)..=rect.center().x.max(x),
+        rect.y_range(),
+    );
+    painter.rect_filled(fill, 2., ui.visuals().selection.bg_fill);
+    painter.vline(rect.center().x, rect.y_range(), ui.visuals().window_stroke);
 }
@@ -1,4 +1,4 @@
-use crate::CarSet;
+use crate::{config::Config, CarSet};
 use bevy::prelude::*;
 use bevy_garage_car::{Car, Player};
 use virtual_joystick::*;
@@ -79,22 +79,28 @@ pub fn joystick_start_system(mut cmd: Commands, asset_server: Res<AssetServer>)
 fn update_joystick(
     mut virtual_joystick_events: EventReader<VirtualJoystickEvent<JoystickTypeAxis>>,
     mut cars: Query<&mut Car, With<Player>>,
+    config: Res<Config>,
 ) {
     for mut car in cars.iter_mut() {
         for j in virtual_joystick_events.read() {
             let Vec2 { x, y } = j.axis();
+            // node dead_zone stays 0, the shared analog response handles it
+            let (x, y) = (
+                config.steering_response.apply(x),
+                config.throttle_response.apply(y),
+            );
             // println!("x{x}, y{y}");
             match j.id() {
                 JoystickTypeAxis::X => {
-                    car.steering = x;
+                    car.set_steering(x);
                 }
                 JoystickTypeAxis::Y => {
                     if y < 0. {
-                        car.brake = -y;
-                        car.gas = 0.;
+                        car.set_brake(-y);
+                        car.set_gas(0.);
                     } else {
-                        car.gas = y;
-                        car.brake = 0.;
+                        car.set_gas(y);
+                        car.set_brake(0.);
                     }
                 }
             }
@@ -1,20 +1,25 @@
 #![feature(slice_flatten)]
-mod config;
+pub mod config;
 mod dash;
 pub mod font;
 mod input;
 #[cfg(feature = "virtual_joystick")]
 pub mod joystick;
 mod spawn;
-use std::num::NonZeroUsize;
-
 use bevy::{
     diagnostic::FrameTimeDiagnosticsPlugin, ecs::system::SystemParam,
     pbr::DirectionalLightShadowMap, prelude::*,
 };
-use bevy_garage_car::{aero_system, car_start_system, esp_system, CarRes, CarSet};
+use bevy_garage_car::{
+    aero_system, car_assets_error_system, car_assets_ready, car_settle_system, car_start_system,
+    configure_car_sets, esp_system, not_paused, pause_toggle_system, physics_catch_up_system,
+    physics_params_system, seed_from_env, show_rays_toggle_system, suspension_config_system,
+    weather_system, AeroConfig, CarRes, CarSet, Paused, SettleConfig, SuspensionConfig, Weather,
+};
 use bevy_garage_light::{animate_light_direction, light_start_system};
-use bevy_garage_track::{track_polyline_start_system, SpawnCarOnTrackEvent, TrackPlugin};
+use bevy_garage_track::{
+    track_polyline_start_system, GhostPlugin, SpawnCarOnTrackEvent, SpawnRng, TrackPlugin,
+};
 use bevy_rapier3d::prelude::*;
 use bevy_renet::{
     renet::{
@@ -33,38 +38,46 @@ use spawn::*;
 
 pub mod server;
 
-fn rapier_config_start_system(mut c: ResMut<RapierContext>) {
-    c.integration_parameters.num_solver_iterations = NonZeroUsize::new(6).unwrap();
-    c.integration_parameters.warmstart_coefficient = 0.;
-    c.integration_parameters.contact_natural_frequency = 50.;
-    c.integration_parameters.contact_damping_ratio = 50.;
-    // c.integration_parameters.num_internal_pgs_iterations = 16;
-    // c.integration_parameters.num_additional_friction_iterations = 8;
-    dbg!(c.integration_parameters);
-}
+pub use bevy_garage_car::PhysicsParams;
 
 pub fn car_app(app: &mut App) -> &mut App {
-    #[cfg(feature = "nn")]
-    let esp_run_after: CarSet = CarSet::NeuralNetwork;
-    #[cfg(not(feature = "nn"))]
-    let esp_run_after: CarSet = CarSet::Input;
+    configure_car_sets(app);
 
+    // apps may insert their own preset before calling car_app
+    let physics_params = app
+        .world()
+        .get_resource::<PhysicsParams>()
+        .cloned()
+        .unwrap_or_else(PhysicsParams::from_env)
+        .with_fixed_from_env();
     let mut rapier_config = RapierConfiguration::new(1.);
-    rapier_config.timestep_mode = TimestepMode::Variable {
-        max_dt: 1. / 60.,
-        time_scale: 1.,
-        substeps: 5,
-    };
+    rapier_config.timestep_mode = physics_params.timestep_mode(1.);
+    if physics_params.fixed.is_some() {
+        // random spawns repeat too, the training seed is taken by NeuralNetworkPlugin
+        app.insert_resource(SpawnRng::new(seed_from_env()));
+    }
     app.init_resource::<FontHandle>()
         .insert_resource(rapier_config)
-        .insert_resource(Msaa::Sample4)
-        .insert_resource(Config::default())
-        .insert_resource(CarRes::default())
+        .insert_resource(physics_params)
+        .init_resource::<GraphicsConfig>()
+        .init_resource::<Config>()
+        // rays are drawn from the start while training, F4 toggles them
+        .insert_resource(CarRes {
+            show_rays: cfg!(feature = "nn"),
+            ..default()
+        })
+        .init_resource::<SettleConfig>()
+        .insert_resource(bevy_garage_car::sensor::SensorConfig::from_env())
+        .init_resource::<SuspensionConfig>()
+        .init_resource::<Weather>()
+        .init_resource::<Paused>()
+        .insert_resource(AeroConfig::from_env())
         .insert_resource(DirectionalLightShadowMap::default())
         .add_plugins((
             FrameTimeDiagnosticsPlugin::default(),
             RapierPhysicsPlugin::<MyPhysicsHooks>::default(),
             TrackPlugin,
+            GhostPlugin,
             RapierDebugRenderPlugin {
                 enabled: false,
                 style: DebugRenderStyle {
@@ -84,22 +97,37 @@ pub fn car_app(app: &mut App) -> &mut App {
             Startup,
             (
                 car_start_system.after(track_polyline_start_system),
-                spawn_car_start_system.after(car_start_system),
+                camera_players_start_system,
                 light_start_system,
                 dash_start_system,
-                rapier_config_start_system,
             ),
         )
         .add_systems(
             Update,
             (
-                spawn_car_system,
-                aero_system.in_set(CarSet::Input),
-                input_system.in_set(CarSet::Input),
-                esp_system.in_set(CarSet::Esp).after(esp_run_after),
+                spawn_car_start_system.run_if(car_assets_ready.and_then(run_once())),
+                spawn_car_system
+                    .after(spawn_car_start_system)
+                    .run_if(car_assets_ready),
+                car_assets_error_system,
+                aero_system.in_set(CarSet::Input).run_if(not_paused),
+                input_system.in_set(CarSet::Input).run_if(not_paused),
+                car_settle_system
+                    .after(CarSet::NeuralNetwork)
+                    .before(CarSet::Esp),
+                esp_system.in_set(CarSet::Esp),
                 animate_light_direction,
                 dash_fps_system,
                 dash_speed_update_system,
+                dash_lap_update_system,
+                show_rays_toggle_system,
+                (time_scale_system, pause_toggle_system),
+                physics_catch_up_system.after(time_scale_system),
+                graphics_config_system,
+                physics_params_system,
+                suspension_config_system,
+                weather_system,
+                track_export_system,
             ),
         );
 
@@ -111,17 +139,35 @@ pub fn car_app(app: &mut App) -> &mut App {
     app.insert_resource(server).insert_resource(transport);
     app.add_plugins(bevy_egui::EguiPlugin);
     app.insert_resource(renet_visualizer::RenetServerVisualizer::<200>::default());
-    app.add_systems(Update, (update_visulizer_system, server_update_system));
+    app.add_systems(
+        Update,
+        (
+            update_visulizer_system,
+            server_update_system,
+            input_settings_ui_system,
+            input_display_system,
+            edge_warning_system,
+            graphics_settings_ui_system,
+            suspension_settings_ui_system,
+            aero_settings_ui_system,
+            physics_settings_ui_system,
+        ),
+    );
 
     ///////////////////////////////////////////////
 
     #[cfg(feature = "dsp")]
     {
-        app.add_plugins(bevy_garage_dsp::EngineSoundPlugin);
+        app.add_plugins(bevy_garage_dsp::EngineSoundPlugin)
+            .add_systems(Startup, engine_profile_start_system);
     }
+    // the network plugin casts the rays itself, in its CarSet::Input slot
+    #[cfg(not(feature = "nn"))]
+    app.add_systems(Update, bevy_garage_car::sensor::sensor_system);
     #[cfg(feature = "nn")]
     {
-        app.add_plugins(bevy_garage_nn::NeuralNetworkPlugin);
+        app.add_plugins(bevy_garage_nn::NeuralNetworkPlugin)
+            .add_systems(Startup, camera_shake_off_start_system);
     }
 
     app
@@ -8,12 +8,23 @@ fn main() {
     let res = WindowResolution::default();
     #[cfg(target_arch = "wasm32")]
     let res = WindowResolution::new(720., 360.);
+    // headless training only hides the window and stops waiting for vsync,
+    // the renderer keeps drawing once per frame
+    #[cfg(feature = "nn")]
+    let headless = bevy_garage_nn::TrainingMode::from_env().is_headless();
+    #[cfg(not(feature = "nn"))]
+    let headless = false;
     app.add_plugins((
         DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Bevy Garage".to_string(),
                 resolution: res,
                 canvas: Some("#bevy-garage".to_string()),
+                visible: !headless,
+                present_mode: match headless {
+                    true => bevy::window::PresentMode::AutoNoVsync,
+                    false => default(),
+                },
                 ..default()
             }),
             ..default()
@@ -23,4 +34,3 @@ fn main() {
 
     car_app(&mut app).run();
 }
-
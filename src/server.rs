@@ -1,6 +1,7 @@
 use std::{net::UdpSocket, time::Duration};
 
 use bevy::{
+    asset::AssetServer,
     input::ButtonInput,
     log::tracing_subscriber::fmt::time::SystemTime,
     math::Vec3,
@@ -17,7 +18,7 @@ use bevy_renet::{
         transport::{
             NetcodeServerTransport, ServerAuthentication, ServerConfig, NETCODE_KEY_BYTES,
         },
-        ChannelConfig, ConnectionConfig, RenetClient, RenetServer, SendType, ServerEvent,
+        ChannelConfig, ClientId, ConnectionConfig, RenetClient, RenetServer, SendType, ServerEvent,
     },
     transport::NetcodeServerPlugin,
     RenetServerPlugin,
@@ -79,10 +80,10 @@ pub enum ServerMessages {
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct NetworkedEntities {
     pub entities: Vec<Entity>,
-    pub translations: Vec<[f32; 3]>,
-    pub rotations: Vec<[f32; 4]>,
-    pub wheels_translations: Vec<[[f32; 3]; 4]>,
-    pub wheels_rotations: Vec<[[f32; 4]; 4]>,
+    pub positions: Vec<[f32; 3]>,
+    pub orientations: Vec<[f32; 4]>,
+    pub wheel_positions: Vec<Vec<[f32; 3]>>,
+    pub wheel_orientations: Vec<Vec<[f32; 4]>>,
 }
 
 impl From<ClientChannel> for u8 {
@@ -166,7 +167,10 @@ pub fn create_new_renet_server() -> (RenetServer, NetcodeServerTransport) {
 
     let server_configuration = ServerConfig {
         current_time,
-        max_clients: 12,
+        max_clients: std::env::var("MAX_CLIENTS")
+            .ok()
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(12),
         protocol_id: PROTOCOL_ID,
         public_addresses: vec![public_addr],
         authentication: ServerAuthentication::Unsecure,
@@ -184,52 +188,22 @@ pub fn server_update_system(
     mut server: ResMut<RenetServer>,
     players: Query<(Entity, &Player, &Transform)>,
     car_res: Res<bevy_garage_car::CarRes>,
+    asset_server: Res<AssetServer>,
     mut visualizer: ResMut<renet_visualizer::RenetServerVisualizer<200>>,
+    // connects wait for the car scenes, server events don't
+    mut pending: Local<Vec<ClientId>>,
 ) {
     for event in server_events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 println!("Client {} connected.", client_id);
-
                 visualizer.add_client(*client_id);
-
-                // Send startup message to client
-                server.send_message(
-                    *client_id,
-                    ServerChannel::ServerMessages,
-                    b"Welcome!".as_slice(),
-                );
-
-                // Send information and position of all other players
-                for (entity, player, transform) in players.iter() {
-                    let translation: [f32; 3] = transform.translation.into();
-                    let message = bincode::serialize(&ServerMessages::PlayerCreate {
-                        id: player.id,
-                        entity,
-                        translation,
-                    })
-                    .unwrap();
-                    server.send_message(*client_id, ServerChannel::ServerMessages, message);
-                }
-
-                // Create new player
-                let transform = Transform::from_xyz(0., 0., 0.);
-                let player_entity = spawn_car(
-                    &mut cmd,
-                    &car_res.car_scene.as_ref().unwrap(),
-                    &car_res.wheel_scene.as_ref().unwrap(),
-                    false,
-                    transform,
-                );
-
-                cmd.entity(player_entity).insert(Player {
-                    id: client_id.raw(),
-                });
-                lobby.players.insert(client_id.raw(), player_entity);
+                pending.push(*client_id);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 println!("Client {} disconnected for reason: {} ", client_id, reason);
                 visualizer.remove_client(*client_id);
+                pending.retain(|id| id != client_id);
                 if let Some(player_entity) = lobby.players.remove(&client_id.raw()) {
                     cmd.entity(player_entity).despawn();
                 }
@@ -245,6 +219,45 @@ pub fn server_update_system(
             }
         }
     }
+
+    if !car_res.scenes_loaded(&asset_server) {
+        return;
+    }
+    for client_id in pending.drain(..) {
+        // Send startup message to client
+        server.send_message(
+            client_id,
+            ServerChannel::ServerMessages,
+            b"Welcome!".as_slice(),
+        );
+
+        // Send information and position of all other players
+        for (entity, player, transform) in players.iter() {
+            let translation: [f32; 3] = transform.translation.into();
+            let message = bincode::serialize(&ServerMessages::PlayerCreate {
+                id: player.id,
+                entity,
+                translation,
+            })
+            .unwrap();
+            server.send_message(client_id, ServerChannel::ServerMessages, message);
+        }
+
+        // Create new player
+        let transform = Transform::from_xyz(0., 0., 0.);
+        let player_entity = spawn_car(
+            &mut cmd,
+            &car_res.car_scene.as_ref().unwrap(),
+            &car_res.wheel_scene.as_ref().unwrap(),
+            false,
+            transform,
+        );
+
+        cmd.entity(player_entity).insert(Player {
+            id: client_id.raw(),
+        });
+        lobby.players.insert(client_id.raw(), player_entity);
+    }
 }
 
 pub fn update_visulizer_system(
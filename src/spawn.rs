@@ -1,13 +1,22 @@
+use crate::config::Config;
 use bevy::prelude::*;
-use bevy_garage_car::CarRes;
-use bevy_garage_track::{spawn_car_on_track, SpawnCarOnTrackEvent, TrackConfig};
+use bevy_garage_car::{
+    sensor::{CarSensors, SensorConfig},
+    Car, CarRes, CarSpec, LocalPlayer,
+};
+use bevy_garage_track::{spawn_car_on_track, SpawnCarOnTrackEvent, SpawnRng, TrackConfig};
 
-pub fn spawn_car_start_system(mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>) {
-    car_spawn_events.send(SpawnCarOnTrackEvent {
-        player: true,
-        index: 0,
-        position: Some(0.),
-    });
+pub fn spawn_car_start_system(
+    mut car_spawn_events: EventWriter<SpawnCarOnTrackEvent>,
+    config: Res<Config>,
+) {
+    for index in 0..config.local_players.len() {
+        car_spawn_events.send(SpawnCarOnTrackEvent {
+            player: true,
+            index,
+            position: Some(index as f32 * config.local_players_gap),
+        });
+    }
 }
 
 pub fn spawn_car_system(
@@ -15,19 +24,26 @@ pub fn spawn_car_system(
     mut cmd: Commands,
     track_config: ResMut<TrackConfig>,
     car_res: ResMut<CarRes>,
+    cars: Query<&Transform, With<Car>>,
+    mut spawn_rng: Option<ResMut<SpawnRng>>,
+    sensor_config: Res<SensorConfig>,
 ) {
+    let mut occupied: Vec<Vec3> = cars.iter().map(|tf| tf.translation).collect();
     for spawn_event in events.read() {
         dbg!(spawn_event);
 
-        let (transform, init_meters) = if let Some(init_meters) = spawn_event.position {
-            let (translate, quat) = track_config.get_transform_by_meter(init_meters);
-            let transform = Transform::from_translation(translate).with_rotation(quat);
-            (transform, init_meters)
-        } else {
-            track_config.get_transform_random()
+        let requested_meters = match spawn_event.position {
+            Some(init_meters) => init_meters,
+            None => match spawn_rng.as_deref_mut() {
+                Some(spawn_rng) => spawn_rng.meters(&track_config),
+                None => track_config.get_transform_random().1,
+            },
         };
+        let (transform, init_meters) =
+            track_config.get_free_transform_by_meter(requested_meters, &occupied);
+        occupied.push(transform.translation);
 
-        spawn_car_on_track(
+        let car_id = spawn_car_on_track(
             &mut cmd,
             &car_res.car_scene.as_ref().unwrap(),
             &car_res.wheel_scene.as_ref().unwrap(),
@@ -36,5 +52,14 @@ pub fn spawn_car_system(
             spawn_event.index,
             init_meters,
         );
+        cmd.entity(car_id).insert(CarSensors::with_config(
+            &CarSpec::default().size,
+            &sensor_config,
+        ));
+        if spawn_event.player {
+            cmd.entity(car_id).insert(LocalPlayer {
+                index: spawn_event.index,
+            });
+        }
     }
 }
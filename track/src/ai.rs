@@ -0,0 +1,111 @@
+use crate::{CarTrack, TrackConfig};
+use bevy::prelude::*;
+use bevy_garage_car::{Car, CarSpec};
+use bevy_rapier3d::prelude::Velocity;
+
+/// Meters between centerline samples used by `ai_driver_system`.
+pub const AI_SAMPLE_STEP: f32 = 2.;
+const GRAVITY: f32 = 9.8;
+
+/// Scripted driver, pure pursuit steering plus a speed controller which brakes
+/// for the corners it sees ahead.
+#[derive(Component, Debug, Clone)]
+pub struct AiDriver {
+    /// Lateral acceleration limit in g, lower values take corners slower.
+    pub grip: f32,
+    /// Fraction of `grip` used for braking, lower values brake earlier and softer.
+    pub brake_aggression: f32,
+    /// Pursuit point distance in meters at standstill.
+    pub min_lookahead: f32,
+    /// Added pursuit distance per m/s of speed.
+    pub lookahead_per_speed: f32,
+    /// How far ahead corners are considered.
+    pub horizon: f32,
+}
+
+impl Default for AiDriver {
+    fn default() -> Self {
+        Self {
+            grip: 1.2,
+            brake_aggression: 0.8,
+            min_lookahead: 6.,
+            lookahead_per_speed: 0.4,
+            horizon: 150.,
+        }
+    }
+}
+
+impl AiDriver {
+    /// Highest speed which still allows slowing down to every corner within the horizon.
+    pub fn target_speed(&self, samples: &[Vec3], curvatures: &[f32], track_position: f32) -> f32 {
+        let count = samples.len();
+        let start = (track_position / AI_SAMPLE_STEP) as usize;
+        let deceleration = self.brake_aggression * self.grip * GRAVITY;
+        let steps = (self.horizon / AI_SAMPLE_STEP) as usize;
+        (0..steps.min(count))
+            .map(|i| {
+                let corner_speed = (self.grip * GRAVITY / curvatures[(start + i) % count]).sqrt();
+                let distance = i as f32 * AI_SAMPLE_STEP;
+                (corner_speed.powi(2) + 2. * deceleration * distance).sqrt()
+            })
+            .fold(f32::MAX, f32::min)
+    }
+}
+
+/// Curvature in 1/m at each sample of a closed centerline.
+fn curvatures(samples: &[Vec3]) -> Vec<f32> {
+    let count = samples.len();
+    (0..count)
+        .map(|i| {
+            let prev = samples[(i + count - 1) % count];
+            let next = samples[(i + 1) % count];
+            let a = (samples[i] - prev).normalize_or_zero();
+            let b = (next - samples[i]).normalize_or_zero();
+            a.angle_between(b).max(1e-4) / AI_SAMPLE_STEP
+        })
+        .collect()
+}
+
+pub fn ai_driver_system(
+    track_config: Res<TrackConfig>,
+    mut centerline: Local<(Vec<Vec3>, Vec<f32>)>,
+    mut cars: Query<(
+        &mut Car,
+        &AiDriver,
+        &CarTrack,
+        &CarSpec,
+        &Transform,
+        &Velocity,
+    )>,
+) {
+    if centerline.0.is_empty() || track_config.is_changed() {
+        let samples = track_config.sample_centerline(AI_SAMPLE_STEP);
+        let curvatures = curvatures(&samples);
+        *centerline = (samples, curvatures);
+    }
+    let (samples, curvatures) = &*centerline;
+    if samples.len() < 3 {
+        return;
+    }
+    for (mut car, driver, car_track, spec, transform, velocity) in cars.iter_mut() {
+        let speed = velocity.linvel.length();
+
+        let lookahead = driver.min_lookahead + speed * driver.lookahead_per_speed;
+        let i = ((car_track.track_position + lookahead) / AI_SAMPLE_STEP) as usize;
+        let target = transform
+            .rotation
+            .inverse()
+            .mul_vec3(samples[i % samples.len()] - transform.translation);
+        // forward is +Z and left is +X, left steering is negative
+        let angle = target.x.atan2(target.z);
+        let steering = -angle / spec.wheel_max_angle;
+
+        let target_speed = driver.target_speed(samples, curvatures, car_track.track_position);
+        let error = target_speed - speed;
+        if error >= 0. {
+            car.set_controls((error / 5.).max(0.2), 0., steering);
+        } else {
+            car.set_controls(0., -error / 5., steering);
+        }
+    }
+}
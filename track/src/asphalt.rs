@@ -4,7 +4,7 @@ use bevy::{
     prelude::*,
     render::{mesh::*, primitives::Aabb, render_asset::RenderAssetUsages},
 };
-use bevy_garage_car::STATIC_GROUP;
+use bevy_garage_car::{ROAD_GROUP, STATIC_GROUP};
 use bevy_rapier3d::{na::Point3, prelude::*, rapier::prelude::ColliderShape};
 
 #[derive(Component, Debug)]
@@ -150,7 +150,7 @@ pub fn spawn_road(
             track.collider_indices.clone(),
         )),
         ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
+        CollisionGroups::new(STATIC_GROUP | ROAD_GROUP, Group::ALL),
         Friction {
             combine_rule: CoefficientCombineRule::Average,
             coefficient: 5.,
@@ -1,5 +1,8 @@
+use crate::TrackConfig;
 use bevy::prelude::*;
-use bevy_garage_car::spawn_car;
+use bevy_garage_car::{spawn_car, CarWheels, Settling, Wheel};
+use bevy_rapier3d::prelude::Velocity;
+use std::time::Duration;
 
 #[derive(Debug, Event)]
 pub struct SpawnCarOnTrackEvent {
@@ -8,16 +11,38 @@ pub struct SpawnCarOnTrackEvent {
     pub position: Option<f32>,
 }
 
+/// Puts a flipped or stranded car back on the track upright and at rest,
+/// at the centerline point it is closest to.
+#[derive(Debug, Event)]
+pub struct ResetCarOnTrackEvent {
+    pub entity: Entity,
+}
+
+/// A car drove one full track length forward since its lap timer started.
+#[derive(Debug, Event)]
+pub struct LapEvent {
+    pub entity: Entity,
+    pub lap: i32,
+    /// Seconds.
+    pub time: f32,
+}
+
 #[derive(Component, Debug)]
 pub struct CarTrack {
     pub index: usize,
     pub start_shift: f32,
     pub track_position: f32,
     pub ride_distance: f32,
+    /// `track_position` normalized by the track length, `0..1` along the lap.
+    pub fraction: f32,
+    /// Distance driven since spawn across all laps, `ride_distance + lap * track_length`.
+    pub total_meters: f32,
     pub lap: i32,
     pub line_dir: Vec3,
     pub line_pos: Vec3,
     pub place: usize,
+    /// Last centerline segment, keeps the projection search local.
+    pub segment_i: Option<usize>,
 }
 impl Default for CarTrack {
     fn default() -> Self {
@@ -26,14 +51,38 @@ impl Default for CarTrack {
             start_shift: 0.,
             track_position: 0.,
             ride_distance: 0.,
+            fraction: 0.,
+            total_meters: 0.,
             place: 0,
             lap: 0,
             line_dir: Vec3::ZERO,
             line_pos: Vec3::ZERO,
+            segment_i: None,
         }
     }
 }
 
+/// Lap count and lap times of a car, kept by `progress_system`. The only place
+/// lap timing lives, UI, ghosts and the server all read it from here.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct LapState {
+    /// Completed laps, driving backward over the line never makes it negative.
+    pub lap: u32,
+    /// Time since the current lap started.
+    pub lap_time: Duration,
+    pub last_lap: Option<Duration>,
+    pub best_lap: Option<Duration>,
+    /// `CarTrack::total_meters` at the current lap start.
+    pub start_meters: f32,
+}
+
+/// Standing among all cars on the track, `place` starts at 1 for the leader.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RacePosition {
+    pub place: u32,
+    pub of: u32,
+}
+
 pub fn spawn_car_on_track(
     cmd: &mut Commands,
     car_gl: &Handle<Scene>,
@@ -51,3 +100,38 @@ pub fn spawn_car_on_track(
     });
     car_id
 }
+
+/// Wheels keep their pose relative to the body, `Settling` then drops the car
+/// onto the road and holds its controls for a moment.
+pub fn reset_car_on_track_system(
+    mut cmd: Commands,
+    mut events: EventReader<ResetCarOnTrackEvent>,
+    track_config: Res<TrackConfig>,
+    mut cars: Query<(&mut Transform, &mut Velocity, &CarTrack, &CarWheels), Without<Wheel>>,
+    mut wheels: Query<(&mut Transform, &mut Velocity), With<Wheel>>,
+) {
+    if track_config.polyline.is_none() {
+        return;
+    }
+    for event in events.read() {
+        let Ok((mut transform, mut velocity, car_track, car_wheels)) = cars.get_mut(event.entity)
+        else {
+            continue;
+        };
+        let (translation, rotation) = track_config.get_transform_by_meter(car_track.track_position);
+        let turn = rotation * transform.rotation.inverse();
+        for wheel in car_wheels.entities.iter() {
+            if let Ok((mut wheel_transform, mut wheel_velocity)) = wheels.get_mut(*wheel) {
+                wheel_transform.translation =
+                    translation + turn * (wheel_transform.translation - transform.translation);
+                wheel_transform.rotation = turn * wheel_transform.rotation;
+                *wheel_velocity = Velocity::zero();
+            }
+        }
+        transform.translation = translation;
+        transform.rotation = rotation;
+        // angular velocity too, the car would keep tumbling otherwise
+        *velocity = Velocity::zero();
+        cmd.entity(event.entity).insert(Settling::default());
+    }
+}
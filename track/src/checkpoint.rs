@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+/// Gates per lap, the start line is the last one.
+pub const CHECKPOINT_COUNT: usize = 8;
+
+/// Evenly spaced gates along the track line, in meters driven from a car's start.
+/// A lap only counts after passing all of them in order, so cutting across the
+/// infield jumps over gates and the lap is not credited.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Checkpoints {
+    /// Meters of every gate before the start line, ascending.
+    pub gates: Vec<f32>,
+}
+
+impl Checkpoints {
+    pub fn new(track_length: f32, count: usize) -> Self {
+        let spacing = track_length / count.max(1) as f32;
+        Self {
+            gates: (1..count).map(|i| i as f32 * spacing).collect(),
+        }
+    }
+}
+
+/// Gates a car passed in order on its current lap.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointState {
+    pub passed: usize,
+    /// Start line crossings which missed a gate, they don't add to `CarTrack::lap`.
+    pub cut_laps: u32,
+    /// `passed` and whether the crossing was cut, restored when backing over the line.
+    line_crossing: Option<(usize, bool)>,
+}
+
+impl CheckpointState {
+    /// Follows a move from `from` to `to` meters within one lap. Passing the next
+    /// gate counts, backing over a passed one takes it back, crossing more than one
+    /// gate at once or one out of order is ignored.
+    pub fn advance(&mut self, checkpoints: &Checkpoints, from: f32, to: f32) {
+        let crossed: Vec<usize> = checkpoints
+            .gates
+            .iter()
+            .enumerate()
+            .filter(|(_, gate)| (from < **gate) != (to < **gate))
+            .map(|(i, _)| i)
+            .collect();
+        if to < from {
+            if let Some(&first) = crossed.first() {
+                self.passed = self.passed.min(first);
+            }
+            return;
+        }
+        if let [gate] = crossed[..] {
+            if gate == self.passed {
+                self.passed += 1;
+            }
+        }
+    }
+
+    pub fn lap_complete(&self, checkpoints: &Checkpoints) -> bool {
+        self.passed >= checkpoints.gates.len()
+    }
+
+    /// Driving forward over the start line adds a lap only after every gate.
+    pub fn cross_line(&mut self, checkpoints: &Checkpoints, lap: &mut i32) {
+        let cut = !self.lap_complete(checkpoints);
+        match cut {
+            true => self.cut_laps += 1,
+            false => *lap += 1,
+        }
+        self.line_crossing = Some((self.passed, cut));
+        self.passed = 0;
+    }
+
+    /// Backing over the start line takes the last crossing back, a counted lap or a cut one.
+    pub fn uncross_line(&mut self, checkpoints: &Checkpoints, lap: &mut i32) {
+        match self.line_crossing.take() {
+            Some((passed, true)) => {
+                self.cut_laps = self.cut_laps.saturating_sub(1);
+                self.passed = passed;
+            }
+            Some((passed, false)) => {
+                *lap -= 1;
+                self.passed = passed;
+            }
+            None => {
+                // back into a lap driven before the timing started, driving on counts again
+                *lap -= 1;
+                self.passed = checkpoints.gates.len();
+            }
+        }
+    }
+}
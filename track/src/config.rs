@@ -1,30 +1,109 @@
+use crate::TrackSource;
 use bevy::prelude::*;
+use bevy_garage_car::{CAR_HALF_HEIGHT, RIDE_HEIGHT};
 use bevy_rapier3d::parry::shape::Polyline;
-use rand::Rng;
-// use std::f32::consts::PI;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+
+/// Seeded source of random spawn points, inserted for reproducible runs,
+/// `get_transform_random` draws from the thread rng otherwise.
+#[derive(Resource, Debug, Clone)]
+pub struct SpawnRng(StdRng);
+
+impl SpawnRng {
+    pub fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+    /// Meters from the start line, anywhere along the track.
+    pub fn meters(&mut self, track_config: &TrackConfig) -> f32 {
+        self.0.gen_range(0.0..track_config.track_length)
+    }
+}
+
+/// Height of a spawned car center above the road: the default body half height and
+/// ride height put the wheels on the road, the same gap again lets the car drop onto it.
+pub const SPAWN_HEIGHT: f32 = CAR_HALF_HEIGHT + 2. * RIDE_HEIGHT;
+
+/// Distance in meters between spawn grid slots along the track.
+pub const SPAWN_GRID_GAP: f32 = 10.;
+/// Minimal distance in meters from a spawn point to any other car.
+pub const SPAWN_CLEARANCE: f32 = 6.;
+
+/// Segments searched on each side of the previous one in `TrackConfig::project`.
+pub const PROJECTION_WINDOW: usize = 8;
+/// Local projections farther than this from the centerline fall back to a full search.
+pub const PROJECTION_MAX_DISTANCE: f32 = 30.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackProjection {
+    /// Meters from the start line, `0..track_length`.
+    pub meters: f32,
+    pub segment_i: usize,
+    /// Closest centerline point.
+    pub point: Vec3,
+    pub dir: Vec3,
+}
 
 #[derive(Resource)]
 pub struct TrackConfig {
+    pub source: TrackSource,
     pub polyline: Option<Polyline>,
     pub segments: Vec<f32>,
     pub start_segment_i: usize,
     pub start_segment_shift: f32,
     pub start_shift: f32,
     pub track_length: f32,
+    /// Race the loop backward, meters then run against the polyline from the
+    /// same start line and headings are flipped.
+    pub reverse: bool,
 }
 impl Default for TrackConfig {
     fn default() -> Self {
         Self {
+            source: TrackSource::builtin(),
             polyline: None,
             segments: vec![],
             start_segment_i: 0,
             start_segment_shift: 0.,
             start_shift: 0.,
             track_length: 0.,
+            reverse: false,
         }
     }
 }
 impl TrackConfig {
+    /// Track from `TRACK_FILE`, reversed by the file or by `APP_TRACK_REVERSE`.
+    pub fn from_env() -> Self {
+        let source = TrackSource::from_env();
+        Self {
+            reverse: source.reverse || Self::reverse_from_env(),
+            source,
+            ..default()
+        }
+    }
+    /// The centerline in the `TrackSource::from_csv` format, with the start line and
+    /// race direction of this config, as a starting point for a hand edited track.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!(
+            "# {}, {:.1} meters\nwidth,{}\nstart,{}\nreverse,{}\n",
+            self.source.name,
+            self.track_length,
+            self.source.half_width * 2.,
+            self.start_shift,
+            self.reverse as u8
+        );
+        for point in self.source.points.iter() {
+            csv.push_str(format!("{},{},{}\n", point.x, point.y, point.z).as_str());
+        }
+        csv
+    }
+    /// `APP_TRACK_REVERSE=1` runs the track backward.
+    pub fn reverse_from_env() -> bool {
+        matches!(
+            std::env::var("APP_TRACK_REVERSE").as_deref(),
+            Ok("1" | "true")
+        )
+    }
     // pub fn get_transform_by_index(&self, i: usize) -> (Transform, f32) {
     //     let meters = i as f32 * self.track_length / self.cars_count as f32;
     //     let (translate, quat) = self.get_transform_by_meter(meters);
@@ -38,13 +117,124 @@ impl TrackConfig {
         let transform = Transform::from_translation(translate).with_rotation(quat);
         return (transform, meters);
     }
+    pub fn length(&self) -> f32 {
+        self.track_length
+    }
+    /// Centerline points every `step` meters starting at the start line,
+    /// empty until the polyline is built.
+    pub fn sample_centerline(&self, step: f32) -> Vec<Vec3> {
+        if self.polyline.is_none() || !step.is_finite() || step <= 0. || self.track_length <= 0. {
+            return vec![];
+        }
+        let count = (self.track_length / step).ceil() as usize;
+        (0..count)
+            .map(|i| self.get_transform_by_meter(i as f32 * step).0)
+            .collect()
+    }
+    /// Track meters from the start line of the centerline point closest to `point`.
+    pub fn nearest_progress(&self, point: Vec3) -> f32 {
+        self.project(point, None).map_or(0., |p| p.meters)
+    }
+    /// Projects `point` on the closest centerline segment. With a `hint` only
+    /// `PROJECTION_WINDOW` segments around it are searched, wrapping around the loop,
+    /// and the whole track is searched when nothing in the window is close enough.
+    pub fn project(&self, point: Vec3, hint: Option<usize>) -> Option<TrackProjection> {
+        let polyline = self.polyline.as_ref()?;
+        let count = polyline.num_segments();
+        if count == 0 {
+            return None;
+        }
+        let closest = |indices: &mut dyn Iterator<Item = usize>| {
+            indices
+                .map(|i| {
+                    let segment = polyline.segment(i as u32);
+                    let a: Vec3 = segment.a.into();
+                    let b: Vec3 = segment.b.into();
+                    let ab = b - a;
+                    let t = match ab.length_squared() {
+                        l if l > 0. => ((point - a).dot(ab) / l).clamp(0., 1.),
+                        _ => 0.,
+                    };
+                    let projected = a + ab * t;
+                    (i, t, projected, projected.distance_squared(point))
+                })
+                .min_by(|x, y| x.3.total_cmp(&y.3))
+        };
+        let local = hint
+            .filter(|_| count > 2 * PROJECTION_WINDOW)
+            .and_then(|hint| {
+                let window = PROJECTION_WINDOW as i64;
+                closest(
+                    &mut (-window..=window)
+                        .map(|shift| (hint as i64 + shift).rem_euclid(count as i64) as usize),
+                )
+                .filter(|c| c.3 <= PROJECTION_MAX_DISTANCE * PROJECTION_MAX_DISTANCE)
+            });
+        let (segment_i, t, projected, _) = local.or_else(|| closest(&mut (0..count)))?;
+        let segment = polyline.segment(segment_i as u32);
+        let meters = self.segments[segment_i] + t * segment.length() - self.start_shift;
+        let dir: Vec3 = segment.direction().map_or(Vec3::Z, |d| d.into());
+        Some(TrackProjection {
+            meters: self.validate_meters(if self.reverse { -meters } else { meters }),
+            segment_i,
+            point: projected,
+            dir: if self.reverse { -dir } else { dir },
+        })
+    }
+    /// Wraps meters into `0..track_length`, non finite values fall back to the start line.
+    pub fn validate_meters(&self, meters: f32) -> f32 {
+        if !meters.is_finite() || self.track_length <= 0. {
+            return 0.;
+        }
+        meters.rem_euclid(self.track_length)
+    }
+    /// Snaps meters to the spawn grid and searches the nearest slot which is
+    /// at least `SPAWN_CLEARANCE` away from every occupied position.
+    /// Falls back to the requested slot when the whole grid is taken.
+    pub fn get_free_transform_by_meter(&self, meters: f32, occupied: &[Vec3]) -> (Transform, f32) {
+        let meters = self.validate_meters(meters);
+        let slots = ((self.track_length / SPAWN_GRID_GAP).floor() as i64).max(1);
+        let slot = (meters / SPAWN_GRID_GAP).round() as i64;
+        let slot_meters =
+            |i: i64| self.validate_meters((slot + i).rem_euclid(slots) as f32 * SPAWN_GRID_GAP);
+        let is_free = |translate: Vec3| {
+            occupied
+                .iter()
+                .all(|p| p.distance(translate) >= SPAWN_CLEARANCE)
+        };
+        for i in 0..slots {
+            for shift in [i, -i] {
+                let slot_meters = slot_meters(shift);
+                let (translate, quat) = self.get_transform_by_meter(slot_meters);
+                if is_free(translate) {
+                    let transform = Transform::from_translation(translate).with_rotation(quat);
+                    return (transform, slot_meters);
+                }
+            }
+        }
+        let (translate, quat) = self.get_transform_by_meter(meters);
+        (
+            Transform::from_translation(translate).with_rotation(quat),
+            meters,
+        )
+    }
+    /// Pose at `meters` from the start line in the configured race direction.
     pub fn get_transform_by_meter(&self, meters: f32) -> (Vec3, Quat) {
+        match self.reverse {
+            true => self.get_transform_by_meter_reversed(meters),
+            false => self.get_transform_by_meter_forward(meters),
+        }
+    }
+    /// Pose `meters` behind the start line along the polyline, turned around
+    /// to face the reverse race direction.
+    pub fn get_transform_by_meter_reversed(&self, meters: f32) -> (Vec3, Quat) {
+        let (pos, quat) = self.get_transform_by_meter_forward(self.validate_meters(-meters));
+        (pos, quat * Quat::from_rotation_y(PI))
+    }
+    pub fn get_transform_by_meter_forward(&self, meters: f32) -> (Vec3, Quat) {
         let polyline = self.polyline.as_ref().unwrap();
         let mut seg_meters = 0.;
-        let mut shift = meters + self.start_shift;
-        if shift > self.track_length {
-            shift = shift - self.track_length * (shift / self.track_length).floor();
-        }
+        let shift = self.validate_meters(meters + self.start_shift);
 
         for segment in polyline.segments() {
             let new_seg_meters: f32 = seg_meters + segment.length();
@@ -54,11 +244,195 @@ impl TrackConfig {
                 let a: Vec3 = segment.a.into();
                 let dir: Vec3 = segment.direction().unwrap().into();
                 let mut pos: Vec3 = a + dir * (shift - seg_meters);
-                pos.y = 0.47;
+                pos.y += SPAWN_HEIGHT;
 
                 return (pos, Quat::from_rotation_arc(Vec3::Z, dir));
             }
         }
-        panic!();
+        // float rounding past the last segment, wrap to the polyline start
+        let segment = polyline.segments().next().unwrap();
+        let dir: Vec3 = segment.direction().unwrap().into();
+        let mut pos: Vec3 = segment.a.into();
+        pos.y += SPAWN_HEIGHT;
+        (pos, Quat::from_rotation_arc(Vec3::Z, dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track_polyline_start_system;
+    use bevy::ecs::system::RunSystemOnce;
+
+    /// 100 meter square loop from the origin, driven towards +Z first, with a
+    /// centerline point every `step` meters.
+    fn square_points(step: f32) -> Vec<Vec3> {
+        let corners = [
+            Vec3::ZERO,
+            Vec3::new(0., 0., 100.),
+            Vec3::new(100., 0., 100.),
+            Vec3::new(100., 0., 0.),
+            Vec3::ZERO,
+        ];
+        let per_side = (100. / step).round() as usize;
+        let mut points: Vec<Vec3> = corners
+            .windows(2)
+            .flat_map(|side| {
+                (0..per_side).map(move |i| side[0].lerp(side[1], i as f32 / per_side as f32))
+            })
+            .collect();
+        points.push(Vec3::ZERO);
+        points
+    }
+
+    /// Track with the start line at the centerline point closest to `start`.
+    fn track(points: Vec<Vec3>, start: Vec3, reverse: bool) -> TrackConfig {
+        let mut world = World::new();
+        world.insert_resource(TrackConfig {
+            source: TrackSource {
+                name: "square".to_string(),
+                points,
+                half_width: 4.,
+                start,
+                reverse,
+            },
+            reverse,
+            ..default()
+        });
+        world.run_system_once(track_polyline_start_system);
+        world.remove_resource::<TrackConfig>().unwrap()
+    }
+
+    /// The square with the start line 10 meters in, off the corner shared by two segments.
+    fn square_track(reverse: bool) -> TrackConfig {
+        track(square_points(100.), Vec3::new(-1., 0., 10.), reverse)
+    }
+
+    #[test]
+    fn length_of_a_known_track() {
+        assert!((square_track(false).length() - 400.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn nearest_progress_on_a_known_track() {
+        let track = square_track(false);
+        for (point, meters) in [
+            (Vec3::new(0., 0., 50.), 40.),
+            (Vec3::new(-3., 0., 75.), 65.),
+            (Vec3::new(50., 0., 103.), 140.),
+            (Vec3::new(97., 0., 20.), 270.),
+            (Vec3::new(60., 0., -2.), 330.),
+            (Vec3::new(0., 0., 5.), 395.),
+        ] {
+            let progress = track.nearest_progress(point);
+            assert!(
+                (progress - meters).abs() < 1e-3,
+                "{point} at {progress} instead of {meters}"
+            );
+        }
+    }
+
+    #[test]
+    fn nearest_progress_runs_backward_on_a_reversed_track() {
+        let track = square_track(true);
+        let progress = track.nearest_progress(Vec3::new(0., 0., 50.));
+        assert!((progress - 360.).abs() < 1e-3, "{progress}");
+        let projection = track.project(Vec3::new(0., 0., 50.), None).unwrap();
+        assert!(projection.dir.abs_diff_eq(Vec3::NEG_Z, 1e-5));
+    }
+
+    #[test]
+    fn centerline_samples_start_at_the_start_line() {
+        let track = square_track(false);
+        let samples = track.sample_centerline(100.);
+        assert_eq!(samples.len(), 4);
+        for (sample, corner) in samples.iter().zip([
+            Vec3::new(0., 0., 10.),
+            Vec3::new(10., 0., 100.),
+            Vec3::new(100., 0., 90.),
+            Vec3::new(90., 0., 0.),
+        ]) {
+            assert!(
+                sample.xz().distance(corner.xz()) < 1e-3,
+                "{sample} != {corner}"
+            );
+        }
+    }
+
+    #[test]
+    fn queries_are_empty_before_the_polyline_is_built() {
+        let track = TrackConfig::default();
+        assert!(track.sample_centerline(10.).is_empty());
+        assert_eq!(track.nearest_progress(Vec3::ZERO), 0.);
+    }
+
+    #[test]
+    fn offset_car_progress_advances_across_the_loop_closure() {
+        // enough segments for the local search around the previous one to kick in
+        let track = track(square_points(10.), Vec3::new(-1., 0., 15.), false);
+        assert!(track.polyline.as_ref().unwrap().num_segments() > 2 * PROJECTION_WINDOW);
+        let length = track.length();
+        let mut hint = None;
+        let mut last = None;
+        let mut driven = 0.;
+        // a lap and a half, 3 meters right of the centerline
+        for i in 0..300 {
+            let (pos, rot) = track.get_transform_by_meter(i as f32 * 2.);
+            let projection = track.project(pos + rot * Vec3::NEG_X * 3., hint).unwrap();
+            hint = Some(projection.segment_i);
+            if let Some(last) = last {
+                let mut delta: f32 = projection.meters - last;
+                if delta < -length / 2. {
+                    // crossed the start line
+                    delta += length;
+                }
+                assert!(
+                    (0. ..5.).contains(&delta),
+                    "progress jumped by {delta} at {}",
+                    projection.meters
+                );
+                driven += delta;
+            }
+            last = Some(projection.meters);
+        }
+        assert!((driven - 598.).abs() < 1., "{driven}");
+    }
+
+    #[test]
+    fn reversed_headings_oppose_forward_ones() {
+        let forward = square_track(false);
+        let reversed = square_track(true);
+        let length = forward.length();
+        // none of them at a corner, where the heading switches segments
+        for meters in [0., 37., 150., 260., 333.] {
+            let (rev_pos, rev_rot) = reversed.get_transform_by_meter(meters);
+            let (fwd_pos, fwd_rot) = forward.get_transform_by_meter(length - meters);
+            assert!(
+                rev_pos.distance(fwd_pos) < 1e-3,
+                "{meters}: {rev_pos} != {fwd_pos}"
+            );
+            let (rev_heading, fwd_heading) = (rev_rot * Vec3::Z, fwd_rot * Vec3::Z);
+            assert!(
+                rev_heading.dot(fwd_heading) < -0.999,
+                "{meters}: {rev_heading} not against {fwd_heading}"
+            );
+        }
+    }
+
+    #[test]
+    fn headings_point_along_the_race_direction() {
+        for track in [square_track(false), square_track(true)] {
+            for meters in [0., 37., 150., 260., 333.] {
+                let (pos, rot) = track.get_transform_by_meter(meters);
+                let (ahead, _) = track.get_transform_by_meter(meters + 1.);
+                let heading = rot * Vec3::Z;
+                let driven = (ahead - pos).normalize();
+                assert!(
+                    heading.dot(driven) > 0.999,
+                    "reverse {} at {meters}: heading {heading}, driving {driven}",
+                    track.reverse
+                );
+            }
+        }
     }
 }
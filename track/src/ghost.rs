@@ -0,0 +1,316 @@
+use crate::{progress_system, LapEvent, LapState, TrackConfig};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_garage_car::{CarSet, CarSpec, LocalPlayer, Player};
+
+/// A recording starts with the lap, samples from later in a lap wait for the next one.
+const GHOST_START_WINDOW: f32 = 0.5;
+
+/// Pose of the recorded car `time` seconds into its lap.
+#[derive(Debug, Clone, Copy)]
+pub struct GhostSample {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+/// One timed lap of the first local player's car, samples in time order.
+#[derive(Debug, Clone, Default)]
+pub struct GhostRecording {
+    pub samples: Vec<GhostSample>,
+    /// Seconds, as timed by `LapEvent`.
+    pub lap_time: f32,
+}
+
+impl GhostRecording {
+    /// Pose `time` seconds into the lap between the two nearest samples, `None` past the end.
+    pub fn pose(&self, time: f32) -> Option<Transform> {
+        let i = self.samples.partition_point(|sample| sample.time < time);
+        let b = self.samples.get(i)?;
+        let Some(a) = i.checked_sub(1).map(|i| self.samples[i]) else {
+            return Some(Transform::from_translation(b.translation).with_rotation(b.rotation));
+        };
+        let t = ((time - a.time) / (b.time - a.time).max(f32::EPSILON)).clamp(0., 1.);
+        Some(
+            Transform::from_translation(a.translation.lerp(b.translation, t))
+                .with_rotation(a.rotation.slerp(b.rotation, t)),
+        )
+    }
+    /// `lap,<seconds>` then a `time,x,y,z,qx,qy,qz,qw` row per sample.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("lap,{}\n", self.lap_time);
+        for sample in self.samples.iter() {
+            let (t, r) = (sample.translation, sample.rotation);
+            let (x, y, z) = (t.x, t.y, t.z);
+            let (qx, qy, qz, qw) = (r.x, r.y, r.z, r.w);
+            csv.push_str(format!("{},{x},{y},{z},{qx},{qy},{qz},{qw}\n", sample.time).as_str());
+        }
+        csv
+    }
+    pub fn from_csv(csv: &str) -> Result<Self, String> {
+        let mut lines = csv.lines();
+        let lap_time = lines
+            .next()
+            .and_then(|line| line.strip_prefix("lap,"))
+            .and_then(|lap_time| lap_time.trim().parse().ok())
+            .ok_or("missing lap time")?;
+        let mut samples = vec![];
+        for (i, line) in lines.enumerate() {
+            let values: Vec<f32> = line
+                .split(',')
+                .map(|value| value.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("bad sample {i}"))?;
+            let [time, x, y, z, qx, qy, qz, qw] = values[..] else {
+                return Err(format!("bad sample {i}"));
+            };
+            samples.push(GhostSample {
+                time,
+                translation: Vec3::new(x, y, z),
+                rotation: Quat::from_xyzw(qx, qy, qz, qw).normalize(),
+            });
+        }
+        Ok(Self { samples, lap_time })
+    }
+}
+
+/// Best lap of `track` kept between runs, next to its `<track>-export.csv`.
+pub fn ghost_path(track: &str) -> String {
+    format!("{track}-ghost.csv")
+}
+
+/// Best lap per track name, replayed as a ghost car on the following laps.
+#[derive(Resource, Debug)]
+pub struct Ghosts {
+    /// `G` starts and stops recording, the ghost keeps replaying either way.
+    pub recording: bool,
+    pub best: HashMap<String, GhostRecording>,
+    /// Car and samples of the lap being recorded.
+    current: Option<(Entity, Vec<GhostSample>)>,
+}
+
+impl Default for Ghosts {
+    fn default() -> Self {
+        Self {
+            recording: true,
+            best: HashMap::default(),
+            current: None,
+        }
+    }
+}
+
+/// Translucent stand-in without a collider, following the best lap.
+#[derive(Component, Debug)]
+pub struct Ghost;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Ghosts>()
+            .add_systems(Startup, ghost_start_system)
+            .add_systems(
+                Update,
+                (
+                    ghost_load_system,
+                    ghost_key_system,
+                    ghost_record_system
+                        .in_set(CarSet::Input)
+                        .after(progress_system)
+                        .after(ghost_key_system)
+                        .after(ghost_load_system),
+                    ghost_replay_system.after(ghost_record_system),
+                ),
+            );
+    }
+}
+
+pub fn ghost_start_system(
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let size = CarSpec::default().size;
+    cmd.spawn((
+        PbrBundle {
+            mesh: meshes.add(Cuboid::new(size.hw * 2., size.hh * 2., size.hl * 2.)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::srgba(0.6, 0.8, 1., 0.3),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Ghost,
+    ));
+}
+
+/// Reads the saved best lap of a track the first time it is driven.
+pub fn ghost_load_system(track_config: Res<TrackConfig>, mut ghosts: ResMut<Ghosts>) {
+    let track = &track_config.source.name;
+    if !track_config.is_changed() || ghosts.best.contains_key(track) {
+        return;
+    }
+    let path = ghost_path(track);
+    let Ok(csv) = std::fs::read_to_string(&path) else {
+        return;
+    };
+    match GhostRecording::from_csv(&csv) {
+        Ok(ghost) => {
+            println!("ghost of a {:.3}s lap loaded from {path}", ghost.lap_time);
+            ghosts.best.insert(track.clone(), ghost);
+        }
+        Err(err) => println!("ghost {path} loading error: {err}"),
+    }
+}
+
+/// `G` starts and stops recording, `Shift+G` drops the ghost of the current track.
+pub fn ghost_key_system(
+    input: Res<ButtonInput<KeyCode>>,
+    track_config: Res<TrackConfig>,
+    mut ghosts: ResMut<Ghosts>,
+) {
+    if !input.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        let track = &track_config.source.name;
+        ghosts.best.remove(track);
+        let _ = std::fs::remove_file(ghost_path(track));
+        println!("ghost cleared");
+        return;
+    }
+    ghosts.recording = !ghosts.recording;
+    ghosts.current = None;
+    println!(
+        "ghost recording {}",
+        if ghosts.recording { "on" } else { "off" }
+    );
+}
+
+fn first_local_car<'a>(
+    cars: impl Iterator<Item = (Entity, &'a Transform, &'a LapState, Option<&'a LocalPlayer>)>,
+) -> Option<(Entity, &'a Transform, f32)> {
+    cars.filter(|(.., local)| local.map_or(0, |local| local.index) == 0)
+        .map(|(entity, transform, lap_state, _)| {
+            (entity, transform, lap_state.lap_time.as_secs_f32())
+        })
+        .next()
+}
+
+/// Samples the first local player's car every frame, a timed lap faster than
+/// the track's ghost replaces it. Run it after `progress_system`.
+pub fn ghost_record_system(
+    track_config: Res<TrackConfig>,
+    mut ghosts: ResMut<Ghosts>,
+    mut lap_events: EventReader<LapEvent>,
+    cars: Query<(Entity, &Transform, &LapState, Option<&LocalPlayer>), With<Player>>,
+) {
+    let Some((entity, transform, time)) = first_local_car(cars.iter()) else {
+        ghosts.current = None;
+        return;
+    };
+    let lap_time = lap_events
+        .read()
+        .filter(|event| event.entity == entity)
+        .map(|event| event.time)
+        .last();
+    if !ghosts.recording {
+        return;
+    }
+    let mut samples = match ghosts.current.take() {
+        // a respawned car starts over
+        Some((car, samples)) if car == entity => samples,
+        _ => vec![],
+    };
+    if let Some(lap_time) = lap_time {
+        let track = &track_config.source.name;
+        let faster = ghosts
+            .best
+            .get(track)
+            .is_none_or(|best| lap_time < best.lap_time);
+        if faster && !samples.is_empty() {
+            println!("ghost of a {lap_time:.3}s lap recorded");
+            let samples = std::mem::take(&mut samples);
+            let ghost = GhostRecording { samples, lap_time };
+            let path = ghost_path(track);
+            if let Err(err) = std::fs::write(&path, ghost.to_csv()) {
+                println!("ghost saving to {path} failed: {err}");
+            }
+            ghosts.best.insert(track.clone(), ghost);
+        }
+        samples.clear();
+    } else if samples.last().is_some_and(|last| time < last.time) {
+        // the timer restarted without a timed lap, e.g. backward over the line
+        samples.clear();
+    }
+    if !samples.is_empty() || time <= GHOST_START_WINDOW {
+        samples.push(GhostSample {
+            time,
+            translation: transform.translation,
+            rotation: transform.rotation,
+        });
+    }
+    ghosts.current = Some((entity, samples));
+}
+
+/// Puts the ghost where the best lap was as far into its lap as the player is,
+/// so it starts over whenever the player's lap timer does.
+pub fn ghost_replay_system(
+    track_config: Res<TrackConfig>,
+    ghosts: Res<Ghosts>,
+    cars: Query<(Entity, &Transform, &LapState, Option<&LocalPlayer>), With<Player>>,
+    mut ghost: Query<(&mut Transform, &mut Visibility), (With<Ghost>, Without<Player>)>,
+) {
+    let Ok((mut ghost_transform, mut visibility)) = ghost.get_single_mut() else {
+        return;
+    };
+    let pose = first_local_car(cars.iter())
+        .and_then(|(_, _, time)| ghosts.best.get(&track_config.source.name)?.pose(time));
+    match pose {
+        Some(pose) => {
+            *ghost_transform = pose;
+            visibility.set_if_neq(Visibility::Visible);
+        }
+        None => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghost_csv_round_trip() {
+        let ghost = GhostRecording {
+            samples: vec![
+                GhostSample {
+                    time: 0.,
+                    translation: Vec3::new(1., 0.5, -2.),
+                    rotation: Quat::IDENTITY,
+                },
+                GhostSample {
+                    time: 0.25,
+                    translation: Vec3::new(1.5, 0.5, 3.25),
+                    rotation: Quat::from_rotation_y(0.5),
+                },
+            ],
+            lap_time: 61.5,
+        };
+
+        let loaded = GhostRecording::from_csv(&ghost.to_csv()).unwrap();
+
+        assert_eq!(loaded.lap_time, ghost.lap_time);
+        assert_eq!(loaded.samples.len(), 2);
+        for (loaded, sample) in loaded.samples.iter().zip(&ghost.samples) {
+            assert_eq!(loaded.time, sample.time);
+            assert_eq!(loaded.translation, sample.translation);
+            assert!(loaded.rotation.angle_between(sample.rotation) < 1e-5);
+        }
+        assert!(GhostRecording::from_csv("lap,1\n0,1,2\n").is_err());
+        assert!(GhostRecording::from_csv("").is_err());
+    }
+}
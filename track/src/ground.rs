@@ -9,6 +9,100 @@ pub struct GroundCell {
     pub is_color: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroundShape {
+    /// Thick cuboid, nothing to fall through at high speed.
+    Flat,
+    /// Flat heightfield, the collider used under the track.
+    #[default]
+    Heightfield,
+}
+
+/// Ground collider setup shared by the game, the hackaton level and the examples,
+/// groups default to what car bodies and wheels collide with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroundConfig {
+    pub shape: GroundShape,
+    /// Extent along x and z.
+    pub size: Vec2,
+    /// Heightfield cells per side, unused for flat ground.
+    pub subdivisions: usize,
+    /// Peak height of the heightfield bumps, 0 keeps it flat.
+    pub bump_amplitude: f32,
+    /// Distance between neighbouring bump peaks.
+    pub bump_wavelength: f32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub collision_groups: CollisionGroups,
+    /// Center of the ground surface.
+    pub translation: Vec3,
+}
+
+impl Default for GroundConfig {
+    fn default() -> Self {
+        Self {
+            shape: GroundShape::default(),
+            size: Vec2::splat(100.),
+            subdivisions: 10,
+            bump_amplitude: 0.,
+            bump_wavelength: 8.,
+            friction: 3.,
+            restitution: 0.,
+            collision_groups: CollisionGroups::new(STATIC_GROUP, Group::ALL),
+            translation: Vec3::ZERO,
+        }
+    }
+}
+
+impl GroundConfig {
+    /// Heightfield samples per side.
+    pub fn cells(&self) -> usize {
+        self.subdivisions.max(2)
+    }
+    /// Column major `cells * cells` heights, a smooth egg crate of `bump_amplitude`.
+    pub fn heights(&self) -> Vec<f32> {
+        let cells = self.cells();
+        let step = self.size / (cells - 1) as f32;
+        let k = std::f32::consts::TAU / self.bump_wavelength.max(0.01);
+        (0..cells * cells)
+            .map(|i| {
+                let (x, z) = ((i / cells) as f32 * step.x, (i % cells) as f32 * step.y);
+                self.bump_amplitude * (k * x).sin() * (k * z).sin()
+            })
+            .collect()
+    }
+}
+
+/// Spawns the ground collider only, visuals are up to the caller.
+pub fn spawn_ground(cmd: &mut Commands, config: &GroundConfig) -> Entity {
+    let (collider, translation) = match config.shape {
+        GroundShape::Flat => (
+            Collider::cuboid(config.size.x / 2., 0.5, config.size.y / 2.),
+            config.translation - Vec3::Y * 0.5,
+        ),
+        GroundShape::Heightfield => (
+            Collider::heightfield(
+                config.heights(),
+                config.cells(),
+                config.cells(),
+                Vec3::new(config.size.x, 1., config.size.y),
+            ),
+            config.translation,
+        ),
+    };
+    cmd.spawn((
+        Name::new("ground"),
+        RigidBody::Fixed,
+        ColliderScale::Absolute(Vec3::ONE),
+        config.collision_groups,
+        Friction::coefficient(config.friction),
+        Restitution::coefficient(config.restitution),
+        collider,
+        TransformBundle::from_transform(Transform::from_translation(translation)),
+    ))
+    .id()
+}
+
 pub fn spawn_ground_heightfield(
     cmd: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
@@ -18,7 +112,6 @@ pub fn spawn_ground_heightfield(
 ) {
     let aabb_center: Vec3 = aabb.center.into();
     let size: Vec2 = 2. * (aabb.half_extents.xz() + padding * Vec2::ONE);
-    let (cols, rows) = (10, 10);
 
     let meshes_n_half = 10;
     let size_s = size / (2 * meshes_n_half) as f32;
@@ -48,20 +141,13 @@ pub fn spawn_ground_heightfield(
         }
     }
 
-    cmd.spawn((
-        Name::new("ground-heightfield"),
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(3.),
-        // Restitution::coefficient(0.05),
-        Restitution::coefficient(0.),
-        Collider::heightfield(
-            vec![0.; rows * cols],
-            rows,
-            cols,
-            Vec3::new(size.x, 0., size.y),
-        ),
-        TransformBundle::from_transform(Transform::from_translation(aabb_center)),
-    ));
+    let ground = spawn_ground(
+        cmd,
+        &GroundConfig {
+            size,
+            translation: aabb_center,
+            ..default()
+        },
+    );
+    cmd.entity(ground).insert(Name::new("ground-heightfield"));
 }
@@ -5,7 +5,7 @@ use bevy::{
     prelude::*,
     render::{mesh::*, render_asset::RenderAssetUsages},
 };
-use bevy_garage_car::STATIC_GROUP;
+use bevy_garage_car::{ROAD_GROUP, STATIC_GROUP};
 use bevy_rapier3d::{na::Point3, prelude::*, rapier::prelude::ColliderShape};
 use std::ops::Sub;
 
@@ -17,7 +17,7 @@ pub fn spawn_kerb(
 ) {
     let kerb_length: f32 = 10.;
     let kerb_height: f32 = 0.002;
-    let from_center: f32 = 5.;
+    let from_center: f32 = track.half_width();
     let top_norm = Vec3::Y;
 
     let normals_side = &track.left_norm;
@@ -74,7 +74,7 @@ pub fn spawn_kerb(
         },
         NotShadowCaster,
         ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
+        CollisionGroups::new(STATIC_GROUP | ROAD_GROUP, Group::ALL),
         Restitution::coefficient(0.),
     ));
 
@@ -131,7 +131,7 @@ pub fn spawn_kerb(
         },
         NotShadowCaster,
         ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
+        CollisionGroups::new(STATIC_GROUP | ROAD_GROUP, Group::ALL),
         Restitution::coefficient(0.),
     ));
 }
@@ -1,7 +1,10 @@
+pub mod ai;
 pub mod asphalt;
 pub mod car_track;
+pub mod checkpoint;
 pub mod config;
 pub mod decor;
+pub mod ghost;
 pub mod ground;
 pub mod kerb;
 pub mod material;
@@ -9,19 +12,24 @@ pub mod mesh;
 pub mod progress;
 pub mod quality;
 pub mod shader;
+pub mod source;
 pub mod track;
 pub mod wall;
 
+pub use ai::*;
 pub use asphalt::*;
 use bevy_garage_car::CarSet;
 pub use car_track::*;
+pub use checkpoint::*;
 pub use config::*;
 pub use decor::*;
+pub use ghost::*;
 pub use ground::*;
 pub use material::*;
 pub use progress::*;
 pub use quality::*;
 pub use shader::*;
+pub use source::*;
 pub use track::*;
 
 use bevy::prelude::*;
@@ -35,7 +43,9 @@ pub struct TrackPlugin;
 
 impl Plugin for TrackPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(TrackConfig::default())
+        app.insert_resource(TrackConfig::from_env())
+            .add_event::<LapEvent>()
+            .add_event::<ResetCarOnTrackEvent>()
             .add_plugins((
                 ShadersPlugin,
                 // MaterialPlugin::<GroundMaterial>::default(),
@@ -50,7 +60,22 @@ impl Plugin for TrackPlugin {
                     track_decorations_start_system.after(track_polyline_start_system),
                 ),
             )
-            .add_systems(Update, (far_culling, progress_system.in_set(CarSet::Input)));
+            .add_systems(
+                Update,
+                (
+                    far_culling,
+                    progress_system.in_set(CarSet::Input),
+                    ai_driver_system
+                        .in_set(CarSet::Input)
+                        .after(progress_system),
+                    standings_system
+                        .in_set(CarSet::Input)
+                        .after(progress_system),
+                    reset_car_on_track_system
+                        .in_set(CarSet::Input)
+                        .after(progress_system),
+                ),
+            );
     }
 }
 
@@ -58,17 +83,21 @@ pub fn track_start_system(
     handled_materials: Res<MaterialHandle>,
     mut cmd: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    track_config: Res<TrackConfig>,
 ) {
-    let track = Track::new();
+    let source = &track_config.source;
+    let track = Track::new(&source.points, source.half_width);
     let aabb = spawn_road(&handled_materials, &mut cmd, &mut meshes, &track);
     spawn_ground_heightfield(&mut cmd, &mut meshes, &handled_materials, &aabb, 100.);
 
     spawn_kerb(&mut cmd, &mut meshes, &handled_materials, &track);
+    // walls stand 2.5 meters off the road edge
+    let wall_distance = track.half_width() + 2.5;
     let mut left_wall_points: Vec<Vec3> = vec![];
     let mut right_wall_points: Vec<Vec3> = vec![];
     for (i, p) in track.points.iter().enumerate() {
-        left_wall_points.push(*p + track.right_norm[i] * 7.5);
-        right_wall_points.push(*p + track.right_norm[i] * -7.5);
+        left_wall_points.push(*p + track.right_norm[i] * wall_distance);
+        right_wall_points.push(*p + track.right_norm[i] * -wall_distance);
     }
     spawn_walls(
         &mut cmd,
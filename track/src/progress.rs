@@ -1,5 +1,6 @@
-use crate::car_track::CarTrack;
-use crate::{TrackConfig, TRACK_POSITIONS};
+use crate::car_track::{CarTrack, LapEvent, LapState, RacePosition};
+use crate::checkpoint::{CheckpointState, Checkpoints, CHECKPOINT_COUNT};
+use crate::TrackConfig;
 use bevy::prelude::*;
 use bevy_garage_car::{CarRes, CAR_TRAINING_GROUP, STATIC_GROUP};
 use bevy_rapier3d::parry::query::PointQueryWithLocation;
@@ -7,17 +8,18 @@ use bevy_rapier3d::parry::shape::{Polyline, SegmentPointLocation};
 use bevy_rapier3d::prelude::Real;
 use bevy_rapier3d::{na::Point3, prelude::*, rapier::prelude::ColliderShape};
 use std::cmp::Ordering;
+use std::time::Duration;
 
 pub fn track_polyline_start_system(mut cmd: Commands, mut track_config: ResMut<TrackConfig>) {
-    let positions = TRACK_POSITIONS;
-
-    let vertices: Vec<Point3<Real>> = positions
+    let vertices: Vec<Point3<Real>> = track_config
+        .source
+        .points
         .iter()
-        .map(|pos| Point3::new(pos.0, pos.1, pos.2))
+        .map(|pos| Point3::new(pos.x, pos.y, pos.z))
         .collect();
 
     let polyline = Polyline::new(vertices.clone(), None);
-    let initial_point = Point3::from(Vec3::ZERO);
+    let initial_point = Point3::from(track_config.source.start);
     let point_location = polyline.project_local_point_and_get_location(&initial_point, true);
     let (segment_i, segment_location) = point_location.1;
     let segment = polyline.segment(segment_i);
@@ -42,10 +44,14 @@ pub fn track_polyline_start_system(mut cmd: Commands, mut track_config: ResMut<T
         track_config.segments[track_config.start_segment_i] + track_config.start_segment_shift;
     track_config.start_shift = start_shift;
     track_config.track_length = track_length;
+    cmd.insert_resource(Checkpoints::new(track_length, CHECKPOINT_COUNT));
 
     println!(
-        "track length: {track_length:.1}, start_shift: {:.1}, segment_shift: {:.1}, segment_i: {}",
-        start_shift, track_config.start_segment_shift, track_config.start_segment_i
+        "track {}, length: {track_length:.1}, start_shift: {:.1}, segment_shift: {:.1}, segment_i: {}",
+        track_config.source.name,
+        start_shift,
+        track_config.start_segment_shift,
+        track_config.start_segment_i
     );
 
     cmd.spawn((
@@ -58,34 +64,35 @@ pub fn track_polyline_start_system(mut cmd: Commands, mut track_config: ResMut<T
     ));
 }
 
+/// Share of the track length a lap has to cover forward to be timed,
+/// crossing the line back and forth only restarts the timer.
+pub const LAP_MIN_FRACTION: f32 = 0.9;
+
 pub fn progress_system(
+    mut cmd: Commands,
+    time: Res<Time>,
     track_config: Res<TrackConfig>,
-    mut cars: Query<(&Transform, &mut CarTrack, Entity)>,
+    checkpoints: Option<Res<Checkpoints>>,
+    mut lap_events: EventWriter<LapEvent>,
+    mut cars: Query<(
+        &Transform,
+        &mut CarTrack,
+        Entity,
+        Option<&mut CheckpointState>,
+        Option<&mut LapState>,
+    )>,
     car_res: Res<CarRes>,
     mut gizmos: Gizmos,
 ) {
-    let polyline = track_config.polyline.as_ref().unwrap();
     let mut board: Vec<(Entity, f32)> = Vec::new();
-    for (tr, mut car, e) in cars.iter_mut() {
-        let point: Point3<Real> = Point3::from(tr.translation);
-        let point_location = polyline.project_local_point_and_get_location(&point, true);
-        let (segment_i, segment_location) = point_location.1;
-        let segment = polyline.segment(segment_i);
-        let segment_progress = match segment_location {
-            SegmentPointLocation::OnEdge(uv) => uv[1] * segment.length(),
-            _ => {
-                continue;
-            }
-        };
-
-        let segments_progress: f32 =
-            track_config.segments[segment_i as usize] + segment_progress - track_config.start_shift;
-        let track_position: f32 = if segments_progress > 0. {
-            segments_progress
-        } else {
-            segments_progress + track_config.track_length
+    for (tr, mut car, e, checkpoint_state, lap_state) in cars.iter_mut() {
+        let Some(projection) = track_config.project(tr.translation, car.segment_i) else {
+            continue;
         };
+        car.segment_i = Some(projection.segment_i);
+        let track_position = projection.meters;
 
+        let lap = car.lap;
         let mut ride_distance = if track_position >= car.start_shift {
             track_position - car.start_shift
         } else {
@@ -96,22 +103,63 @@ pub fn progress_system(
             // prevent increasing distance by going backward
             ride_distance = ride_distance - track_config.track_length;
         }
-        if ride_distance.is_sign_positive() && car.ride_distance.is_sign_negative()
-            || ride_distance < half && car.ride_distance > half
-        {
-            car.lap += 1;
-        }
-        if ride_distance.is_sign_negative() && car.ride_distance.is_sign_positive()
-            || ride_distance > -half && car.ride_distance < -half
-        {
-            car.lap -= 1;
+        let mut checkpoint = checkpoint_state.as_deref().copied().unwrap_or_default();
+        let forward = ride_distance.is_sign_positive() && car.ride_distance.is_sign_negative()
+            || ride_distance < half && car.ride_distance > half;
+        let backward = ride_distance.is_sign_negative() && car.ride_distance.is_sign_positive()
+            || ride_distance > -half && car.ride_distance < -half;
+        match &checkpoints {
+            Some(checkpoints) if forward => checkpoint.cross_line(checkpoints, &mut car.lap),
+            Some(checkpoints) if backward => checkpoint.uncross_line(checkpoints, &mut car.lap),
+            Some(checkpoints) => checkpoint.advance(checkpoints, car.ride_distance, ride_distance),
+            None if forward => car.lap += 1,
+            None if backward => car.lap -= 1,
+            None => {}
         }
         car.track_position = track_position;
         car.ride_distance = ride_distance;
+        // a degenerate track has no length to divide by
+        car.fraction = match track_config.track_length > 0. {
+            true => track_position / track_config.track_length,
+            false => 0.,
+        };
+        car.total_meters = ride_distance + car.lap as f32 * track_config.track_length;
+
+        let mut state = lap_state.as_deref().cloned().unwrap_or_default();
+        state.lap = car.lap.max(0) as u32;
+        state.lap_time += time.delta();
+        if forward || backward {
+            let lap_meters = car.total_meters - state.start_meters;
+            if car.lap > lap && lap_meters >= track_config.track_length * LAP_MIN_FRACTION {
+                let lap_time = state.lap_time;
+                lap_events.send(LapEvent {
+                    entity: e,
+                    lap: car.lap,
+                    time: lap_time.as_secs_f32(),
+                });
+                state.last_lap = Some(lap_time);
+                state.best_lap = Some(state.best_lap.map_or(lap_time, |best| best.min(lap_time)));
+            }
+            state.lap_time = Duration::ZERO;
+            state.start_meters = car.total_meters;
+        }
+        match lap_state {
+            Some(mut lap_state) => *lap_state = state,
+            None => {
+                cmd.entity(e).insert(state);
+            }
+        }
+        match checkpoint_state {
+            Some(mut checkpoint_state) => {
+                checkpoint_state.set_if_neq(checkpoint);
+            }
+            None => {
+                cmd.entity(e).insert(checkpoint);
+            }
+        }
 
-        let dir = Vec3::from(segment.direction().unwrap());
-        car.line_dir = dir;
-        car.line_pos = Vec3::from(segment.a) + dir * segment_progress;
+        car.line_dir = projection.dir;
+        car.line_pos = projection.point;
         if car_res.show_rays {
             let h = Vec3::Y * 0.6;
             gizmos.line(
@@ -129,7 +177,42 @@ pub fn progress_system(
         Ordering::Less
     });
     for (i, (e, _)) in board.iter().enumerate() {
-        let (_, mut p, _) = cars.get_mut(*e).unwrap();
+        let (_, mut p, ..) = cars.get_mut(*e).unwrap();
         p.place = i;
     }
 }
+
+/// Keeps `RacePosition` of every car on the track, inserting it on cars which
+/// don't have it yet. Cars are ranked by the distance driven from the start line,
+/// laps included.
+pub fn standings_system(
+    mut cmd: Commands,
+    mut cars: Query<(Entity, &CarTrack, Option<&mut RacePosition>)>,
+) {
+    let mut board: Vec<(Entity, f32)> = cars
+        .iter()
+        .map(|(e, car, _)| {
+            let distance = car.start_shift + car.total_meters;
+            (e, if distance.is_finite() { distance } else { 0. })
+        })
+        .collect();
+    board.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let of = board.len() as u32;
+    for (i, (e, _)) in board.iter().enumerate() {
+        let Ok((e, _, race_position)) = cars.get_mut(*e) else {
+            continue;
+        };
+        let position = RacePosition {
+            place: i as u32 + 1,
+            of,
+        };
+        match race_position {
+            Some(mut race_position) => {
+                race_position.set_if_neq(position);
+            }
+            None => {
+                cmd.entity(e).insert(position);
+            }
+        }
+    }
+}
@@ -37,12 +37,19 @@ pub fn far_culling(
         >,
     )>,
 ) {
-    let cam_translation = pset.p0().single().translation;
+    // split-screen spawns a camera per local player, keep cells visible for any of them
+    let cam_translations: Vec<Vec3> = pset.p0().iter().map(|tf| tf.translation).collect();
+    let cam_distance = |translation: Vec3| -> f32 {
+        cam_translations
+            .iter()
+            .map(|cam_translation| (*cam_translation - translation).length())
+            .fold(f32::MAX, f32::min)
+    };
 
     for (transform, mut cell_visibility, inherited_visibility, entity, mut cell) in
         pset.p1().iter_mut()
     {
-        let distance = (cam_translation - transform.translation).length();
+        let distance = cam_distance(transform.translation);
         if distance > VISIBILITY_COLOR {
             if !cell.is_color {
                 cmd.entity(entity).remove::<HandleGround>();
@@ -68,7 +75,7 @@ pub fn far_culling(
     for (transform, mut cell_visibility, inherited_visibility, entity, mut cell) in
         pset.p2().iter_mut()
     {
-        let distance = (cam_translation - transform.translation).length();
+        let distance = cam_distance(transform.translation);
         if distance > VISIBILITY_COLOR {
             if !cell.is_color {
                 cmd.entity(entity).remove::<HandleAsphalt>();
@@ -0,0 +1,173 @@
+use crate::{ROAD_HALF_WIDTH, TRACK_NAME, TRACK_POSITIONS};
+use bevy::prelude::*;
+use std::path::Path;
+
+/// Fewest centerline points of a loaded track, the closing point included.
+pub const MIN_TRACK_POINTS: usize = 4;
+/// Meters the last centerline point may be away from the first one.
+pub const TRACK_CLOSE_TOLERANCE: f32 = 1.;
+
+/// Centerline the track is built from, the built-in one or a file from `TRACK_FILE`.
+#[derive(Debug, Clone)]
+pub struct TrackSource {
+    /// Key of the track, e.g. for per-track lap records, a loaded track uses its file stem.
+    pub name: String,
+    /// Closed loop, the last point repeats the first one.
+    pub points: Vec<Vec3>,
+    /// Meters from the centerline to either road edge.
+    pub half_width: f32,
+    /// The start line is at the centerline point closest to it.
+    pub start: Vec3,
+    /// Race the loop backward.
+    pub reverse: bool,
+}
+
+impl Default for TrackSource {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl TrackSource {
+    pub fn builtin() -> Self {
+        Self {
+            name: TRACK_NAME.to_string(),
+            points: TRACK_POSITIONS
+                .iter()
+                .map(|pos| Vec3::new(pos.0, pos.1, pos.2))
+                .collect(),
+            half_width: ROAD_HALF_WIDTH,
+            start: Vec3::ZERO,
+            reverse: false,
+        }
+    }
+
+    /// The track file named by `TRACK_FILE`, the built-in track when unset or invalid.
+    pub fn from_env() -> Self {
+        let Ok(path) = std::env::var("TRACK_FILE") else {
+            return Self::builtin();
+        };
+        match Self::load(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                println!("TRACK_FILE {path}: {err}, using the built-in track");
+                Self::builtin()
+            }
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let csv = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let name = path
+            .file_stem()
+            .map_or(TRACK_NAME.to_string(), |stem| stem.to_string_lossy().into());
+        Self::from_csv(name, &csv)
+    }
+
+    /// One centerline point per line, `x,z` on flat ground or `x,y,z`, `#` starts a comment.
+    /// Optional lines: `width,<meters>` sets the road width, `start,<meters>` puts the
+    /// start line that far along the points instead of at the first one and
+    /// `reverse,1` races the loop backward.
+    pub fn from_csv(name: String, csv: &str) -> Result<Self, String> {
+        let mut points: Vec<Vec3> = vec![];
+        let mut half_width = ROAD_HALF_WIDTH;
+        let mut start_meters = 0.;
+        let mut reverse = false;
+        for (i, line) in csv.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_n = i + 1;
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values[0] == "width" {
+                let width: f32 = values
+                    .get(1)
+                    .and_then(|width| width.parse().ok())
+                    .filter(|width: &f32| width.is_finite() && *width > 0.)
+                    .ok_or(format!("bad width on line {line_n}"))?;
+                half_width = width / 2.;
+                continue;
+            }
+            if values[0] == "start" {
+                start_meters = values
+                    .get(1)
+                    .and_then(|meters| meters.parse().ok())
+                    .filter(|meters: &f32| meters.is_finite() && *meters >= 0.)
+                    .ok_or(format!("bad start on line {line_n}"))?;
+                continue;
+            }
+            if values[0] == "reverse" {
+                reverse = match values.get(1).copied() {
+                    Some("1" | "true") => true,
+                    Some("0" | "false") => false,
+                    _ => return Err(format!("bad reverse on line {line_n}")),
+                };
+                continue;
+            }
+            let values = values
+                .iter()
+                .map(|value| value.parse::<f32>().ok().filter(|value| value.is_finite()))
+                .collect::<Option<Vec<f32>>>()
+                .ok_or(format!("bad number on line {line_n}"))?;
+            let point = match values[..] {
+                [x, z] => Vec3::new(x, 0., z),
+                [x, y, z] => Vec3::new(x, y, z),
+                _ => return Err(format!("expected x,z or x,y,z on line {line_n}")),
+            };
+            points.push(point);
+        }
+        let mut source = Self {
+            name,
+            start: point_along(&points, start_meters),
+            points,
+            half_width,
+            reverse,
+        };
+        source.validate()?;
+        // the road mesh joins the last point onto the first one
+        let first = source.points[0];
+        if let Some(last) = source.points.last_mut() {
+            *last = first;
+        }
+        Ok(source)
+    }
+
+    /// A closed loop of at least `MIN_TRACK_POINTS` points without zero length segments.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.len() < MIN_TRACK_POINTS {
+            return Err(format!(
+                "{} points, a track needs at least {MIN_TRACK_POINTS}",
+                self.points.len()
+            ));
+        }
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if first.distance(last) > TRACK_CLOSE_TOLERANCE {
+            return Err(format!(
+                "not closed, the last point {last} is not the first {first}"
+            ));
+        }
+        if let Some(i) = self
+            .points
+            .windows(2)
+            .position(|pair| pair[0].distance_squared(pair[1]) == 0.)
+        {
+            return Err(format!("points {} and {} are the same", i + 1, i + 2));
+        }
+        Ok(())
+    }
+}
+
+/// Point `meters` along the polyline from its first point, the last point past its end.
+fn point_along(points: &[Vec3], meters: f32) -> Vec3 {
+    let mut left = meters;
+    for pair in points.windows(2) {
+        let length = pair[0].distance(pair[1]);
+        if left <= length && length > 0. {
+            return pair[0].lerp(pair[1], left / length);
+        }
+        left -= length;
+    }
+    points.last().copied().unwrap_or_default()
+}
@@ -4,6 +4,9 @@ use std::f32::consts::FRAC_PI_2;
 // https://google.github.io/filament/Filament.html#materialsystem/parameterization/
 // https://google.github.io/filament/Material%20Properties.pdf
 
+/// Meters from the centerline to either road edge.
+pub const ROAD_HALF_WIDTH: f32 = 5.;
+
 #[derive(Component, Debug)]
 pub struct TrackRoad;
 
@@ -22,7 +25,7 @@ pub struct Track {
 impl Track {
     pub fn empty() -> Self {
         Track {
-            width: 5.,
+            width: ROAD_HALF_WIDTH,
             points: Vec::new(),
             indices: Vec::new(),
             collider_indices: Vec::new(),
@@ -32,17 +35,14 @@ impl Track {
             right_norm: Vec::new(),
         }
     }
-    pub fn new() -> Self {
-        let positions = TRACK_POSITIONS;
+    /// Road along a closed centerline, the last point repeats the first one.
+    pub fn new(positions: &[Vec3], half_width: f32) -> Self {
         let mut track = Track::empty();
-        let mut points: Vec<Vec3> = vec![];
-        points.extend(
-            positions
-                .iter()
-                .map(|pos| Vec3::new(pos.0, pos.1 + 0.001, pos.2))
-                .collect::<Vec<Vec3>>(),
-        );
-        track.points = points;
+        track.width = half_width;
+        track.points = positions
+            .iter()
+            .map(|pos| Vec3::new(pos.x, pos.y + 0.001, pos.z))
+            .collect();
         for (i, point) in track.points.iter().enumerate() {
             let last: bool = i + 1 == track.points.len();
             let ix2: u32 = i as u32 * 2;
@@ -93,6 +93,10 @@ impl Track {
         }
         return (vertices, normals);
     }
+    /// Meters from the centerline to either road edge.
+    pub fn half_width(&self) -> f32 {
+        self.width
+    }
 }
 
 // fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
@@ -110,6 +114,9 @@ pub const _TRACK_POSITIONS: [(f32, f32, f32, f32); 7] = [
     (0., 0.0, 0., 1.0),
 ];
 
+/// Key of the built-in track, e.g. for per-track lap records.
+pub const TRACK_NAME: &str = "default";
+
 pub const TRACK_POSITIONS: [(f32, f32, f32, f32); 365] = [
     (-65.2042, 0.0, 80.13815, 1.0),
     (-115.01793, 0.0, 143.06631, 1.0),
@@ -3,7 +3,8 @@ use std::time::Instant;
 // use db_client::db::{rb, PrismaClient};
 // use crate::replay::ReplayBuffer;
 
-pub const ACTIONS: usize = 8;
+/// Must match the game's action count, replay buffers record coasting as action 8.
+pub const ACTIONS: usize = 9;
 pub const SENSOR_COUNT: usize = 31;
 pub const STATE_SIZE_BASE: usize = 3;
 pub const STATE_SIZE: usize = STATE_SIZE_BASE + SENSOR_COUNT;
@@ -19,7 +19,7 @@ pub fn camera_controller_system(
     )>,
 ) {
     let follow_option: Option<Transform> = match config.mode {
-        CameraMode::Free => None,
+        CameraMode::Free | CameraMode::Orbit | CameraMode::TopDown => None,
         CameraMode::Follow(_, from, at) => {
             if let Ok(car_tf) = pset.p1().get_single() {
                 let mut tf = car_tf.clone();